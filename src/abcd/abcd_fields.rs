@@ -4,9 +4,11 @@ use std::fs::File;
 use std::io::BufReader;
 use std::path::Path;
 
-use failure::Error;
+use failure::{Error, Fail};
 use serde::{Deserialize, Serialize};
 
+use crate::abcd::AbcdVersion;
+
 /// This struct reflect a field within the ABCD fields specification file.
 #[derive(Debug, Deserialize, Serialize)]
 #[serde(rename_all = "camelCase")]
@@ -17,6 +19,34 @@ pub struct AbcdField {
     pub gfbio_mandatory: bool,
     pub global_field: bool,
     pub unit: String,
+    #[serde(default)]
+    pub multiplicity: FieldMultiplicity,
+    #[serde(default = "AbcdField::default_separator")]
+    pub separator: String,
+    /// Source paths for ABCD versions that differ from `name`.
+    #[serde(default)]
+    pub version_paths: HashMap<AbcdVersion, String>,
+}
+
+impl AbcdField {
+    fn default_separator() -> String {
+        "; ".into()
+    }
+}
+
+/// This enum defines how repeated occurrences of a field within a dataset or unit are handled.
+#[derive(Clone, Copy, Debug, Default, Deserialize, Serialize, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum FieldMultiplicity {
+    /// Keep the first occurrence.
+    First,
+    /// Keep the last occurrence.
+    #[default]
+    Last,
+    /// Keep all occurrences as an ordered list.
+    All,
+    /// Join all occurrences into one text value using the field's separator.
+    Join,
 }
 
 type BinaryString = Vec<u8>;
@@ -24,18 +54,77 @@ type BinaryString = Vec<u8>;
 #[derive(Debug)]
 pub struct AbcdFields {
     fields: HashMap<BinaryString, AbcdField>,
+    version_paths: HashMap<AbcdVersion, HashMap<BinaryString, BinaryString>>,
 }
 
 impl AbcdFields {
     pub fn from_path(path: &Path) -> Result<Self, Error> {
-        let file = File::open(path)?;
-        let reader = BufReader::new(file);
+        Self::from_paths(&[path])
+    }
+
+    /// Load and merge multiple field specification files, e.g., the core fields and extension fields.
+    /// Fields of later files replace fields with the same name.
+    pub fn from_paths<P: AsRef<Path>>(paths: &[P]) -> Result<Self, Error> {
+        let mut field_list = Vec::new();
+        for path in paths {
+            let file = File::open(path)?;
+            let reader = BufReader::new(file);
+
+            let file_fields: Vec<AbcdField> = serde_json::from_reader(reader)?;
+            field_list.extend(file_fields);
+        }
+
+        let fields = Self::fields_to_map(field_list);
+        let version_paths = Self::version_paths_to_map(&fields)?;
 
         Ok(Self {
-            fields: Self::fields_to_map(serde_json::from_reader(reader)?),
+            fields,
+            version_paths,
         })
     }
 
+    /// This function creates a map from binary source path to binary field name for each supported ABCD version.
+    /// A field is read from its `name` unless it specifies a different path for the version.
+    /// Two fields that are read from the same source path lead to an error.
+    fn version_paths_to_map(
+        fields: &HashMap<BinaryString, AbcdField>,
+    ) -> Result<HashMap<AbcdVersion, HashMap<BinaryString, BinaryString>>, Error> {
+        let mut version_paths = HashMap::with_capacity(AbcdVersion::SUPPORTED.len());
+
+        for &version in &AbcdVersion::SUPPORTED {
+            let mut paths: HashMap<BinaryString, BinaryString> =
+                HashMap::with_capacity(fields.len());
+
+            for (name, field) in fields {
+                let path = field
+                    .version_paths
+                    .get(&version)
+                    .map_or_else(|| name.clone(), |path| path.as_bytes().into());
+
+                if let Some(other_name) = paths.insert(path.clone(), name.clone()) {
+                    let mut field_names = [
+                        String::from_utf8_lossy(&other_name).into_owned(),
+                        field.name.clone(),
+                    ];
+                    field_names.sort();
+                    let [first_field, second_field] = field_names;
+
+                    return Err(AbcdFieldsError::DuplicateSourcePath {
+                        version,
+                        path: String::from_utf8_lossy(&path).into_owned(),
+                        first_field,
+                        second_field,
+                    }
+                    .into());
+                }
+            }
+
+            version_paths.insert(version, paths);
+        }
+
+        Ok(version_paths)
+    }
+
     /// This function creates a map from binary field name to `AbcdField` from a list of `AbcdField`s.
     fn fields_to_map(fields: Vec<AbcdField>) -> HashMap<Vec<u8>, AbcdField> {
         let mut map = HashMap::with_capacity(fields.len());
@@ -49,6 +138,14 @@ impl AbcdFields {
         self.fields.get(field)
     }
 
+    /// Retrieve the field that is read from the source `path` in files of the given ABCD `version`.
+    pub fn value_of_path(&self, version: AbcdVersion, path: &[u8]) -> Option<&AbcdField> {
+        self.version_paths
+            .get(&version)
+            .and_then(|paths| paths.get(path))
+            .and_then(|name| self.fields.get(name))
+    }
+
     pub fn len(&self) -> usize {
         self.fields.len()
     }
@@ -58,6 +155,22 @@ impl AbcdFields {
     }
 }
 
+/// An error enum for invalid field specifications.
+#[derive(Debug, Fail)]
+pub enum AbcdFieldsError {
+    /// This error occurs when two fields are read from the same source path of an ABCD version.
+    #[fail(
+        display = "Fields `{}` and `{}` are both read from `{}` in ABCD {}",
+        first_field, second_field, path, version
+    )]
+    DuplicateSourcePath {
+        version: AbcdVersion,
+        path: String,
+        first_field: String,
+        second_field: String,
+    },
+}
+
 impl<'a> IntoIterator for &'a AbcdFields {
     type Item = &'a AbcdField;
     type IntoIter = Values<'a, BinaryString, AbcdField>;
@@ -121,6 +234,229 @@ mod tests {
         assert_eq!(number_of_fields, 2);
     }
 
+    #[test]
+    fn multiplicity() {
+        let path = test_utils::create_temp_file(
+            r#"[
+                {
+                    "name": "/DataSets/DataSet/Units/Unit/UnitID",
+                    "numeric": false,
+                    "vatMandatory": false,
+                    "gfbioMandatory": false,
+                    "globalField": false,
+                    "unit": ""
+                },
+                {
+                    "name": "/DataSets/DataSet/Units/Unit/MultiMediaObjects/MultiMediaObject/FileURI",
+                    "numeric": false,
+                    "vatMandatory": false,
+                    "gfbioMandatory": false,
+                    "globalField": false,
+                    "unit": "",
+                    "multiplicity": "all"
+                },
+                {
+                    "name": "/DataSets/DataSet/Units/Unit/MultiMediaObjects/MultiMediaObject/Creator",
+                    "numeric": false,
+                    "vatMandatory": false,
+                    "gfbioMandatory": false,
+                    "globalField": false,
+                    "unit": "",
+                    "multiplicity": "join",
+                    "separator": " | "
+                }
+            ]"#,
+        );
+
+        let abcd_fields = AbcdFields::from_path(&path).expect("Unable to deserialize input.");
+
+        let field1 = abcd_fields
+            .value_of(b"/DataSets/DataSet/Units/Unit/UnitID")
+            .expect("Field not found");
+        assert_eq!(field1.multiplicity, FieldMultiplicity::Last);
+        assert_eq!(field1.separator, "; ");
+
+        let field2 = abcd_fields
+            .value_of(b"/DataSets/DataSet/Units/Unit/MultiMediaObjects/MultiMediaObject/FileURI")
+            .expect("Field not found");
+        assert_eq!(field2.multiplicity, FieldMultiplicity::All);
+
+        let field3 = abcd_fields
+            .value_of(b"/DataSets/DataSet/Units/Unit/MultiMediaObjects/MultiMediaObject/Creator")
+            .expect("Field not found");
+        assert_eq!(field3.multiplicity, FieldMultiplicity::Join);
+        assert_eq!(field3.separator, " | ");
+    }
+
+    #[test]
+    fn version_paths() {
+        let path = test_utils::create_temp_file(
+            r#"[
+                {
+                    "name": "/DataSets/DataSet/Units/Unit/UnitID",
+                    "numeric": false,
+                    "vatMandatory": false,
+                    "gfbioMandatory": false,
+                    "globalField": false,
+                    "unit": ""
+                },
+                {
+                    "name": "/DataSets/DataSet/Units/Unit/Gathering/Country/Name",
+                    "numeric": false,
+                    "vatMandatory": false,
+                    "gfbioMandatory": false,
+                    "globalField": false,
+                    "unit": "",
+                    "versionPaths": {
+                        "2.1": "/DataSets/DataSet/Units/Unit/Gathering/Country/CountryName"
+                    }
+                }
+            ]"#,
+        );
+
+        let abcd_fields = AbcdFields::from_path(&path).expect("Unable to deserialize input.");
+
+        for version in &AbcdVersion::SUPPORTED {
+            let field = abcd_fields
+                .value_of_path(*version, b"/DataSets/DataSet/Units/Unit/UnitID")
+                .expect("Field not found");
+            assert_eq!(field.name, "/DataSets/DataSet/Units/Unit/UnitID");
+        }
+
+        let field = abcd_fields
+            .value_of_path(
+                AbcdVersion::Version206,
+                b"/DataSets/DataSet/Units/Unit/Gathering/Country/Name",
+            )
+            .expect("Field not found");
+        assert_eq!(
+            field.name,
+            "/DataSets/DataSet/Units/Unit/Gathering/Country/Name"
+        );
+
+        let field = abcd_fields
+            .value_of_path(
+                AbcdVersion::Version210,
+                b"/DataSets/DataSet/Units/Unit/Gathering/Country/CountryName",
+            )
+            .expect("Field not found");
+        assert_eq!(
+            field.name,
+            "/DataSets/DataSet/Units/Unit/Gathering/Country/Name"
+        );
+
+        assert!(abcd_fields
+            .value_of_path(
+                AbcdVersion::Version210,
+                b"/DataSets/DataSet/Units/Unit/Gathering/Country/Name",
+            )
+            .is_none());
+        assert!(abcd_fields
+            .value_of_path(AbcdVersion::Unknown, b"/DataSets/DataSet/Units/Unit/UnitID")
+            .is_none());
+    }
+
+    #[test]
+    fn duplicate_version_paths() {
+        let path = test_utils::create_temp_file(
+            r#"[
+                {
+                    "name": "/DataSets/DataSet/Units/Unit/Gathering/Country/Name",
+                    "numeric": false,
+                    "vatMandatory": false,
+                    "gfbioMandatory": false,
+                    "globalField": false,
+                    "unit": "",
+                    "versionPaths": {
+                        "2.1": "/DataSets/DataSet/Units/Unit/Gathering/Country/CountryName"
+                    }
+                },
+                {
+                    "name": "/DataSets/DataSet/Units/Unit/Gathering/Country/CountryName",
+                    "numeric": false,
+                    "vatMandatory": false,
+                    "gfbioMandatory": false,
+                    "globalField": false,
+                    "unit": ""
+                }
+            ]"#,
+        );
+
+        let error = AbcdFields::from_path(&path).unwrap_err();
+
+        match error.downcast_ref::<AbcdFieldsError>() {
+            Some(AbcdFieldsError::DuplicateSourcePath {
+                version,
+                path,
+                first_field,
+                second_field,
+            }) => {
+                assert_eq!(*version, AbcdVersion::Version210);
+                assert_eq!(
+                    path,
+                    "/DataSets/DataSet/Units/Unit/Gathering/Country/CountryName"
+                );
+                assert_eq!(
+                    first_field,
+                    "/DataSets/DataSet/Units/Unit/Gathering/Country/CountryName"
+                );
+                assert_eq!(
+                    second_field,
+                    "/DataSets/DataSet/Units/Unit/Gathering/Country/Name"
+                );
+            }
+            _ => panic!("Unexpected error: {}", error),
+        }
+    }
+
+    #[test]
+    fn multiple_files() {
+        let core_path = create_test_file_path();
+        let extension_path = test_utils::create_temp_file(
+            r#"[
+                {
+                    "name": "/DataSets/DataSet/Units/Unit/UnitExtension/efg:EarthScienceSpecimen/efg:UnitStratigraphicDetermination/efg:ChronostratigraphicAttributions/efg:ChronostratigraphicAttribution/efg:ChronostratigraphicName",
+                    "numeric": false,
+                    "vatMandatory": false,
+                    "gfbioMandatory": false,
+                    "globalField": false,
+                    "unit": "",
+                    "multiplicity": "all"
+                }
+            ]"#,
+        );
+
+        let abcd_fields =
+            AbcdFields::from_paths(&[&core_path, &extension_path]).expect("Unable to load files.");
+
+        assert_eq!(abcd_fields.len(), 3);
+        assert!(abcd_fields.value_of(br#"/DataSets/DataSet/Units/Unit/UnitExtension/efg:EarthScienceSpecimen/efg:UnitStratigraphicDetermination/efg:ChronostratigraphicAttributions/efg:ChronostratigraphicAttribution/efg:ChronostratigraphicName"#).is_some());
+        assert!(abcd_fields
+            .value_of(b"/DataSets/DataSet/DatasetGUID")
+            .is_some());
+    }
+
+    #[test]
+    fn unknown_version_in_version_paths() {
+        let path = test_utils::create_temp_file(
+            r#"[
+                {
+                    "name": "/DataSets/DataSet/Units/Unit/UnitID",
+                    "numeric": false,
+                    "vatMandatory": false,
+                    "gfbioMandatory": false,
+                    "globalField": false,
+                    "unit": "",
+                    "versionPaths": {
+                        "3.0": "/DataSets/DataSet/Units/Unit/ID"
+                    }
+                }
+            ]"#,
+        );
+
+        assert!(AbcdFields::from_path(&path).is_err());
+    }
+
     fn create_test_file_path() -> TempPath {
         test_utils::create_temp_file(
             r#"[
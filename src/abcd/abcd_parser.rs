@@ -1,11 +1,12 @@
-use std::collections::HashMap;
+use std::collections::hash_map::Entry;
+use std::collections::{BTreeMap, HashMap};
+use std::io::BufRead;
 
-use failure::Error;
 use failure::Fail;
 use quick_xml::events::Event;
 use quick_xml::Reader;
 
-use crate::abcd::{AbcdFields, AbcdVersion};
+use crate::abcd::{AbcdField, AbcdFields, AbcdVersion, FieldMultiplicity};
 use crate::settings::AbcdSettings;
 use crate::vat_type::VatType;
 
@@ -17,8 +18,11 @@ pub struct AbcdParser<'a> {
     abcd_fields: &'a AbcdFields,
     abcd_settings: &'a AbcdSettings,
     abcd_version: AbcdVersion,
+    namespace_prefixes: HashMap<Vec<u8>, Vec<u8>>,
     xml_tag_path: Vec<u8>,
+    xml_tag_starts: Vec<usize>,
     xml_buffer: Vec<u8>,
+    namespace_buffer: Vec<u8>,
     values: ValueMap,
 }
 
@@ -29,137 +33,180 @@ impl<'a> AbcdParser<'a> {
             abcd_settings,
             abcd_fields,
             abcd_version: AbcdVersion::Unknown,
+            namespace_prefixes: abcd_settings
+                .namespaces
+                .iter()
+                .map(|(prefix, uri)| (uri.as_bytes().into(), prefix.as_bytes().into()))
+                .collect(),
             xml_tag_path: Vec::new(),
+            xml_tag_starts: Vec::new(),
             xml_buffer: Vec::new(),
+            namespace_buffer: Vec::new(),
             values: ValueMap::new(),
         }
     }
 
-    /// Parse a binary XML file to `AbcdResult`s.
-    pub fn parse(
-        &mut self,
+    /// Parse an XML file from a reader and stream its units.
+    /// The dataset metadata is read eagerly and is available from the returned stream.
+    /// Units are only parsed on demand, so they are never kept in memory at once.
+    pub fn parse_stream<'p, R: BufRead>(
+        &'p mut self,
         dataset_id: &str,
         dataset_path: &str,
         landing_page_proposal: &str,
         provider_name: &str,
-        xml_bytes: &[u8],
-    ) -> Result<AbcdResult, Error> {
-        let mut xml_reader = Reader::from_reader(xml_bytes);
+        reader: R,
+    ) -> Result<AbcdUnitStream<'p, 'a, R>, AbcdParserError> {
+        let mut xml_reader = Reader::from_reader(reader);
         xml_reader.trim_text(true);
 
-        let mut dataset_data = None;
-        let mut units = Vec::new();
+        self.clear();
+
+        let dataset_data = loop {
+            match self.read_next_item(&mut xml_reader) {
+                Ok(Some(AbcdItem::Dataset(dataset_data))) => break dataset_data,
+                Ok(Some(AbcdItem::Unit(_))) => {} // units can only follow the metadata
+                Ok(None) => {
+                    self.clear();
+                    return Err(AbcdParserError::MissingDatasetMetadata);
+                }
+                Err(e) => {
+                    self.clear(); // clear resources like buffers, also after a failure
+                    return Err(e);
+                }
+            }
+        };
+
+        let landing_page = if let Some(VatType::Textual(value)) =
+            dataset_data.get(&self.abcd_settings.landing_page_field)
+        {
+            value.clone()
+        } else {
+            landing_page_proposal.into()
+        };
+
+        let dataset = AbcdResult::new(
+            dataset_id.into(),
+            dataset_path.into(),
+            landing_page,
+            provider_name.into(),
+            self.abcd_version,
+            dataset_data,
+        );
+
+        Ok(AbcdUnitStream {
+            parser: self,
+            xml_reader,
+            dataset,
+            finished: false,
+        })
+    }
 
+    /// Read XML events until the dataset metadata or a unit is complete.
+    /// Returns `None` at the end of the file.
+    fn read_next_item<R: BufRead>(
+        &mut self,
+        xml_reader: &mut Reader<R>,
+    ) -> Result<Option<AbcdItem>, AbcdParserError> {
         loop {
-            match xml_reader.read_event(&mut self.xml_buffer) {
-                Ok(Event::Start(ref e)) => {
-                    self.xml_tag_path.push(b'/');
-                    self.xml_tag_path.extend(Self::strip_tag(e.name()));
+            self.xml_buffer.clear();
 
-                    //                    debug!("XML START: {}", String::from_utf8_lossy(&self.xml_tag_path));
+            match xml_reader.read_namespaced_event(&mut self.xml_buffer, &mut self.namespace_buffer)
+            {
+                Ok((namespace, Event::Start(ref e))) => {
+                    if self.xml_tag_path.is_empty() {
+                        // the root element determines the ABCD version
+                        match namespace.and_then(AbcdVersion::from_namespace) {
+                            Some(version) => self.abcd_version = version,
+                            None => {
+                                let namespaces: Vec<String> = e
+                                    .attributes()
+                                    .filter_map(Result::ok)
+                                    .filter(|attribute| attribute.key.starts_with(b"xmlns"))
+                                    .map(|attribute| {
+                                        String::from_utf8_lossy(&attribute.value).into_owned()
+                                    })
+                                    .collect();
 
-                    match self.xml_tag_path.as_slice() {
-                        b"/DataSets" => {
-                            for attribute in e.attributes().filter_map(Result::ok) {
-                                match attribute.value.as_ref() {
-                                    b"http://www.tdwg.org/schemas/abcd/2.06" => {
-                                        self.abcd_version = AbcdVersion::Version206;
-                                        break;
-                                    }
-                                    b"http://www.tdwg.org/schemas/abcd/2.1" => {
-                                        self.abcd_version = AbcdVersion::Version210;
-                                        break;
-                                    }
-                                    _ => {}
-                                }
+                                return Err(AbcdParserError::UnsupportedVersion(
+                                    namespaces.join(", "),
+                                ));
                             }
-
-                            //                            dbg!(&abcd_version);
                         }
-                        b"/DataSets/DataSet/Units" => {
-                            //                            eprintln!("Dataset Metadata:");
-                            //                            dbg!(&numeric_values);
-                            //                            dbg!(&textual_values);
-                            //                            dbg!(units);
+                    }
 
-                            dataset_data = Some(self.finish_map())
-                        }
-                        _ => {} // ignore other start tags
+                    self.xml_tag_starts.push(self.xml_tag_path.len());
+                    self.xml_tag_path.push(b'/');
+                    Self::push_tag_name(
+                        &mut self.xml_tag_path,
+                        &self.namespace_prefixes,
+                        namespace,
+                        e.name(),
+                        e.local_name(),
+                    );
+
+                    //                    debug!("XML START: {}", String::from_utf8_lossy(&self.xml_tag_path));
+
+                    if self.xml_tag_path == b"/DataSets/DataSet/Units" {
+                        //                            eprintln!("Dataset Metadata:");
+                        //                            dbg!(&numeric_values);
+                        //                            dbg!(&textual_values);
+                        //                            dbg!(units);
+
+                        return Ok(Some(AbcdItem::Dataset(self.finish_map())));
                     }
                 }
-                Ok(Event::End(ref e)) => {
-                    const SEPARATOR_LENGTH: usize = 1;
-
-                    let tag: Vec<u8> = Self::strip_tag(e.name()).cloned().collect();
-                    let stripped_name_length = tag.len();
+                Ok((_, Event::End(_))) => {
+                    let is_unit = self.xml_tag_path == b"/DataSets/DataSet/Units/Unit";
 
-                    self.xml_tag_path.truncate(
-                        self.xml_tag_path.len() - stripped_name_length - SEPARATOR_LENGTH,
-                    );
+                    let tag_start = self.xml_tag_starts.pop().unwrap_or_default();
+                    self.xml_tag_path.truncate(tag_start);
 
-                    if self.xml_tag_path == b"/DataSets/DataSet/Units" && tag == b"Unit" {
+                    if is_unit {
                         //                        eprintln!("Unit Data:");
                         //                        dbg!(&numeric_values);
                         //                        dbg!(&textual_values);
 
-                        units.push(self.finish_map());
+                        return Ok(Some(AbcdItem::Unit(self.finish_map())));
                     }
                 }
-                Ok(Event::Text(ref e)) => {
-                    if let Some(abcd_field) = self.abcd_fields.value_of(&self.xml_tag_path) {
-                        if abcd_field.numeric {
-                            let string = String::from_utf8_lossy(e.escaped());
-                            if let Ok(number) = string.parse::<f64>() {
-                                self.values.insert(abcd_field.name.clone(), number.into());
-                            }
-                        } else {
-                            self.values.insert(
-                                abcd_field.name.clone(),
-                                String::from_utf8_lossy(e.escaped()).into(),
-                            );
+                Ok((_, Event::Text(ref e))) => {
+                    if let Some(abcd_field) = self
+                        .abcd_fields
+                        .value_of_path(self.abcd_version, &self.xml_tag_path)
+                    {
+                        // skip values that do not match the field's type
+                        if let Some(value) = Self::text_to_value(abcd_field, e.escaped()) {
+                            Self::insert_value(&mut self.values, abcd_field, value);
                         }
                     }
                 }
-                Ok(Event::Eof) => break, // exits the loop when reaching end of file
-                Err(e) => panic!(
-                    "Error at position {}: {:?}",
-                    xml_reader.buffer_position(),
-                    e
-                ),
+                Ok((_, Event::Eof)) => return Ok(None), // reached end of file
+                Err(quick_xml::Error::Utf8(e)) => {
+                    return Err(AbcdParserError::Encoding {
+                        position: xml_reader.buffer_position(),
+                        message: e.to_string(),
+                    });
+                }
+                Err(e) => {
+                    return Err(AbcdParserError::MalformedXml {
+                        position: xml_reader.buffer_position(),
+                        tag_path: String::from_utf8_lossy(&self.xml_tag_path).into_owned(),
+                        message: e.to_string(),
+                    });
+                }
                 _ => (), // ignore all other events
             }
-
-            self.xml_buffer.clear();
-        }
-
-        self.clear(); // clear resources like buffers
-
-        if let Some(dataset_data) = dataset_data {
-            let landing_page = if let Some(VatType::Textual(value)) =
-                dataset_data.get(&self.abcd_settings.landing_page_field)
-            {
-                value
-            } else {
-                landing_page_proposal
-            };
-
-            Ok(AbcdResult::new(
-                dataset_id.into(),
-                dataset_path.into(),
-                landing_page.into(),
-                provider_name.into(),
-                dataset_data,
-                units,
-            ))
-        } else {
-            Err(AbcdContainsNoDatasetMetadata {}.into())
         }
     }
 
-    /// Clear all buffers.
+    /// Clear all buffers and the detected version.
     fn clear(&mut self) {
+        self.abcd_version = AbcdVersion::Unknown;
         self.xml_tag_path.clear();
+        self.xml_tag_starts.clear();
         self.xml_buffer.clear();
+        self.namespace_buffer.clear();
         self.values.clear();
     }
 
@@ -170,50 +217,178 @@ impl<'a> AbcdParser<'a> {
         result
     }
 
-    /// Strip the namespace from a tag.
-    fn strip_tag(tag: &[u8]) -> impl Iterator<Item = &u8> {
-        let has_colon = tag.iter().any(|&b| b == b':');
-        tag.iter()
-            .skip_while(move |&&b| has_colon && b != b':')
-            .skip(if has_colon { 1 } else { 0 }) // the ':' itself
+    /// Convert the text of an XML element to a value of the field's type.
+    /// Invalid UTF-8 sequences are replaced, so a single bad byte does not discard the file.
+    /// Returns `None` if a numeric field contains no number.
+    fn text_to_value(abcd_field: &AbcdField, text: &[u8]) -> Option<VatType> {
+        let string = String::from_utf8_lossy(text);
+        if abcd_field.numeric && abcd_field.multiplicity != FieldMultiplicity::Join {
+            string.parse::<f64>().ok().map(Into::into)
+        } else {
+            Some(string.into())
+        }
     }
+
+    /// Insert a value into the value map with respect to the field's multiplicity.
+    fn insert_value(values: &mut ValueMap, abcd_field: &AbcdField, value: VatType) {
+        match (
+            abcd_field.multiplicity,
+            values.entry(abcd_field.name.clone()),
+        ) {
+            (FieldMultiplicity::First, Entry::Occupied(_)) => {}
+            (FieldMultiplicity::Last, Entry::Occupied(mut entry)) => {
+                entry.insert(value);
+            }
+            (FieldMultiplicity::All, Entry::Occupied(mut entry))
+            | (FieldMultiplicity::Join, Entry::Occupied(mut entry)) => {
+                entry.get_mut().append(value, &abcd_field.separator);
+            }
+            (FieldMultiplicity::All, Entry::Vacant(entry)) => {
+                entry.insert(value.into_list());
+            }
+            (_, Entry::Vacant(entry)) => {
+                entry.insert(value);
+            }
+        }
+    }
+
+    /// Append the name of an element to the tag path.
+    /// Elements of the ABCD namespace are added by their local name and elements of configured
+    /// extension namespaces by `prefix:name`.
+    /// Elements of other namespaces use the notation `{namespace}name`, so they never collide with ABCD paths.
+    fn push_tag_name(
+        xml_tag_path: &mut Vec<u8>,
+        namespace_prefixes: &HashMap<Vec<u8>, Vec<u8>>,
+        namespace: Option<&[u8]>,
+        name: &[u8],
+        local_name: &[u8],
+    ) {
+        match namespace {
+            None => xml_tag_path.extend_from_slice(name), // unbound prefixes are kept as they are
+            Some(namespace) if AbcdVersion::from_namespace(namespace).is_some() => {
+                xml_tag_path.extend_from_slice(local_name);
+            }
+            Some(namespace) => {
+                if let Some(prefix) = namespace_prefixes.get(namespace) {
+                    xml_tag_path.extend_from_slice(prefix);
+                    xml_tag_path.push(b':');
+                } else {
+                    xml_tag_path.push(b'{');
+                    xml_tag_path.extend_from_slice(namespace);
+                    xml_tag_path.push(b'}');
+                }
+                xml_tag_path.extend_from_slice(local_name);
+            }
+        }
+    }
+}
+
+/// This enum reflects the completed parts of an ABCD file.
+enum AbcdItem {
+    Dataset(ValueMap),
+    Unit(ValueMap),
+}
+
+/// This iterator streams the units of an ABCD file without keeping them in memory.
+pub struct AbcdUnitStream<'p, 'a, R: BufRead> {
+    parser: &'p mut AbcdParser<'a>,
+    xml_reader: Reader<R>,
+    dataset: AbcdResult,
+    finished: bool,
 }
 
-/// This struct reflects the result of a parsed xml file with miscellaneous additional static meta data
+impl<'p, 'a, R: BufRead> AbcdUnitStream<'p, 'a, R> {
+    /// Output the dataset metadata.
+    pub fn dataset(&self) -> &AbcdResult {
+        &self.dataset
+    }
+}
+
+impl<'p, 'a, R: BufRead> Iterator for AbcdUnitStream<'p, 'a, R> {
+    type Item = Result<ValueMap, AbcdParserError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        while !self.finished {
+            match self.parser.read_next_item(&mut self.xml_reader) {
+                Ok(Some(AbcdItem::Unit(unit))) => return Some(Ok(unit)),
+                Ok(Some(AbcdItem::Dataset(_))) => {} // only the first dataset's metadata is used
+                Ok(None) => self.finished = true,
+                Err(e) => {
+                    self.finished = true;
+                    return Some(Err(e));
+                }
+            }
+        }
+
+        None
+    }
+}
+
+impl<'p, 'a, R: BufRead> Drop for AbcdUnitStream<'p, 'a, R> {
+    fn drop(&mut self) {
+        self.parser.clear(); // clear resources like buffers
+    }
+}
+
+/// This struct reflects the dataset of a parsed xml file with miscellaneous additional static meta data.
+/// The units are streamed separately by `AbcdUnitStream`.
+#[derive(Clone, Debug)]
 pub struct AbcdResult {
     pub dataset_id: String,
     pub dataset_path: String,
     pub landing_page: String,
     pub provider_name: String,
+    pub abcd_version: AbcdVersion,
     pub dataset: ValueMap,
-    pub units: Vec<ValueMap>,
+    /// Dataset metadata of the source that is stored in separate columns.
+    pub source_metadata: BTreeMap<String, String>,
 }
 
 impl AbcdResult {
-    /// This constructor creates a new `AbcdResult` from dataset and unit data.
+    /// This constructor creates a new `AbcdResult` from dataset data.
     pub fn new(
         dataset_id: String,
         dataset_path: String,
         landing_page: String,
         provider_name: String,
+        abcd_version: AbcdVersion,
         dataset_data: ValueMap,
-        units_data: Vec<ValueMap>,
     ) -> Self {
         AbcdResult {
             dataset_id,
             dataset_path,
             landing_page,
             provider_name,
+            abcd_version,
             dataset: dataset_data,
-            units: units_data,
+            source_metadata: BTreeMap::new(),
         }
     }
 }
 
-/// This error occurs when a dataset's metadata is missing.
-#[derive(Debug, Default, Fail)]
-#[fail(display = "ABCD file contains no dataset metadata.")]
-struct AbcdContainsNoDatasetMetadata {}
+/// An error enum for different ABCD parser errors.
+#[derive(Debug, Fail)]
+pub enum AbcdParserError {
+    /// This error occurs when the XML is not well-formed.
+    #[fail(
+        display = "Malformed XML at position {} (`{}`): {}",
+        position, tag_path, message
+    )]
+    MalformedXml {
+        position: usize,
+        tag_path: String,
+        message: String,
+    },
+    /// This error occurs when the ABCD namespace of the file is not supported.
+    #[fail(display = "Unsupported ABCD version (namespaces: {})", _0)]
+    UnsupportedVersion(String),
+    /// This error occurs when a dataset's metadata is missing.
+    #[fail(display = "ABCD file contains no dataset metadata.")]
+    MissingDatasetMetadata,
+    /// This error occurs when the XML contains invalid UTF-8.
+    #[fail(display = "Encoding error at position {}: {}", position, message)]
+    Encoding { position: usize, message: String },
+}
 
 #[cfg(test)]
 mod tests {
@@ -234,8 +409,11 @@ mod tests {
         let abcd_fields = create_abcd_fields();
         let abcd_settings = AbcdSettings {
             fields_file: "".into(),
+            extension_fields_files: vec![],
+            namespaces: HashMap::new(),
             landing_page_field: "/DataSets/DataSet/Metadata/Description/Representation/URI".into(),
             storage_dir: "raw_data".into(),
+            max_archive_megabytes: 1,
         };
 
         let test_file = create_file_as_bytes();
@@ -247,15 +425,15 @@ mod tests {
         let landing_page_proposal = "landing_page proposal";
         let provider_name = "provider_id";
 
-        let result = parser
-            .parse(
-                dataset_id,
-                dataset_path,
-                landing_page_proposal,
-                provider_name,
-                &test_file,
-            )
-            .expect("Unable to parse bytes");
+        let (result, units) = parse(
+            &mut parser,
+            dataset_id,
+            dataset_path,
+            landing_page_proposal,
+            provider_name,
+            &test_file,
+        )
+        .expect("Unable to parse bytes");
 
         assert_eq!(result.dataset_id, dataset_id);
         assert_eq!(result.dataset_path, dataset_path);
@@ -275,9 +453,9 @@ mod tests {
                 .get("/DataSets/DataSet/Metadata/Description/Representation/Title")
         );
 
-        assert_eq!(result.units.len(), 1);
+        assert_eq!(units.len(), 1);
 
-        let unit = result.units.get(0).unwrap();
+        let unit = units.first().unwrap();
 
         assert_eq!(
             Some(&VatType::Textual(UNIT_ID.into())),
@@ -297,6 +475,568 @@ mod tests {
         }
     }
 
+    #[test]
+    fn stream_units_from_reader() {
+        let abcd_fields = create_abcd_fields();
+        let abcd_settings = create_abcd_settings();
+
+        let mut parser = AbcdParser::new(&abcd_settings, &abcd_fields);
+
+        let test_file = create_file_as_bytes();
+        let reader = std::io::BufReader::new(test_file.as_slice());
+
+        let mut stream = parser
+            .parse_stream("dataset_id", "dataset_path", "", "provider_id", reader)
+            .expect("Unable to parse dataset metadata");
+
+        assert_eq!(stream.dataset().dataset_id, "dataset_id");
+        assert_eq!(
+            Some(&VatType::Textual(DESCRIPTION_TITLE.into())),
+            stream
+                .dataset()
+                .dataset
+                .get("/DataSets/DataSet/Metadata/Description/Representation/Title")
+        );
+
+        let unit = stream
+            .next()
+            .expect("Missing unit")
+            .expect("Unable to parse unit");
+        assert_eq!(
+            Some(&VatType::Textual(UNIT_ID.into())),
+            unit.get("/DataSets/DataSet/Units/Unit/UnitID")
+        );
+
+        assert!(stream.next().is_none());
+    }
+
+    #[test]
+    fn repeated_elements() {
+        let abcd_fields = test_utils::create_temp_file(
+            r#"[
+                {
+                    "name": "/DataSets/DataSet/Metadata/Description/Representation/Title",
+                    "numeric": false,
+                    "vatMandatory": false,
+                    "gfbioMandatory": true,
+                    "globalField": true,
+                    "unit": ""
+                },
+                {
+                    "name": "/DataSets/DataSet/Units/Unit/Identifications/Identification/Result/TaxonIdentified/HigherTaxa/HigherTaxon/HigherTaxonName",
+                    "numeric": false,
+                    "vatMandatory": false,
+                    "gfbioMandatory": false,
+                    "globalField": false,
+                    "unit": "",
+                    "multiplicity": "all"
+                },
+                {
+                    "name": "/DataSets/DataSet/Units/Unit/Identifications/Identification/Result/TaxonIdentified/HigherTaxa/HigherTaxon/HigherTaxonRank",
+                    "numeric": false,
+                    "vatMandatory": false,
+                    "gfbioMandatory": false,
+                    "globalField": false,
+                    "unit": "",
+                    "multiplicity": "join",
+                    "separator": "/"
+                },
+                {
+                    "name": "/DataSets/DataSet/Units/Unit/Identifications/Identification/Result/TaxonIdentified/ScientificName/FullScientificNameString",
+                    "numeric": false,
+                    "vatMandatory": false,
+                    "gfbioMandatory": false,
+                    "globalField": false,
+                    "unit": "",
+                    "multiplicity": "first"
+                },
+                {
+                    "name": "/DataSets/DataSet/Units/Unit/MultiMediaObjects/MultiMediaObject/FileURI",
+                    "numeric": false,
+                    "vatMandatory": false,
+                    "gfbioMandatory": false,
+                    "globalField": false,
+                    "unit": ""
+                },
+                {
+                    "name": "/DataSets/DataSet/Units/Unit/MeasurementsOrFacts/MeasurementOrFact/MeasurementOrFactAtomised/LowerValue",
+                    "numeric": true,
+                    "vatMandatory": false,
+                    "gfbioMandatory": false,
+                    "globalField": false,
+                    "unit": "",
+                    "multiplicity": "all"
+                }
+            ]"#,
+        );
+        let abcd_fields =
+            AbcdFields::from_path(&abcd_fields).expect("Unable to create ABCD Fields Spec");
+        let abcd_settings = create_abcd_settings();
+
+        let test_file = br#"
+            <?xml version="1.0" encoding="UTF-8"?>
+            <abcd:DataSets xmlns:abcd="http://www.tdwg.org/schemas/abcd/2.06">
+            <abcd:DataSet>
+                <abcd:Metadata>
+                    <abcd:Description>
+                        <abcd:Representation language="en">
+                            <abcd:Title>TITLE</abcd:Title>
+                        </abcd:Representation>
+                    </abcd:Description>
+                </abcd:Metadata>
+                <abcd:Units>
+                    <abcd:Unit>
+                        <abcd:Identifications>
+                            <abcd:Identification>
+                                <abcd:Result>
+                                    <abcd:TaxonIdentified>
+                                        <abcd:HigherTaxa>
+                                            <abcd:HigherTaxon>
+                                                <abcd:HigherTaxonName>Plantae</abcd:HigherTaxonName>
+                                                <abcd:HigherTaxonRank>regnum</abcd:HigherTaxonRank>
+                                            </abcd:HigherTaxon>
+                                            <abcd:HigherTaxon>
+                                                <abcd:HigherTaxonName>Asteraceae</abcd:HigherTaxonName>
+                                                <abcd:HigherTaxonRank>familia</abcd:HigherTaxonRank>
+                                            </abcd:HigherTaxon>
+                                        </abcd:HigherTaxa>
+                                        <abcd:ScientificName>
+                                            <abcd:FullScientificNameString>Bellis perennis</abcd:FullScientificNameString>
+                                        </abcd:ScientificName>
+                                    </abcd:TaxonIdentified>
+                                </abcd:Result>
+                            </abcd:Identification>
+                            <abcd:Identification>
+                                <abcd:Result>
+                                    <abcd:TaxonIdentified>
+                                        <abcd:ScientificName>
+                                            <abcd:FullScientificNameString>Bellis annua</abcd:FullScientificNameString>
+                                        </abcd:ScientificName>
+                                    </abcd:TaxonIdentified>
+                                </abcd:Result>
+                            </abcd:Identification>
+                        </abcd:Identifications>
+                        <abcd:MultiMediaObjects>
+                            <abcd:MultiMediaObject>
+                                <abcd:FileURI>http://image/1</abcd:FileURI>
+                            </abcd:MultiMediaObject>
+                            <abcd:MultiMediaObject>
+                                <abcd:FileURI>http://image/2</abcd:FileURI>
+                            </abcd:MultiMediaObject>
+                        </abcd:MultiMediaObjects>
+                        <abcd:MeasurementsOrFacts>
+                            <abcd:MeasurementOrFact>
+                                <abcd:MeasurementOrFactAtomised>
+                                    <abcd:LowerValue>1.5</abcd:LowerValue>
+                                </abcd:MeasurementOrFactAtomised>
+                            </abcd:MeasurementOrFact>
+                            <abcd:MeasurementOrFact>
+                                <abcd:MeasurementOrFactAtomised>
+                                    <abcd:LowerValue>NaN?</abcd:LowerValue>
+                                </abcd:MeasurementOrFactAtomised>
+                            </abcd:MeasurementOrFact>
+                            <abcd:MeasurementOrFact>
+                                <abcd:MeasurementOrFactAtomised>
+                                    <abcd:LowerValue>2.5</abcd:LowerValue>
+                                </abcd:MeasurementOrFactAtomised>
+                            </abcd:MeasurementOrFact>
+                        </abcd:MeasurementsOrFacts>
+                    </abcd:Unit>
+                    <abcd:Unit>
+                        <abcd:MultiMediaObjects>
+                            <abcd:MultiMediaObject>
+                                <abcd:FileURI>http://image/3</abcd:FileURI>
+                            </abcd:MultiMediaObject>
+                        </abcd:MultiMediaObjects>
+                    </abcd:Unit>
+                </abcd:Units>
+            </abcd:DataSet>
+            </abcd:DataSets>
+            "#;
+
+        let mut parser = AbcdParser::new(&abcd_settings, &abcd_fields);
+
+        let (_, units) = parse(
+            &mut parser,
+            "dataset_id",
+            "dataset_path",
+            "",
+            "provider_id",
+            test_file,
+        )
+        .expect("Unable to parse bytes");
+
+        assert_eq!(units.len(), 2);
+
+        let unit = &units[0];
+
+        assert_eq!(
+            Some(&VatType::TextualList(vec!["Plantae".into(), "Asteraceae".into()])),
+            unit.get("/DataSets/DataSet/Units/Unit/Identifications/Identification/Result/TaxonIdentified/HigherTaxa/HigherTaxon/HigherTaxonName")
+        );
+        assert_eq!(
+            Some(&VatType::Textual("regnum/familia".into())),
+            unit.get("/DataSets/DataSet/Units/Unit/Identifications/Identification/Result/TaxonIdentified/HigherTaxa/HigherTaxon/HigherTaxonRank")
+        );
+        assert_eq!(
+            Some(&VatType::Textual("Bellis perennis".into())),
+            unit.get("/DataSets/DataSet/Units/Unit/Identifications/Identification/Result/TaxonIdentified/ScientificName/FullScientificNameString")
+        );
+        assert_eq!(
+            Some(&VatType::Textual("http://image/2".into())),
+            unit.get("/DataSets/DataSet/Units/Unit/MultiMediaObjects/MultiMediaObject/FileURI")
+        );
+        assert_eq!(
+            Some(&VatType::NumericList(vec![1.5, 2.5])),
+            unit.get("/DataSets/DataSet/Units/Unit/MeasurementsOrFacts/MeasurementOrFact/MeasurementOrFactAtomised/LowerValue")
+        );
+
+        let unit = &units[1];
+
+        assert_eq!(
+            Some(&VatType::Textual("http://image/3".into())),
+            unit.get("/DataSets/DataSet/Units/Unit/MultiMediaObjects/MultiMediaObject/FileURI")
+        );
+        assert_eq!(
+            None,
+            unit.get("/DataSets/DataSet/Units/Unit/Identifications/Identification/Result/TaxonIdentified/HigherTaxa/HigherTaxon/HigherTaxonName")
+        );
+    }
+
+    #[test]
+    fn malformed_xml_leads_to_error_and_parser_recovers() {
+        let abcd_fields = create_abcd_fields();
+        let abcd_settings = create_abcd_settings();
+
+        let mut parser = AbcdParser::new(&abcd_settings, &abcd_fields);
+
+        let broken_file = br#"<?xml version="1.0" encoding="UTF-8"?>
+            <abcd:DataSets xmlns:abcd="http://www.tdwg.org/schemas/abcd/2.06">
+            <abcd:DataSet>
+                <abcd:TechnicalContacts>
+                    <abcd:TechnicalContact>
+                        <abcd:Name>BROKEN</abcd:Nam>
+            "#;
+
+        match parse(&mut parser, "", "", "", "", broken_file) {
+            Err(AbcdParserError::MalformedXml {
+                position, tag_path, ..
+            }) => {
+                assert!(position > 0);
+                assert_eq!(
+                    tag_path,
+                    "/DataSets/DataSet/TechnicalContacts/TechnicalContact/Name"
+                );
+            }
+            _ => panic!("Expected a malformed XML error"),
+        }
+
+        let (result, units) = parse(&mut parser, "", "", "", "", &create_file_as_bytes())
+            .expect("Unable to parse bytes");
+
+        assert_eq!(units.len(), 1);
+        assert_eq!(
+            Some(&VatType::Textual(TECHNICAL_CONTACT_NAME.into())),
+            result
+                .dataset
+                .get("/DataSets/DataSet/TechnicalContacts/TechnicalContact/Name")
+        );
+        assert_eq!(
+            None,
+            units[0].get("/DataSets/DataSet/TechnicalContacts/TechnicalContact/Name")
+        );
+    }
+
+    #[test]
+    fn unsupported_version() {
+        let abcd_fields = create_abcd_fields();
+        let abcd_settings = create_abcd_settings();
+
+        let mut parser = AbcdParser::new(&abcd_settings, &abcd_fields);
+
+        let file = br#"<?xml version="1.0" encoding="UTF-8"?>
+            <abcd:DataSets xmlns:abcd="http://www.tdwg.org/schemas/abcd/1.2">
+            </abcd:DataSets>
+            "#;
+
+        match parse(&mut parser, "", "", "", "", file) {
+            Err(AbcdParserError::UnsupportedVersion(namespaces)) => {
+                assert_eq!(namespaces, "http://www.tdwg.org/schemas/abcd/1.2");
+            }
+            _ => panic!("Expected an unsupported version error"),
+        }
+    }
+
+    #[test]
+    fn version_specific_paths() {
+        let fields_file = test_utils::create_temp_file(
+            r#"[
+                {
+                    "name": "/DataSets/DataSet/Metadata/Description/Representation/Title",
+                    "numeric": false,
+                    "vatMandatory": false,
+                    "gfbioMandatory": true,
+                    "globalField": true,
+                    "unit": ""
+                },
+                {
+                    "name": "/DataSets/DataSet/Units/Unit/Gathering/Country/Name",
+                    "numeric": false,
+                    "vatMandatory": false,
+                    "gfbioMandatory": false,
+                    "globalField": false,
+                    "unit": "",
+                    "versionPaths": {
+                        "2.1": "/DataSets/DataSet/Units/Unit/Gathering/Country/CountryName"
+                    }
+                }
+            ]"#,
+        );
+        let abcd_fields = AbcdFields::from_path(&fields_file).unwrap();
+        let abcd_settings = create_abcd_settings();
+
+        let mut parser = AbcdParser::new(&abcd_settings, &abcd_fields);
+
+        let file_210 = br#"<?xml version="1.0" encoding="UTF-8"?>
+            <abcd:DataSets xmlns:abcd="http://www.tdwg.org/schemas/abcd/2.1">
+            <abcd:DataSet>
+                <abcd:Metadata><abcd:Description><abcd:Representation>
+                    <abcd:Title>Title</abcd:Title>
+                </abcd:Representation></abcd:Description></abcd:Metadata>
+                <abcd:Units>
+                    <abcd:Unit>
+                        <abcd:Gathering><abcd:Country>
+                            <abcd:Name>Ignored</abcd:Name>
+                            <abcd:CountryName>Germany</abcd:CountryName>
+                        </abcd:Country></abcd:Gathering>
+                    </abcd:Unit>
+                </abcd:Units>
+            </abcd:DataSet>
+            </abcd:DataSets>
+            "#;
+
+        let (dataset, units) = parse(&mut parser, "", "", "", "", file_210).unwrap();
+
+        assert_eq!(dataset.abcd_version, AbcdVersion::Version210);
+        assert_eq!(units.len(), 1);
+        assert_eq!(
+            Some(&VatType::Textual("Germany".into())),
+            units[0].get("/DataSets/DataSet/Units/Unit/Gathering/Country/Name")
+        );
+
+        let file_206 = br#"<?xml version="1.0" encoding="UTF-8"?>
+            <abcd:DataSets xmlns:abcd="http://www.tdwg.org/schemas/abcd/2.06">
+            <abcd:DataSet>
+                <abcd:Metadata><abcd:Description><abcd:Representation>
+                    <abcd:Title>Title</abcd:Title>
+                </abcd:Representation></abcd:Description></abcd:Metadata>
+                <abcd:Units>
+                    <abcd:Unit>
+                        <abcd:Gathering><abcd:Country>
+                            <abcd:Name>Germany</abcd:Name>
+                        </abcd:Country></abcd:Gathering>
+                    </abcd:Unit>
+                </abcd:Units>
+            </abcd:DataSet>
+            </abcd:DataSets>
+            "#;
+
+        let (dataset, units) = parse(&mut parser, "", "", "", "", file_206).unwrap();
+
+        assert_eq!(dataset.abcd_version, AbcdVersion::Version206);
+        assert_eq!(
+            Some(&VatType::Textual("Germany".into())),
+            units[0].get("/DataSets/DataSet/Units/Unit/Gathering/Country/Name")
+        );
+    }
+
+    #[test]
+    fn extension_namespaces() {
+        const STRATIGRAPHY: &str = "/DataSets/DataSet/Units/Unit/UnitExtension/efg:EarthScienceSpecimen/efg:UnitStratigraphicDetermination/efg:ChronostratigraphicAttributions/efg:ChronostratigraphicAttribution/efg:ChronostratigraphicName";
+
+        let fields_file = test_utils::create_temp_file(&format!(
+            r#"[
+                {{
+                    "name": "/DataSets/DataSet/Metadata/Description/Representation/Title",
+                    "numeric": false,
+                    "vatMandatory": false,
+                    "gfbioMandatory": true,
+                    "globalField": true,
+                    "unit": ""
+                }},
+                {{
+                    "name": "/DataSets/DataSet/Units/Unit/UnitID",
+                    "numeric": false,
+                    "vatMandatory": false,
+                    "gfbioMandatory": true,
+                    "globalField": false,
+                    "unit": ""
+                }},
+                {{
+                    "name": "{}",
+                    "numeric": false,
+                    "vatMandatory": false,
+                    "gfbioMandatory": false,
+                    "globalField": false,
+                    "unit": "",
+                    "multiplicity": "all"
+                }}
+            ]"#,
+            STRATIGRAPHY
+        ));
+        let abcd_fields = AbcdFields::from_path(&fields_file).unwrap();
+        let mut abcd_settings = create_abcd_settings();
+        abcd_settings
+            .namespaces
+            .insert("efg".into(), "http://www.synthesys.info/ABCDEFG/1.0".into());
+
+        let mut parser = AbcdParser::new(&abcd_settings, &abcd_fields);
+
+        // the file uses a default namespace for ABCD and different prefixes for the extensions
+        let file = br#"<?xml version="1.0" encoding="UTF-8"?>
+            <DataSets xmlns="http://www.tdwg.org/schemas/abcd/2.06"
+                      xmlns:geo="http://www.synthesys.info/ABCDEFG/1.0"
+                      xmlns:other="http://example.org/other">
+            <DataSet>
+                <Metadata><Description><Representation>
+                    <Title>Title</Title>
+                </Representation></Description></Metadata>
+                <Units>
+                    <Unit>
+                        <UnitID>ID</UnitID>
+                        <other:UnitID>Other ID</other:UnitID>
+                        <UnitExtension>
+                            <geo:EarthScienceSpecimen>
+                                <geo:UnitStratigraphicDetermination>
+                                    <geo:ChronostratigraphicAttributions>
+                                        <geo:ChronostratigraphicAttribution>
+                                            <geo:ChronostratigraphicName>Jurassic</geo:ChronostratigraphicName>
+                                        </geo:ChronostratigraphicAttribution>
+                                        <geo:ChronostratigraphicAttribution>
+                                            <geo:ChronostratigraphicName>Toarcian</geo:ChronostratigraphicName>
+                                        </geo:ChronostratigraphicAttribution>
+                                    </geo:ChronostratigraphicAttributions>
+                                </geo:UnitStratigraphicDetermination>
+                            </geo:EarthScienceSpecimen>
+                        </UnitExtension>
+                    </Unit>
+                </Units>
+            </DataSet>
+            </DataSets>
+            "#;
+
+        let (dataset, units) = parse(&mut parser, "", "", "", "", file).unwrap();
+
+        assert_eq!(dataset.abcd_version, AbcdVersion::Version206);
+        assert_eq!(
+            Some(&VatType::Textual("Title".into())),
+            dataset
+                .dataset
+                .get("/DataSets/DataSet/Metadata/Description/Representation/Title")
+        );
+
+        assert_eq!(units.len(), 1);
+        assert_eq!(
+            Some(&VatType::Textual("ID".into())),
+            units[0].get("/DataSets/DataSet/Units/Unit/UnitID")
+        );
+        assert_eq!(
+            Some(&VatType::TextualList(vec![
+                "Jurassic".into(),
+                "Toarcian".into()
+            ])),
+            units[0].get(STRATIGRAPHY)
+        );
+    }
+
+    #[test]
+    fn missing_dataset_metadata() {
+        let abcd_fields = create_abcd_fields();
+        let abcd_settings = create_abcd_settings();
+
+        let mut parser = AbcdParser::new(&abcd_settings, &abcd_fields);
+
+        let file = br#"<?xml version="1.0" encoding="UTF-8"?>
+            <abcd:DataSets xmlns:abcd="http://www.tdwg.org/schemas/abcd/2.06">
+                <abcd:DataSet></abcd:DataSet>
+            </abcd:DataSets>
+            "#;
+
+        assert!(matches!(
+            parse(&mut parser, "", "", "", "", file),
+            Err(AbcdParserError::MissingDatasetMetadata)
+        ));
+    }
+
+    #[test]
+    fn invalid_encoding_is_replaced() {
+        let abcd_fields = create_abcd_fields();
+        let abcd_settings = create_abcd_settings();
+
+        let mut parser = AbcdParser::new(&abcd_settings, &abcd_fields);
+
+        let mut file = br#"<?xml version="1.0" encoding="UTF-8"?>
+            <abcd:DataSets xmlns:abcd="http://www.tdwg.org/schemas/abcd/2.06">
+            <abcd:DataSet>
+                <abcd:TechnicalContacts>
+                    <abcd:TechnicalContact>
+                        <abcd:Name>TECHNICAL CONTACT</abcd:Name>
+                    </abcd:TechnicalContact>
+                </abcd:TechnicalContacts>
+                <abcd:Units>
+                    <abcd:Unit>
+                        <abcd:UnitID>UNIT"#
+            .to_vec();
+        file.extend_from_slice(&[0xC3, 0x28]);
+        file.extend_from_slice(
+            br#"</abcd:UnitID>
+                    </abcd:Unit>
+                </abcd:Units>
+            </abcd:DataSet>
+            </abcd:DataSets>"#,
+        );
+
+        let (_, units) = parse(&mut parser, "", "", "", "", &file).unwrap();
+
+        assert_eq!(units.len(), 1);
+        assert_eq!(
+            units[0].get("/DataSets/DataSet/Units/Unit/UnitID"),
+            Some(&VatType::Textual("UNIT\u{FFFD}(".into()))
+        );
+    }
+
+    fn parse(
+        parser: &mut AbcdParser,
+        dataset_id: &str,
+        dataset_path: &str,
+        landing_page_proposal: &str,
+        provider_name: &str,
+        xml_bytes: &[u8],
+    ) -> Result<(AbcdResult, Vec<ValueMap>), AbcdParserError> {
+        let mut stream = parser.parse_stream(
+            dataset_id,
+            dataset_path,
+            landing_page_proposal,
+            provider_name,
+            xml_bytes,
+        )?;
+
+        let units = (&mut stream).collect::<Result<Vec<ValueMap>, AbcdParserError>>()?;
+
+        Ok((stream.dataset().clone(), units))
+    }
+
+    fn create_abcd_settings() -> AbcdSettings {
+        AbcdSettings {
+            fields_file: "".into(),
+            extension_fields_files: vec![],
+            namespaces: HashMap::new(),
+            landing_page_field: "/DataSets/DataSet/Metadata/Description/Representation/URI".into(),
+            storage_dir: "raw_data".into(),
+            max_archive_megabytes: 1,
+        }
+    }
+
     fn create_file_as_bytes() -> Vec<u8> {
         format!(
             r#"
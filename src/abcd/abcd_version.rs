@@ -1,7 +1,38 @@
+use std::fmt;
+
+use serde::{Deserialize, Serialize};
+
 /// This enum defines the ABCD version.
-#[derive(Debug)]
+#[derive(Clone, Copy, Debug, Deserialize, Serialize, PartialEq, Eq, Hash)]
 pub enum AbcdVersion {
+    #[serde(rename = "unknown")]
     Unknown,
+    #[serde(rename = "2.06")]
     Version206,
+    #[serde(rename = "2.1")]
     Version210,
 }
+
+impl AbcdVersion {
+    /// All versions the parser is able to process.
+    pub const SUPPORTED: [AbcdVersion; 2] = [AbcdVersion::Version206, AbcdVersion::Version210];
+
+    /// Detect the version from an ABCD namespace URI.
+    pub fn from_namespace(namespace: &[u8]) -> Option<Self> {
+        match namespace {
+            b"http://www.tdwg.org/schemas/abcd/2.06" => Some(AbcdVersion::Version206),
+            b"http://www.tdwg.org/schemas/abcd/2.1" => Some(AbcdVersion::Version210),
+            _ => None,
+        }
+    }
+}
+
+impl fmt::Display for AbcdVersion {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            AbcdVersion::Unknown => write!(f, "unknown"),
+            AbcdVersion::Version206 => write!(f, "2.06"),
+            AbcdVersion::Version210 => write!(f, "2.1"),
+        }
+    }
+}
@@ -3,7 +3,7 @@ mod abcd_parser;
 mod abcd_version;
 mod archive_reader;
 
-pub use self::abcd_fields::{AbcdField, AbcdFields};
-pub use self::abcd_parser::{AbcdParser, AbcdResult, ValueMap};
+pub use self::abcd_fields::{AbcdField, AbcdFields, FieldMultiplicity};
+pub use self::abcd_parser::{AbcdParser, AbcdParserError, AbcdResult, ValueMap};
 pub use self::abcd_version::AbcdVersion;
 pub use self::archive_reader::ArchiveReader;
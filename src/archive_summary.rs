@@ -0,0 +1,350 @@
+use std::collections::BTreeMap;
+use std::fmt;
+use std::path::Path;
+
+use failure::{Error, Fail};
+
+use crate::abcd::{AbcdFields, AbcdParser, AbcdVersion, ArchiveReader, ValueMap};
+use crate::settings::AbcdSettings;
+
+/// This struct summarizes the ABCD files of an archive without storing them.
+#[derive(Debug)]
+pub struct ArchiveSummary {
+    pub files: Vec<FileSummary>,
+}
+
+/// This struct summarizes a single ABCD file of an archive.
+#[derive(Debug)]
+pub struct FileSummary {
+    pub name: String,
+    pub abcd_version: Option<AbcdVersion>,
+    pub dataset: ValueMap,
+    pub number_of_units: usize,
+    /// The number of units that contain each unit field of the specification.
+    pub field_coverage: BTreeMap<String, usize>,
+    /// The reason why the file could not be read completely.
+    pub error: Option<String>,
+}
+
+impl ArchiveSummary {
+    /// Parse all files of an archive and count their units and field coverage.
+    pub fn from_path(
+        path: &Path,
+        abcd_settings: &AbcdSettings,
+        abcd_fields: &AbcdFields,
+    ) -> Result<Self, Error> {
+        let mut archive_reader = ArchiveReader::from_path(path)?;
+        let mut abcd_parser = AbcdParser::new(abcd_settings, abcd_fields);
+
+        let mut files = Vec::with_capacity(archive_reader.len());
+        for index in 0..archive_reader.len() {
+            let mut file = FileSummary {
+                name: archive_reader
+                    .file_name(index)
+                    .unwrap_or_else(|_| index.to_string()),
+                abcd_version: None,
+                dataset: ValueMap::new(),
+                number_of_units: 0,
+                field_coverage: abcd_fields
+                    .into_iter()
+                    .filter(|field| !field.global_field)
+                    .map(|field| (field.name.clone(), 0))
+                    .collect(),
+                error: None,
+            };
+
+            let xml_reader = match archive_reader.file_reader(index) {
+                Ok(reader) => reader,
+                Err(e) => {
+                    file.error = Some(e.to_string());
+                    files.push(file);
+                    continue;
+                }
+            };
+
+            let abcd_units =
+                match abcd_parser.parse_stream("", &path.to_string_lossy(), "", "", xml_reader) {
+                    Ok(units) => units,
+                    Err(e) => {
+                        file.error = Some(e.to_string());
+                        files.push(file);
+                        continue;
+                    }
+                };
+
+            file.abcd_version = Some(abcd_units.dataset().abcd_version);
+            file.dataset = abcd_units.dataset().dataset.clone();
+
+            for unit in abcd_units {
+                let unit = match unit {
+                    Ok(unit) => unit,
+                    Err(e) => {
+                        file.error = Some(e.to_string());
+                        break;
+                    }
+                };
+
+                file.number_of_units += 1;
+                for field in unit.keys() {
+                    if let Some(count) = file.field_coverage.get_mut(field) {
+                        *count += 1;
+                    }
+                }
+            }
+
+            files.push(file);
+        }
+
+        Ok(Self { files })
+    }
+
+    /// Check that all files are readable and contain the fields that are mandatory for the VAT system.
+    pub fn validate(&self, abcd_fields: &AbcdFields) -> Result<(), ValidationError> {
+        if self.files.is_empty() {
+            return Err(ValidationError::NoFiles);
+        }
+
+        let invalid_files = self
+            .files
+            .iter()
+            .filter(|file| !file.problems(abcd_fields).is_empty())
+            .count();
+        if invalid_files > 0 {
+            return Err(ValidationError::InvalidFiles {
+                invalid_files,
+                files: self.files.len(),
+            });
+        }
+
+        Ok(())
+    }
+}
+
+impl FileSummary {
+    /// List the reasons why the file cannot be stored completely.
+    pub fn problems(&self, abcd_fields: &AbcdFields) -> Vec<String> {
+        let mut problems: Vec<String> = self.error.iter().cloned().collect();
+
+        for field in abcd_fields.into_iter().filter(|field| field.vat_mandatory) {
+            let missing = if field.global_field {
+                !self.dataset.contains_key(&field.name)
+            } else {
+                self.field_coverage.get(&field.name).copied().unwrap_or(0) < self.number_of_units
+            };
+
+            if missing {
+                problems.push(format!("Missing mandatory field `{}`", field.name));
+            }
+        }
+
+        problems.sort();
+        problems
+    }
+}
+
+/// A human-readable overview of the files, their dataset metadata and field coverage.
+impl fmt::Display for ArchiveSummary {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for (index, file) in self.files.iter().enumerate() {
+            if index > 0 {
+                writeln!(f)?;
+            }
+
+            writeln!(f, "File: {}", file.name)?;
+            writeln!(
+                f,
+                "ABCD version: {}",
+                file.abcd_version.unwrap_or(AbcdVersion::Unknown)
+            )?;
+            if let Some(error) = &file.error {
+                writeln!(f, "Error: {}", error)?;
+            }
+            writeln!(f, "Units: {}", file.number_of_units)?;
+
+            writeln!(f, "Dataset:")?;
+            let mut dataset: Vec<_> = file.dataset.iter().collect();
+            dataset.sort_by_key(|(field, _)| *field);
+            for (field, value) in dataset {
+                writeln!(f, "  {} = {}", field, value)?;
+            }
+
+            writeln!(f, "Field coverage:")?;
+            for (field, count) in &file.field_coverage {
+                let percentage = if file.number_of_units > 0 {
+                    *count as f64 / file.number_of_units as f64 * 100.
+                } else {
+                    0.
+                };
+                writeln!(f, "  {} = {} ({:.1} %)", field, count, percentage)?;
+            }
+        }
+
+        Ok(())
+    }
+}
+
+#[derive(Debug, Fail)]
+pub enum ValidationError {
+    /// This error occurs when an archive contains no files.
+    #[fail(display = "The archive contains no files")]
+    NoFiles,
+    /// This error occurs when files of an archive are unreadable or incomplete.
+    #[fail(display = "{} of {} files are invalid", invalid_files, files)]
+    InvalidFiles { invalid_files: usize, files: usize },
+    /// This error occurs when some of the validated archives are invalid.
+    #[fail(display = "{} of {} archives are invalid", invalid_archives, archives)]
+    InvalidArchives {
+        invalid_archives: usize,
+        archives: usize,
+    },
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+
+    use crate::test_utils;
+    use crate::vat_type::VatType;
+
+    use super::*;
+
+    #[test]
+    fn summarize_archive() {
+        let archive = test_utils::create_zip_file(&[
+            ("a.xml", &abcd_file(&["UNIT_1", "UNIT_2"], true)),
+            ("b.xml", "<no-abcd/>"),
+        ]);
+        let abcd_fields = create_abcd_fields();
+
+        let summary =
+            ArchiveSummary::from_path(&archive, &create_abcd_settings(), &abcd_fields).unwrap();
+
+        assert_eq!(summary.files.len(), 2);
+
+        let file = &summary.files[0];
+        assert_eq!(file.name, "a.xml");
+        assert_eq!(file.abcd_version, Some(AbcdVersion::Version206));
+        assert_eq!(file.number_of_units, 2);
+        assert_eq!(
+            file.dataset
+                .get("/DataSets/DataSet/Metadata/Description/Representation/Title"),
+            Some(&VatType::Textual("Title".into()))
+        );
+        assert_eq!(
+            file.field_coverage["/DataSets/DataSet/Units/Unit/UnitID"],
+            2
+        );
+        assert_eq!(
+            file.field_coverage["/DataSets/DataSet/Units/Unit/RecordBasis"],
+            0
+        );
+        assert!(file.error.is_none());
+
+        assert!(summary.files[1].error.is_some());
+
+        let overview = summary.to_string();
+        assert!(overview.contains("File: a.xml"));
+        assert!(overview.contains("/DataSets/DataSet/Units/Unit/UnitID = 2 (100.0 %)"));
+    }
+
+    #[test]
+    fn validate_archive() {
+        let abcd_fields = create_abcd_fields();
+        let summarize = |files: &[(&str, &str)]| {
+            let archive = test_utils::create_zip_file(files);
+            ArchiveSummary::from_path(&archive, &create_abcd_settings(), &abcd_fields).unwrap()
+        };
+
+        assert!(summarize(&[("a.xml", &abcd_file(&["UNIT_1"], true))])
+            .validate(&abcd_fields)
+            .is_ok());
+
+        assert!(matches!(
+            summarize(&[]).validate(&abcd_fields),
+            Err(ValidationError::NoFiles)
+        ));
+
+        let summary = summarize(&[
+            ("a.xml", &abcd_file(&["UNIT_1"], true)),
+            ("b.xml", &abcd_file(&["UNIT_1"], false)),
+        ]);
+        assert!(matches!(
+            summary.validate(&abcd_fields),
+            Err(ValidationError::InvalidFiles {
+                invalid_files: 1,
+                files: 2
+            })
+        ));
+        assert_eq!(
+            summary.files[1].problems(&abcd_fields),
+            vec!["Missing mandatory field `/DataSets/DataSet/Metadata/Description/Representation/Title`"]
+        );
+    }
+
+    fn abcd_file(unit_ids: &[&str], with_title: bool) -> String {
+        format!(
+            r#"<?xml version="1.0" encoding="UTF-8"?>
+            <abcd:DataSets xmlns:abcd="http://www.tdwg.org/schemas/abcd/2.06">
+            <abcd:DataSet>
+                <abcd:Metadata><abcd:Description><abcd:Representation>
+                    {}
+                </abcd:Representation></abcd:Description></abcd:Metadata>
+                <abcd:Units>{}</abcd:Units>
+            </abcd:DataSet>
+            </abcd:DataSets>"#,
+            if with_title {
+                "<abcd:Title>Title</abcd:Title>"
+            } else {
+                ""
+            },
+            unit_ids
+                .iter()
+                .map(|id| format!("<abcd:Unit><abcd:UnitID>{}</abcd:UnitID></abcd:Unit>", id))
+                .collect::<String>(),
+        )
+    }
+
+    fn create_abcd_settings() -> AbcdSettings {
+        AbcdSettings {
+            fields_file: "".into(),
+            extension_fields_files: vec![],
+            namespaces: HashMap::new(),
+            landing_page_field: "/DataSets/DataSet/Metadata/Description/Representation/URI".into(),
+            storage_dir: "".into(),
+            max_archive_megabytes: 1,
+        }
+    }
+
+    fn create_abcd_fields() -> AbcdFields {
+        let fields_file = test_utils::create_temp_file(
+            r#"[
+                {
+                    "name": "/DataSets/DataSet/Metadata/Description/Representation/Title",
+                    "numeric": false,
+                    "vatMandatory": true,
+                    "gfbioMandatory": true,
+                    "globalField": true,
+                    "unit": ""
+                },
+                {
+                    "name": "/DataSets/DataSet/Units/Unit/UnitID",
+                    "numeric": false,
+                    "vatMandatory": true,
+                    "gfbioMandatory": true,
+                    "globalField": false,
+                    "unit": ""
+                },
+                {
+                    "name": "/DataSets/DataSet/Units/Unit/RecordBasis",
+                    "numeric": false,
+                    "vatMandatory": false,
+                    "gfbioMandatory": false,
+                    "globalField": false,
+                    "unit": ""
+                }
+            ]"#,
+        );
+
+        AbcdFields::from_path(&fields_file).expect("Unable to create ABCD Fields Spec")
+    }
+}
@@ -0,0 +1,290 @@
+use std::fmt;
+use std::fs::File;
+use std::path::Path;
+use std::time::Duration;
+
+use failure::{Error, Fail};
+use serde::Serialize;
+
+/// This enum describes how the archive of a dataset was retrieved.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum DownloadStatus {
+    /// The archive was downloaded.
+    Fresh,
+    /// The webserver reported that the cached archive is up to date.
+    Cached,
+    /// The download failed and the cached archive was used instead.
+    Recovered,
+    /// The archive was read from a local file.
+    Local,
+    /// The archive could not be retrieved.
+    Failed,
+}
+
+/// This enum describes the outcome of moving the crawled tables into place.
+#[derive(Clone, Debug, Default, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum SchemaMigration {
+    /// The schema was not migrated (yet).
+    #[default]
+    Skipped,
+    /// The crawled tables replaced the previous tables.
+    Completed,
+    /// The crawl failed the safety checks, so the previous tables remain.
+    Aborted { reason: String },
+    /// The migration failed, so the previous tables remain.
+    Failed { error: String },
+}
+
+/// This struct contains the outcome of processing a single dataset.
+#[derive(Debug, Serialize)]
+pub struct DatasetReport {
+    pub dataset_id: String,
+    pub download_url: String,
+    pub download_status: DownloadStatus,
+    pub download_error: Option<String>,
+    /// The number of files in the archive.
+    pub number_of_files: usize,
+    /// Errors of reading the archive or parsing and inserting its files.
+    pub errors: Vec<String>,
+    /// The number of inserted units, including units that were carried over.
+    pub inserted_units: usize,
+    /// The dataset was copied from the previous crawl because its archive did not change.
+    pub carried_over: bool,
+    /// All files of the archive were inserted successfully.
+    pub complete: bool,
+    pub download_seconds: Option<f64>,
+    pub processing_seconds: f64,
+}
+
+/// This struct collects the outcome of all datasets of a crawl.
+#[derive(Debug, Default, Serialize)]
+pub struct CrawlReport {
+    pub duration_seconds: f64,
+    pub schema_migration: SchemaMigration,
+    pub datasets: Vec<DatasetReport>,
+}
+
+impl DatasetReport {
+    /// Create a report of a dataset whose archive was not retrieved yet.
+    pub fn new(dataset_id: &str, download_url: &str) -> Self {
+        Self {
+            dataset_id: dataset_id.into(),
+            download_url: download_url.into(),
+            download_status: DownloadStatus::Failed,
+            download_error: None,
+            number_of_files: 0,
+            errors: Vec::new(),
+            inserted_units: 0,
+            carried_over: false,
+            complete: false,
+            download_seconds: None,
+            processing_seconds: 0.,
+        }
+    }
+}
+
+impl CrawlReport {
+    /// The number of datasets that were not inserted completely.
+    pub fn number_of_failures(&self) -> usize {
+        self.datasets
+            .iter()
+            .filter(|dataset| !dataset.complete)
+            .count()
+    }
+
+    /// The fraction of datasets that were not inserted completely.
+    pub fn failure_rate(&self) -> f64 {
+        if self.datasets.is_empty() {
+            return 0.;
+        }
+
+        self.number_of_failures() as f64 / self.datasets.len() as f64
+    }
+
+    pub fn set_duration(&mut self, duration: Duration) {
+        self.duration_seconds = duration.as_secs_f64();
+    }
+
+    /// Write the report as JSON.
+    pub fn write_json(&self, path: &Path) -> Result<(), Error> {
+        let file = File::create(path)?;
+        Ok(serde_json::to_writer_pretty(file, self)?)
+    }
+
+    /// Fail if the failure rate exceeds `max_failure_rate`.
+    pub fn check_failure_rate(&self, max_failure_rate: f64) -> Result<(), CrawlReportError> {
+        if self.failure_rate() > max_failure_rate {
+            return Err(CrawlReportError::TooManyFailures {
+                failures: self.number_of_failures(),
+                datasets: self.datasets.len(),
+                max_failure_rate,
+            });
+        }
+
+        Ok(())
+    }
+
+    fn count_status(&self, download_status: DownloadStatus) -> usize {
+        self.datasets
+            .iter()
+            .filter(|dataset| dataset.download_status == download_status)
+            .count()
+    }
+}
+
+/// A human-readable summary of the crawl.
+impl fmt::Display for CrawlReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(
+            f,
+            "Crawled {} datasets in {:.0} seconds.",
+            self.datasets.len(),
+            self.duration_seconds,
+        )?;
+        writeln!(
+            f,
+            "Archives: {} downloaded, {} up to date in cache, {} recovered from cache, {} local, {} failed.",
+            self.count_status(DownloadStatus::Fresh),
+            self.count_status(DownloadStatus::Cached),
+            self.count_status(DownloadStatus::Recovered),
+            self.count_status(DownloadStatus::Local),
+            self.count_status(DownloadStatus::Failed),
+        )?;
+        writeln!(
+            f,
+            "Datasets: {} complete ({} carried over), {} failed ({:.1} %).",
+            self.datasets.len() - self.number_of_failures(),
+            self.datasets
+                .iter()
+                .filter(|dataset| dataset.carried_over)
+                .count(),
+            self.number_of_failures(),
+            self.failure_rate() * 100.,
+        )?;
+        writeln!(
+            f,
+            "Units: {} inserted.",
+            self.datasets
+                .iter()
+                .map(|dataset| dataset.inserted_units)
+                .sum::<usize>(),
+        )?;
+        write!(f, "Schema migration: {}.", self.schema_migration)?;
+
+        for dataset in self.datasets.iter().filter(|dataset| !dataset.complete) {
+            write!(f, "\n- {} ({})", dataset.dataset_id, dataset.download_url)?;
+            for error in dataset.download_error.iter().chain(&dataset.errors) {
+                write!(f, "\n  {}", error)?;
+            }
+        }
+
+        Ok(())
+    }
+}
+
+impl fmt::Display for SchemaMigration {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            SchemaMigration::Skipped => write!(f, "skipped"),
+            SchemaMigration::Completed => write!(f, "completed"),
+            SchemaMigration::Aborted { reason } => write!(f, "aborted ({})", reason),
+            SchemaMigration::Failed { error } => write!(f, "failed ({})", error),
+        }
+    }
+}
+
+#[derive(Debug, Fail)]
+pub enum CrawlReportError {
+    /// This error occurs when too many datasets of a crawl failed.
+    #[fail(
+        display = "{} of {} datasets failed, which exceeds the maximum failure rate of {}",
+        failures, datasets, max_failure_rate
+    )]
+    TooManyFailures {
+        failures: usize,
+        datasets: usize,
+        max_failure_rate: f64,
+    },
+    /// This error occurs when the crawled tables did not replace the previous tables.
+    #[fail(display = "Schema migration aborted: {}", reason)]
+    MigrationAborted { reason: String },
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn create_report() -> CrawlReport {
+        let mut complete = DatasetReport::new("COMPLETE", "http://example.org/complete.zip");
+        complete.download_status = DownloadStatus::Fresh;
+        complete.number_of_files = 2;
+        complete.inserted_units = 42;
+        complete.complete = true;
+
+        let mut carried_over = DatasetReport::new("CARRIED_OVER", "http://example.org/old.zip");
+        carried_over.download_status = DownloadStatus::Cached;
+        carried_over.inserted_units = 8;
+        carried_over.carried_over = true;
+        carried_over.complete = true;
+
+        let mut failed = DatasetReport::new("FAILED", "http://example.org/failed.zip");
+        failed.download_error = Some("Webserver responded with code: 404 Not Found".into());
+
+        CrawlReport {
+            duration_seconds: 1.,
+            schema_migration: SchemaMigration::Completed,
+            datasets: vec![complete, carried_over, failed],
+        }
+    }
+
+    #[test]
+    fn failure_rate() {
+        let report = create_report();
+
+        assert_eq!(report.number_of_failures(), 1);
+        assert!((report.failure_rate() - 1. / 3.).abs() < f64::EPSILON);
+
+        assert!(report.check_failure_rate(0.5).is_ok());
+        assert!(matches!(
+            report.check_failure_rate(0.25),
+            Err(CrawlReportError::TooManyFailures {
+                failures: 1,
+                datasets: 3,
+                ..
+            })
+        ));
+
+        assert_eq!(CrawlReport::default().failure_rate(), 0.);
+    }
+
+    #[test]
+    fn summary() {
+        let summary = create_report().to_string();
+
+        assert!(summary.contains("Crawled 3 datasets"));
+        assert!(summary.contains(
+            "1 downloaded, 1 up to date in cache, 0 recovered from cache, 0 local, 1 failed"
+        ));
+        assert!(summary.contains("2 complete (1 carried over), 1 failed (33.3 %)"));
+        assert!(summary.contains("Units: 50 inserted."));
+        assert!(summary.contains("Schema migration: completed."));
+        assert!(summary.contains("- FAILED (http://example.org/failed.zip)\n  Webserver"));
+        assert!(!summary.contains("- COMPLETE"));
+    }
+
+    #[test]
+    fn write_json() {
+        let path = tempfile::NamedTempFile::new().unwrap().into_temp_path();
+
+        create_report().write_json(&path).unwrap();
+
+        let json: serde_json::Value = serde_json::from_reader(File::open(&path).unwrap()).unwrap();
+
+        assert_eq!(json["datasets"].as_array().unwrap().len(), 3);
+        assert_eq!(json["datasets"][0]["download_status"], "fresh");
+        assert_eq!(json["datasets"][0]["inserted_units"], 42);
+        assert_eq!(json["datasets"][2]["complete"], false);
+    }
+}
@@ -0,0 +1,51 @@
+use std::collections::BTreeMap;
+use std::fmt;
+use std::path::PathBuf;
+
+/// This enum describes where the archive of a dataset is retrieved from.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum ArchiveLocation {
+    /// The archive is downloaded from this URL.
+    Remote(String),
+    /// The archive is read from this file.
+    Local(PathBuf),
+}
+
+/// This struct describes a dataset of a `DatasetSource`.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct DatasetDescription {
+    pub id: String,
+    pub publisher: String,
+    pub archive: ArchiveLocation,
+    /// The URL that a local archive was downloaded from.
+    pub download_url: Option<String>,
+    /// A landing page is proposed for the archive if there is none.
+    pub landing_page: Option<String>,
+    /// Values of the dataset metadata columns.
+    pub metadata: BTreeMap<String, String>,
+}
+
+impl fmt::Display for ArchiveLocation {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ArchiveLocation::Remote(url) => write!(f, "{}", url),
+            ArchiveLocation::Local(path) => write!(f, "{}", path.display()),
+        }
+    }
+}
+
+impl DatasetDescription {
+    /// The URL of the archive, which is only known for local archives if they were downloaded before.
+    pub fn archive_url(&self) -> Option<&str> {
+        match &self.archive {
+            ArchiveLocation::Remote(url) => Some(url),
+            ArchiveLocation::Local(_) => self.download_url.as_deref(),
+        }
+    }
+
+    /// The path that is stored for the dataset: the URL of its archive if known, and its file otherwise.
+    pub fn dataset_path(&self) -> String {
+        self.archive_url()
+            .map_or_else(|| self.archive.to_string(), ToString::to_string)
+    }
+}
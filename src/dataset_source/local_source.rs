@@ -0,0 +1,291 @@
+use std::collections::BTreeMap;
+use std::fs::File;
+use std::path::{Path, PathBuf};
+
+use failure::Error;
+use serde::Deserialize;
+
+use crate::dataset_source::{ArchiveLocation, DatasetDescription, DatasetSource};
+use crate::file_downloader::DownloadInfo;
+
+/// This source reads a single ZIP archive, the archives of a local directory or the files listed in a manifest.
+/// Manifests are JSON arrays (`.json`) or CSV files with the columns `id`, `publisher`, `path` and `landing_page`.
+pub struct LocalSource {
+    path: PathBuf,
+}
+
+/// An entry of a manifest file.
+/// Relative paths are resolved against the manifest's directory.
+#[derive(Debug, Deserialize)]
+struct ManifestEntry {
+    id: String,
+    #[serde(default)]
+    publisher: String,
+    path: PathBuf,
+    #[serde(default)]
+    landing_page: Option<String>,
+    /// Values of the dataset metadata columns, only supported by JSON manifests.
+    #[serde(default)]
+    metadata: BTreeMap<String, String>,
+}
+
+impl LocalSource {
+    pub fn new(path: &Path) -> Self {
+        Self { path: path.into() }
+    }
+
+    /// List the ZIP archives of a directory.
+    fn directory_datasets(&self) -> Result<Vec<DatasetDescription>, Error> {
+        let mut paths = Vec::new();
+        for entry in std::fs::read_dir(&self.path)? {
+            let path = entry?.path();
+            if path.is_file() && path.extension().is_some_and(|extension| extension == "zip") {
+                paths.push(path);
+            }
+        }
+        paths.sort();
+
+        Ok(paths.into_iter().map(Self::archive_dataset).collect())
+    }
+
+    /// Describe a ZIP archive by the download info that is stored alongside an archive of the storage directory.
+    /// Other archives are identified by their file names.
+    fn archive_dataset(path: PathBuf) -> DatasetDescription {
+        let info = DownloadInfo::load_for(&path).unwrap_or_default();
+
+        DatasetDescription {
+            id: info.dataset_id.unwrap_or_else(|| {
+                path.file_stem()
+                    .map(|stem| stem.to_string_lossy().into_owned())
+                    .unwrap_or_default()
+            }),
+            publisher: info.publisher.unwrap_or_default(),
+            archive: ArchiveLocation::Local(path),
+            download_url: info.url,
+            landing_page: info.landing_page.filter(|url| !url.is_empty()),
+            metadata: info.metadata,
+        }
+    }
+
+    fn manifest_datasets(&self) -> Result<Vec<DatasetDescription>, Error> {
+        let file = File::open(&self.path)?;
+
+        let entries: Vec<ManifestEntry> = if self
+            .path
+            .extension()
+            .is_some_and(|extension| extension == "json")
+        {
+            serde_json::from_reader(file)?
+        } else {
+            csv::Reader::from_reader(file)
+                .deserialize()
+                .collect::<Result<_, _>>()?
+        };
+
+        let base_dir = self.path.parent().unwrap_or_else(|| Path::new(""));
+
+        Ok(entries
+            .into_iter()
+            .map(|entry| DatasetDescription {
+                id: entry.id,
+                publisher: entry.publisher,
+                archive: ArchiveLocation::Local(base_dir.join(entry.path)),
+                download_url: None,
+                landing_page: entry.landing_page.filter(|url| !url.is_empty()),
+                metadata: entry.metadata,
+            })
+            .collect())
+    }
+}
+
+impl DatasetSource for LocalSource {
+    fn datasets(&self) -> Result<Vec<DatasetDescription>, Error> {
+        if self.path.is_dir() {
+            self.directory_datasets()
+        } else if self
+            .path
+            .extension()
+            .is_some_and(|extension| extension == "zip")
+        {
+            Ok(vec![Self::archive_dataset(self.path.clone())])
+        } else {
+            self.manifest_datasets()
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn list_directory() {
+        let dir = tempfile::tempdir().unwrap();
+        for file_name in ["b.zip", "a.zip", "a.json", "notes.txt"] {
+            std::fs::write(dir.path().join(file_name), "").unwrap();
+        }
+
+        let datasets = LocalSource::new(dir.path()).datasets().unwrap();
+
+        assert_eq!(
+            datasets,
+            vec![
+                DatasetDescription {
+                    id: "a".into(),
+                    publisher: "".into(),
+                    archive: ArchiveLocation::Local(dir.path().join("a.zip")),
+                    download_url: None,
+                    landing_page: None,
+                    metadata: BTreeMap::new(),
+                },
+                DatasetDescription {
+                    id: "b".into(),
+                    publisher: "".into(),
+                    archive: ArchiveLocation::Local(dir.path().join("b.zip")),
+                    download_url: None,
+                    landing_page: None,
+                    metadata: BTreeMap::new(),
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn list_directory_with_stored_download_info() {
+        let dir = tempfile::tempdir().unwrap();
+        let archive = dir.path().join("ds_1-0123456789abcdef.zip");
+        std::fs::write(&archive, "").unwrap();
+        DownloadInfo {
+            dataset_id: Some("ds/1".into()),
+            publisher: Some("PUBLISHER".into()),
+            url: Some("http://example.org/ds_1.zip".into()),
+            landing_page: Some("http://example.org/ds_1".into()),
+            metadata: [("dataset_title".to_string(), "TITLE".to_string())].into(),
+            ..Default::default()
+        }
+        .store_for(&archive)
+        .unwrap();
+
+        let datasets = LocalSource::new(dir.path()).datasets().unwrap();
+
+        assert_eq!(
+            datasets,
+            vec![DatasetDescription {
+                id: "ds/1".into(),
+                publisher: "PUBLISHER".into(),
+                archive: ArchiveLocation::Local(archive),
+                download_url: Some("http://example.org/ds_1.zip".into()),
+                landing_page: Some("http://example.org/ds_1".into()),
+                metadata: [("dataset_title".to_string(), "TITLE".to_string())].into(),
+            }]
+        );
+        assert_eq!(datasets[0].dataset_path(), "http://example.org/ds_1.zip");
+    }
+
+    #[test]
+    fn read_single_archive() {
+        let dir = tempfile::tempdir().unwrap();
+        let archive = dir.path().join("a.zip");
+        std::fs::write(&archive, "").unwrap();
+
+        let datasets = LocalSource::new(&archive).datasets().unwrap();
+
+        assert_eq!(
+            datasets,
+            vec![DatasetDescription {
+                id: "a".into(),
+                publisher: "".into(),
+                archive: ArchiveLocation::Local(archive.clone()),
+                download_url: None,
+                landing_page: None,
+                metadata: BTreeMap::new(),
+            }]
+        );
+
+        DownloadInfo {
+            dataset_id: Some("ds/1".into()),
+            ..Default::default()
+        }
+        .store_for(&archive)
+        .unwrap();
+
+        let datasets = LocalSource::new(&archive).datasets().unwrap();
+
+        assert_eq!(datasets[0].id, "ds/1");
+    }
+
+    #[test]
+    fn read_json_manifest() {
+        let dir = tempfile::tempdir().unwrap();
+        let manifest = dir.path().join("manifest.json");
+        std::fs::write(
+            &manifest,
+            r#"[
+                {
+                    "id": "DATASET_1",
+                    "publisher": "PUBLISHER",
+                    "path": "archives/1.zip",
+                    "landing_page": "http://example.org/1",
+                    "metadata": { "dataset_title": "TITLE" }
+                },
+                { "id": "DATASET_2", "path": "/data/2.zip" }
+            ]"#,
+        )
+        .unwrap();
+
+        let datasets = LocalSource::new(&manifest).datasets().unwrap();
+
+        assert_eq!(
+            datasets,
+            vec![
+                DatasetDescription {
+                    id: "DATASET_1".into(),
+                    publisher: "PUBLISHER".into(),
+                    archive: ArchiveLocation::Local(dir.path().join("archives/1.zip")),
+                    download_url: None,
+                    landing_page: Some("http://example.org/1".into()),
+                    metadata: [("dataset_title".to_string(), "TITLE".to_string())].into(),
+                },
+                DatasetDescription {
+                    id: "DATASET_2".into(),
+                    publisher: "".into(),
+                    archive: ArchiveLocation::Local("/data/2.zip".into()),
+                    download_url: None,
+                    landing_page: None,
+                    metadata: BTreeMap::new(),
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn read_csv_manifest() {
+        let dir = tempfile::tempdir().unwrap();
+        let manifest = dir.path().join("manifest.csv");
+        std::fs::write(
+            &manifest,
+            "id,publisher,path,landing_page\n\
+             DATASET_1,PUBLISHER,1.zip,http://example.org/1\n\
+             DATASET_2,PUBLISHER,2.zip,\n",
+        )
+        .unwrap();
+
+        let datasets = LocalSource::new(&manifest).datasets().unwrap();
+
+        assert_eq!(datasets.len(), 2);
+        assert_eq!(datasets[0].publisher, "PUBLISHER");
+        assert_eq!(
+            datasets[0].landing_page.as_deref(),
+            Some("http://example.org/1")
+        );
+        assert_eq!(
+            datasets[1].archive,
+            ArchiveLocation::Local(dir.path().join("2.zip"))
+        );
+        assert_eq!(datasets[1].landing_page, None);
+
+        assert!(LocalSource::new(&dir.path().join("missing.csv"))
+            .datasets()
+            .is_err());
+    }
+}
@@ -0,0 +1,15 @@
+mod dataset_description;
+mod local_source;
+mod pangaea_source;
+
+use failure::Error;
+
+pub use self::dataset_description::{ArchiveLocation, DatasetDescription};
+pub use self::local_source::LocalSource;
+pub use self::pangaea_source::PangaeaSource;
+
+/// A source of the datasets that are crawled.
+pub trait DatasetSource {
+    /// Retrieve the descriptions of all datasets.
+    fn datasets(&self) -> Result<Vec<DatasetDescription>, Error>;
+}
@@ -0,0 +1,40 @@
+use failure::Error;
+
+use crate::dataset_source::{ArchiveLocation, DatasetDescription, DatasetSource};
+use crate::http_client::HttpClient;
+use crate::pangaea::PangaeaSearchResult;
+use crate::settings::PangaeaSettings;
+
+/// This source retrieves the datasets from the Pangaea search.
+pub struct PangaeaSource<'a> {
+    pangaea_settings: &'a PangaeaSettings,
+    http_client: &'a HttpClient,
+}
+
+impl<'a> PangaeaSource<'a> {
+    pub fn new(pangaea_settings: &'a PangaeaSettings, http_client: &'a HttpClient) -> Self {
+        Self {
+            pangaea_settings,
+            http_client,
+        }
+    }
+}
+
+impl DatasetSource for PangaeaSource<'_> {
+    fn datasets(&self) -> Result<Vec<DatasetDescription>, Error> {
+        let entries =
+            PangaeaSearchResult::retrieve_all_entries(self.pangaea_settings, self.http_client)?;
+
+        Ok(entries
+            .iter()
+            .map(|entry| DatasetDescription {
+                id: entry.id().into(),
+                publisher: entry.publisher().into(),
+                archive: ArchiveLocation::Remote(entry.download_url().into()),
+                download_url: None,
+                landing_page: None,
+                metadata: entry.metadata(self.pangaea_settings),
+            })
+            .collect())
+    }
+}
@@ -0,0 +1,154 @@
+use std::io::Write;
+
+use failure::Error;
+
+use crate::abcd::{AbcdFields, AbcdResult, ValueMap};
+use crate::sink::Sink;
+use crate::vat_type::VatType;
+
+/// This writer outputs units as CSV with a column per field and a header of field names.
+/// Dataset fields are not repeated for each unit.
+pub struct CsvWriter<'s, W: Write> {
+    writer: csv::Writer<W>,
+    abcd_fields: &'s AbcdFields,
+}
+
+impl<'s, W: Write> CsvWriter<'s, W> {
+    /// Create a writer and write the header.
+    pub fn new(output: W, abcd_fields: &'s AbcdFields) -> Result<Self, Error> {
+        let mut writer = csv::WriterBuilder::new()
+            .delimiter(b',')
+            .has_headers(true)
+            .from_writer(output);
+
+        let field_names: Vec<&str> = abcd_fields
+            .into_iter()
+            .map(|field| field.name.as_str())
+            .collect();
+        writer.write_record(&field_names)?;
+
+        Ok(Self {
+            writer,
+            abcd_fields,
+        })
+    }
+
+    /// Write a row per unit.
+    /// Returns the number of written units.
+    pub fn insert_dataset<I, E>(&mut self, _dataset: &AbcdResult, units: I) -> Result<usize, Error>
+    where
+        I: IntoIterator<Item = Result<ValueMap, E>>,
+        E: Into<Error>,
+    {
+        let mut number_of_units = 0;
+
+        for unit in units {
+            let unit = unit.map_err(Into::into)?;
+
+            for field in self.abcd_fields {
+                match unit.get(&field.name) {
+                    Some(VatType::Textual(value)) => self.writer.write_field(value)?,
+                    Some(value) => self.writer.write_field(value.to_string())?,
+                    None => self.writer.write_field([])?,
+                }
+            }
+
+            // finish line
+            self.writer.write_record(None::<&[u8]>)?;
+
+            number_of_units += 1;
+        }
+
+        Ok(number_of_units)
+    }
+}
+
+impl<'s, W: Write> Sink for CsvWriter<'s, W> {
+    fn insert_dataset(
+        &mut self,
+        dataset: &AbcdResult,
+        units: &mut dyn Iterator<Item = Result<ValueMap, Error>>,
+    ) -> Result<usize, Error> {
+        CsvWriter::insert_dataset(self, dataset, units)
+    }
+
+    fn finish(&mut self) -> Result<(), Error> {
+        self.writer.flush()?;
+
+        Ok(())
+    }
+
+    /// Flush the rows written so far, since they cannot be taken back.
+    fn abort(&mut self) -> Result<(), Error> {
+        self.writer.flush()?;
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::test_utils::{self, create_dataset, UNIT_ID};
+
+    use super::*;
+
+    const ALTITUDE: &str = "/DataSets/DataSet/Units/Unit/Altitude";
+
+    #[test]
+    fn write_rows() {
+        let abcd_fields = create_abcd_fields();
+        let mut output = Vec::new();
+
+        let mut writer = CsvWriter::new(&mut output, &abcd_fields).unwrap();
+        let units: Vec<Result<ValueMap, Error>> = vec![
+            Ok([
+                (UNIT_ID.to_string(), VatType::from("UNIT, 1")),
+                (ALTITUDE.to_string(), VatType::from(100.5)),
+            ]
+            .into_iter()
+            .collect()),
+            Ok([(UNIT_ID.to_string(), VatType::from("UNIT_2"))]
+                .into_iter()
+                .collect()),
+        ];
+        assert_eq!(writer.insert_dataset(&create_dataset(), units).unwrap(), 2);
+        writer.finish().unwrap();
+        drop(writer);
+
+        let mut reader = csv::Reader::from_reader(output.as_slice());
+        let headers = reader.headers().unwrap().clone();
+        let rows: Vec<Vec<(String, String)>> = reader
+            .records()
+            .map(|record| {
+                let mut row: Vec<(String, String)> = headers
+                    .iter()
+                    .zip(record.unwrap().iter())
+                    .map(|(header, value)| (header.to_string(), value.to_string()))
+                    .collect();
+                row.sort();
+                row
+            })
+            .collect();
+
+        assert_eq!(
+            rows,
+            vec![
+                vec![
+                    (ALTITUDE.to_string(), "100.5".to_string()),
+                    (UNIT_ID.to_string(), "UNIT, 1".to_string()),
+                ],
+                vec![
+                    (ALTITUDE.to_string(), String::new()),
+                    (UNIT_ID.to_string(), "UNIT_2".to_string()),
+                ],
+            ]
+        );
+    }
+
+    fn create_abcd_fields() -> AbcdFields {
+        test_utils::create_abcd_fields(&[
+            test_utils::abcd_field(UNIT_ID, false, false),
+            test_utils::abcd_field(ALTITUDE, true, false),
+        ])
+    }
+}
@@ -0,0 +1,520 @@
+use std::fs::File;
+use std::io::{BufReader, Write};
+use std::path::Path;
+
+use failure::Error;
+use serde::Deserialize;
+use zip::write::FileOptions;
+use zip::ZipWriter;
+
+use crate::abcd::{AbcdResult, AbcdVersion, ValueMap};
+use crate::sink::Sink;
+use crate::vat_type::VatType;
+
+/// This struct maps dataset and unit fields to Darwin Core terms.
+#[derive(Debug, Deserialize)]
+pub struct DwcMapping {
+    /// The unit field that identifies an occurrence within its dataset.
+    pub id: String,
+    pub dataset: DwcDatasetMapping,
+    pub occurrence: Vec<DwcTermMapping>,
+}
+
+/// The fields that describe a dataset in the EML metadata.
+#[derive(Debug, Default, Deserialize)]
+#[serde(rename_all = "camelCase", default)]
+pub struct DwcDatasetMapping {
+    pub title: Option<String>,
+    #[serde(rename = "abstract")]
+    pub abstract_: Option<String>,
+    pub creator: Option<String>,
+    pub creator_email: Option<String>,
+    pub contact: Option<String>,
+    pub contact_email: Option<String>,
+    pub license: Option<String>,
+}
+
+/// A column of the occurrence file.
+#[derive(Debug, Deserialize)]
+pub struct DwcTermMapping {
+    /// A Darwin Core term, e.g., `scientificName`, or the IRI of any other term.
+    pub term: String,
+    pub field: String,
+}
+
+impl DwcMapping {
+    pub fn from_path(path: &Path) -> Result<Self, Error> {
+        let reader = BufReader::new(File::open(path)?);
+        let mapping: Self = serde_json::from_reader(reader)?;
+
+        if mapping
+            .occurrence
+            .iter()
+            .any(|term| term.name() == DwcArchiveWriter::OCCURRENCE_ID)
+        {
+            return Err(failure::err_msg(format!(
+                "The `{}` is derived from the dataset id and the `id` field and must not be mapped",
+                DwcArchiveWriter::OCCURRENCE_ID
+            )));
+        }
+
+        Ok(mapping)
+    }
+}
+
+impl DwcTermMapping {
+    const DWC_NAMESPACE: &'static str = "http://rs.tdwg.org/dwc/terms/";
+    const DCTERMS_NAMESPACE: &'static str = "http://purl.org/dc/terms/";
+    /// Terms of the occurrence core that Darwin Core borrows from Dublin Core.
+    const DCTERMS: [&'static str; 8] = [
+        "type",
+        "modified",
+        "language",
+        "license",
+        "rightsHolder",
+        "accessRights",
+        "bibliographicCitation",
+        "references",
+    ];
+
+    /// The IRI of the term.
+    fn iri(&self) -> String {
+        if self.term.contains("://") {
+            self.term.clone()
+        } else if Self::DCTERMS.contains(&self.term.as_str()) {
+            format!("{}{}", Self::DCTERMS_NAMESPACE, self.term)
+        } else {
+            format!("{}{}", Self::DWC_NAMESPACE, self.term)
+        }
+    }
+
+    /// The name of the term without namespace.
+    fn name(&self) -> &str {
+        self.term.rsplit(['/', '#']).next().unwrap_or(&self.term)
+    }
+}
+
+/// This writer creates a Darwin Core Archive, i.e., a ZIP file with an occurrence core (`occurrence.txt`),
+/// its descriptor (`meta.xml`) and the dataset metadata (`eml.xml`).
+/// The EML metadata describes the first dataset, while each occurrence row contains its own dataset fields.
+pub struct DwcArchiveWriter {
+    zip: ZipWriter<File>,
+    mapping: DwcMapping,
+    dataset: Option<AbcdResult>,
+}
+
+impl DwcArchiveWriter {
+    const OCCURRENCE_FILE: &'static str = "occurrence.txt";
+    const OCCURRENCE_ID: &'static str = "occurrenceID";
+
+    /// Create the archive at `path` and start the occurrence file with its header.
+    /// The first two columns are the `id` of the core and the `occurrenceID`.
+    /// Both consist of the dataset id and the unit id, which makes them globally unique and stable across runs.
+    pub fn create(path: &Path, mapping: DwcMapping) -> Result<Self, Error> {
+        let mut zip = ZipWriter::new(File::create(path)?);
+
+        zip.start_file(Self::OCCURRENCE_FILE, FileOptions::default())?;
+
+        let mut writer = Self::occurrence_writer(&mut zip);
+        writer.write_field("id")?;
+        writer.write_field(Self::OCCURRENCE_ID)?;
+        for term in &mapping.occurrence {
+            writer.write_field(term.name())?;
+        }
+        writer.write_record(None::<&[u8]>)?;
+        writer.flush()?;
+        drop(writer);
+
+        Ok(Self {
+            zip,
+            mapping,
+            dataset: None,
+        })
+    }
+
+    /// A tab-separated writer that quotes fields only if necessary, as declared in `meta.xml`.
+    fn occurrence_writer<W: Write>(output: W) -> csv::Writer<W> {
+        csv::WriterBuilder::new()
+            .delimiter(b'\t')
+            .has_headers(false)
+            .from_writer(output)
+    }
+
+    /// Write an occurrence row per unit, looking up fields in the unit and then its dataset.
+    /// Fails for units without an id.
+    /// Returns the number of written units.
+    pub fn insert_dataset<I, E>(&mut self, dataset: &AbcdResult, units: I) -> Result<usize, Error>
+    where
+        I: IntoIterator<Item = Result<ValueMap, E>>,
+        E: Into<Error>,
+    {
+        if self.dataset.is_none() {
+            self.dataset = Some(dataset.clone());
+        }
+
+        let mut writer = Self::occurrence_writer(&mut self.zip);
+
+        let mut number_of_units = 0;
+        for unit in units {
+            let unit = unit.map_err(Into::into)?;
+
+            let unit_id = match unit.get(&self.mapping.id) {
+                Some(VatType::Textual(value)) if !value.is_empty() => value.clone(),
+                Some(VatType::Numeric(value)) => value.to_string(),
+                _ => {
+                    return Err(failure::err_msg(format!(
+                        "Unit without `{}` in dataset `{}`",
+                        self.mapping.id, dataset.dataset_id
+                    )))
+                }
+            };
+            let occurrence_id = format!("{}:{}", dataset.dataset_id, unit_id);
+            writer.write_field(&occurrence_id)?;
+            writer.write_field(&occurrence_id)?;
+
+            for term in &self.mapping.occurrence {
+                match unit
+                    .get(&term.field)
+                    .or_else(|| dataset.dataset.get(&term.field))
+                {
+                    Some(VatType::Textual(value)) => writer.write_field(value)?,
+                    Some(value) => writer.write_field(value.to_string())?,
+                    None => writer.write_field([])?,
+                }
+            }
+
+            writer.write_record(None::<&[u8]>)?;
+            number_of_units += 1;
+        }
+
+        writer.flush()?;
+
+        Ok(number_of_units)
+    }
+
+    fn meta_xml(&self) -> String {
+        let fields: String = self
+            .mapping
+            .occurrence
+            .iter()
+            .enumerate()
+            .map(|(index, term)| {
+                format!(
+                    "\n    <field index=\"{}\" term=\"{}\"/>",
+                    index + 2,
+                    escape(&term.iri())
+                )
+            })
+            .collect();
+
+        format!(
+            r#"<?xml version="1.0" encoding="UTF-8"?>
+<archive xmlns="http://rs.tdwg.org/dwc/text/" metadata="eml.xml">
+  <core encoding="UTF-8" fieldsTerminatedBy="\t" linesTerminatedBy="\n" fieldsEnclosedBy="&quot;" ignoreHeaderLines="1" rowType="http://rs.tdwg.org/dwc/terms/Occurrence">
+    <files>
+      <location>{file}</location>
+    </files>
+    <id index="0"/>
+    <field index="1" term="{dwc_namespace}{occurrence_id}"/>{fields}
+  </core>
+</archive>
+"#,
+            file = Self::OCCURRENCE_FILE,
+            dwc_namespace = DwcTermMapping::DWC_NAMESPACE,
+            occurrence_id = Self::OCCURRENCE_ID,
+            fields = fields,
+        )
+    }
+
+    /// Describe the first dataset, where the title defaults to the dataset id and the creator to the provider.
+    fn eml_xml(&self) -> String {
+        let empty_dataset;
+        let dataset = match &self.dataset {
+            Some(dataset) => dataset,
+            None => {
+                empty_dataset = AbcdResult::new(
+                    String::new(),
+                    String::new(),
+                    String::new(),
+                    String::new(),
+                    AbcdVersion::Unknown,
+                    ValueMap::new(),
+                );
+                &empty_dataset
+            }
+        };
+
+        let mapping = &self.mapping.dataset;
+        let value = |field: &Option<String>| -> Option<String> {
+            field
+                .as_ref()
+                .and_then(|field| dataset.dataset.get(field))
+                .map(|value| escape(&value.to_string()))
+        };
+        let party = |name: Option<String>, email: Option<String>| -> String {
+            let mut party = match name {
+                Some(name) => format!(
+                    "<individualName><surName>{}</surName></individualName>",
+                    name
+                ),
+                None => format!(
+                    "<organizationName>{}</organizationName>",
+                    escape(&dataset.provider_name)
+                ),
+            };
+            if let Some(email) = email {
+                party.push_str(&format!(
+                    "<electronicMailAddress>{}</electronicMailAddress>",
+                    email
+                ));
+            }
+            party
+        };
+
+        let mut elements = vec![format!(
+            "<title>{}</title>",
+            value(&mapping.title).unwrap_or_else(|| escape(&dataset.dataset_id))
+        )];
+        elements.push(format!(
+            "<creator>{}</creator>",
+            party(value(&mapping.creator), value(&mapping.creator_email))
+        ));
+        if let Some(abstract_) = value(&mapping.abstract_) {
+            elements.push(format!("<abstract><para>{}</para></abstract>", abstract_));
+        }
+        if let Some(license) = value(&mapping.license) {
+            elements.push(format!(
+                "<intellectualRights><para>{}</para></intellectualRights>",
+                license
+            ));
+        }
+        if !dataset.landing_page.is_empty() {
+            elements.push(format!(
+                "<distribution><online><url function=\"information\">{}</url></online></distribution>",
+                escape(&dataset.landing_page)
+            ));
+        }
+        elements.push(format!(
+            "<contact>{}</contact>",
+            party(value(&mapping.contact), value(&mapping.contact_email))
+        ));
+
+        format!(
+            r#"<?xml version="1.0" encoding="UTF-8"?>
+<eml:eml xmlns:eml="eml://ecoinformatics.org/eml-2.1.1" xmlns:xsi="http://www.w3.org/2001/XMLSchema-instance" xsi:schemaLocation="eml://ecoinformatics.org/eml-2.1.1 http://rs.gbif.org/schema/eml-gbif-profile/1.1/eml.xsd" packageId="{package_id}" system="http://gbif.org" scope="system" xml:lang="en">
+  <dataset>
+    {elements}
+  </dataset>
+</eml:eml>
+"#,
+            package_id = escape(&dataset.dataset_id),
+            elements = elements.join("\n    "),
+        )
+    }
+}
+
+impl Sink for DwcArchiveWriter {
+    fn insert_dataset(
+        &mut self,
+        dataset: &AbcdResult,
+        units: &mut dyn Iterator<Item = Result<ValueMap, Error>>,
+    ) -> Result<usize, Error> {
+        DwcArchiveWriter::insert_dataset(self, dataset, units)
+    }
+
+    /// Write the descriptor and the dataset metadata and close the archive.
+    fn finish(&mut self) -> Result<(), Error> {
+        self.zip.start_file("meta.xml", FileOptions::default())?;
+        let meta_xml = self.meta_xml();
+        self.zip.write_all(meta_xml.as_bytes())?;
+
+        self.zip.start_file("eml.xml", FileOptions::default())?;
+        let eml_xml = self.eml_xml();
+        self.zip.write_all(eml_xml.as_bytes())?;
+
+        self.zip.finish()?;
+
+        Ok(())
+    }
+
+    /// Close the archive, so the occurrences written so far remain a readable Darwin Core Archive.
+    fn abort(&mut self) -> Result<(), Error> {
+        Sink::finish(self)
+    }
+}
+
+/// Escape a text for XML content and attributes.
+fn escape(value: &str) -> String {
+    String::from_utf8_lossy(&quick_xml::escape::escape(value.as_bytes())).into_owned()
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Read;
+
+    use zip::ZipArchive;
+
+    use crate::abcd::AbcdFields;
+    use crate::test_utils::{self, TITLE, UNIT_ID};
+
+    use super::*;
+
+    const SCIENTIFIC_NAME: &str = "/DataSets/DataSet/Units/Unit/Identifications/Identification/Result/TaxonIdentified/ScientificName/FullScientificNameString";
+    const LATITUDE: &str = "/DataSets/DataSet/Units/Unit/Gathering/SiteCoordinateSets/SiteCoordinates/CoordinatesLatLong/LatitudeDecimal";
+
+    #[test]
+    fn write_archive() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("dwca.zip");
+
+        let mut writer = DwcArchiveWriter::create(&path, create_mapping()).unwrap();
+        let units: Vec<Result<ValueMap, Error>> = vec![
+            Ok([
+                (UNIT_ID.to_string(), VatType::from("UNIT_1")),
+                (
+                    SCIENTIFIC_NAME.to_string(),
+                    VatType::from("Abies alba\tMill."),
+                ),
+                (LATITUDE.to_string(), VatType::from(50.25)),
+            ]
+            .into_iter()
+            .collect()),
+            Ok([(UNIT_ID.to_string(), VatType::from("UNIT_2"))]
+                .into_iter()
+                .collect()),
+        ];
+        assert_eq!(writer.insert_dataset(&create_dataset(), units).unwrap(), 2);
+        writer.finish().unwrap();
+
+        let mut archive = ZipArchive::new(File::open(&path).unwrap()).unwrap();
+        let mut read_file = |name: &str| {
+            let mut content = String::new();
+            archive
+                .by_name(name)
+                .unwrap()
+                .read_to_string(&mut content)
+                .unwrap();
+            content
+        };
+
+        assert_eq!(
+            read_file("occurrence.txt"),
+            "id\toccurrenceID\tcatalogNumber\tscientificName\tdecimalLatitude\tdatasetName\tlicense\n\
+             DATASET_ID:UNIT_1\tDATASET_ID:UNIT_1\tUNIT_1\t\"Abies alba\tMill.\"\t50.25\tTITLE & MORE\t\n\
+             DATASET_ID:UNIT_2\tDATASET_ID:UNIT_2\tUNIT_2\t\t\tTITLE & MORE\t\n"
+        );
+
+        let meta = read_file("meta.xml");
+        assert!(meta.contains(r#"<id index="0"/>"#));
+        assert!(
+            meta.contains(r#"<field index="1" term="http://rs.tdwg.org/dwc/terms/occurrenceID"/>"#)
+        );
+        assert!(meta
+            .contains(r#"<field index="3" term="http://rs.tdwg.org/dwc/terms/scientificName"/>"#));
+        assert!(meta.contains(r#"<field index="6" term="http://purl.org/dc/terms/license"/>"#));
+
+        let eml = read_file("eml.xml");
+        assert!(eml.contains("<title>TITLE &amp; MORE</title>"));
+        assert!(eml.contains("<creator><organizationName>PROVIDER</organizationName></creator>"));
+        assert!(eml.contains(
+            "<contact><individualName><surName>CONTACT</surName></individualName></contact>"
+        ));
+        assert!(eml.contains(r#"<url function="information">http://example.org</url>"#));
+    }
+
+    #[test]
+    fn close_archive_on_abort() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("dwca.zip");
+
+        let mut writer = DwcArchiveWriter::create(&path, create_mapping()).unwrap();
+        Sink::abort(&mut writer).unwrap();
+
+        let archive = ZipArchive::new(File::open(&path).unwrap()).unwrap();
+        let mut file_names: Vec<&str> = archive.file_names().collect();
+        file_names.sort_unstable();
+
+        assert_eq!(file_names, vec!["eml.xml", "meta.xml", "occurrence.txt"]);
+    }
+
+    #[test]
+    fn reject_unit_without_id() {
+        let dir = tempfile::tempdir().unwrap();
+
+        let mut writer =
+            DwcArchiveWriter::create(&dir.path().join("dwca.zip"), create_mapping()).unwrap();
+        let units: Vec<Result<ValueMap, Error>> = vec![Ok([(
+            SCIENTIFIC_NAME.to_string(),
+            VatType::from("Abies alba"),
+        )]
+        .into_iter()
+        .collect())];
+
+        assert!(writer.insert_dataset(&create_dataset(), units).is_err());
+    }
+
+    #[test]
+    fn reject_mapped_occurrence_id() {
+        let mapping_file = test_utils::create_temp_file(
+            &serde_json::json!({
+                "id": UNIT_ID,
+                "dataset": {},
+                "occurrence": [{ "term": "occurrenceID", "field": UNIT_ID }],
+            })
+            .to_string(),
+        );
+
+        assert!(DwcMapping::from_path(&mapping_file).is_err());
+    }
+
+    #[test]
+    fn default_mapping_uses_known_fields() {
+        let mapping = DwcMapping::from_path(Path::new("dwc-mapping.json")).unwrap();
+        let abcd_fields = AbcdFields::from_path(Path::new("abcd-fields.json")).unwrap();
+
+        for field in mapping
+            .occurrence
+            .iter()
+            .map(|term| &term.field)
+            .chain(Some(&mapping.id))
+        {
+            assert!(
+                abcd_fields.value_of(field.as_bytes()).is_some(),
+                "{}",
+                field
+            );
+        }
+
+        assert_eq!(mapping.dataset.title.as_deref(), Some(TITLE));
+    }
+
+    fn create_dataset() -> AbcdResult {
+        let mut dataset = test_utils::create_dataset();
+        dataset.landing_page = "http://example.org".into();
+        dataset.dataset = [
+            (TITLE.to_string(), VatType::from("TITLE & MORE")),
+            ("CONTACT".to_string(), VatType::from("CONTACT")),
+        ]
+        .into_iter()
+        .collect();
+        dataset
+    }
+
+    fn create_mapping() -> DwcMapping {
+        serde_json::from_value(serde_json::json!({
+            "id": UNIT_ID,
+            "dataset": {
+                "title": TITLE,
+                "contact": "CONTACT",
+            },
+            "occurrence": [
+                { "term": "catalogNumber", "field": UNIT_ID },
+                { "term": "scientificName", "field": SCIENTIFIC_NAME },
+                { "term": "decimalLatitude", "field": LATITUDE },
+                { "term": "datasetName", "field": TITLE },
+                { "term": "license", "field": "LICENSE" },
+            ],
+        }))
+        .unwrap()
+    }
+}
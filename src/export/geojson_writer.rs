@@ -0,0 +1,286 @@
+use std::io::Write;
+
+use failure::Error;
+use serde_json::{json, Map, Value};
+
+use crate::abcd::{AbcdField, AbcdFields, AbcdResult, ValueMap};
+use crate::settings::ExportSettings;
+use crate::sink::Sink;
+use crate::vat_type::VatType;
+
+/// This writer outputs units as GeoJSON features with a point geometry.
+/// The features form a single `FeatureCollection` or are written as newline-delimited GeoJSON, one feature per line.
+pub struct GeoJsonWriter<'s, W: Write> {
+    output: W,
+    export_settings: &'s ExportSettings,
+    fields: Vec<&'s AbcdField>,
+    newline_delimited: bool,
+    number_of_features: usize,
+}
+
+impl<'s, W: Write> GeoJsonWriter<'s, W> {
+    /// Create a writer and start the `FeatureCollection` unless it is `newline_delimited`.
+    pub fn new(
+        mut output: W,
+        export_settings: &'s ExportSettings,
+        abcd_fields: &'s AbcdFields,
+        newline_delimited: bool,
+    ) -> Result<Self, Error> {
+        let mut fields: Vec<&AbcdField> = abcd_fields
+            .into_iter()
+            .filter(|field| {
+                field.name != export_settings.longitude_field
+                    && field.name != export_settings.latitude_field
+            })
+            .collect();
+        fields.sort_by(|a, b| a.name.cmp(&b.name));
+
+        if !newline_delimited {
+            write!(output, r#"{{"type":"FeatureCollection","features":["#)?;
+        }
+
+        Ok(Self {
+            output,
+            export_settings,
+            fields,
+            newline_delimited,
+            number_of_features: 0,
+        })
+    }
+
+    /// Write a feature per unit.
+    /// Its properties are the fields of the unit and its dataset, where unit values take precedence.
+    /// Units without numeric longitude and latitude fields have no geometry.
+    /// Returns the number of written units.
+    pub fn insert_dataset<I, E>(&mut self, dataset: &AbcdResult, units: I) -> Result<usize, Error>
+    where
+        I: IntoIterator<Item = Result<ValueMap, E>>,
+        E: Into<Error>,
+    {
+        let mut number_of_units = 0;
+
+        for unit in units {
+            let unit = unit.map_err(Into::into)?;
+
+            let geometry = match (
+                unit.get(&self.export_settings.longitude_field),
+                unit.get(&self.export_settings.latitude_field),
+            ) {
+                (Some(VatType::Numeric(x)), Some(VatType::Numeric(y))) => json!({
+                    "type": "Point",
+                    "coordinates": [x, y],
+                }),
+                _ => Value::Null,
+            };
+
+            let properties: Map<String, Value> = self
+                .fields
+                .iter()
+                .filter_map(|field| {
+                    let value = unit
+                        .get(&field.name)
+                        .or_else(|| dataset.dataset.get(&field.name))?;
+                    Some((field.name.clone(), json_value(value)))
+                })
+                .collect();
+
+            let feature = json!({
+                "type": "Feature",
+                "geometry": geometry,
+                "properties": properties,
+            });
+
+            if self.newline_delimited {
+                serde_json::to_writer(&mut self.output, &feature)?;
+                writeln!(self.output)?;
+            } else {
+                if self.number_of_features > 0 {
+                    write!(self.output, ",")?;
+                }
+                serde_json::to_writer(&mut self.output, &feature)?;
+            }
+
+            self.number_of_features += 1;
+            number_of_units += 1;
+        }
+
+        Ok(number_of_units)
+    }
+}
+
+impl<'s, W: Write> Sink for GeoJsonWriter<'s, W> {
+    fn insert_dataset(
+        &mut self,
+        dataset: &AbcdResult,
+        units: &mut dyn Iterator<Item = Result<ValueMap, Error>>,
+    ) -> Result<usize, Error> {
+        GeoJsonWriter::insert_dataset(self, dataset, units)
+    }
+
+    /// Close the `FeatureCollection` and flush the output.
+    fn finish(&mut self) -> Result<(), Error> {
+        if !self.newline_delimited {
+            writeln!(self.output, "]}}")?;
+        }
+
+        self.output.flush()?;
+
+        Ok(())
+    }
+
+    /// Close the `FeatureCollection`, so the features written so far remain valid GeoJSON.
+    fn abort(&mut self) -> Result<(), Error> {
+        Sink::finish(self)
+    }
+}
+
+/// Convert a value to JSON, keeping numbers and lists.
+fn json_value(value: &VatType) -> Value {
+    match value {
+        VatType::Textual(value) => json!(value),
+        VatType::Numeric(value) => json!(value),
+        VatType::TextualList(values) => json!(values),
+        VatType::NumericList(values) => json!(values),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::test_utils::{
+        self, create_dataset, create_export_settings, LATITUDE, LONGITUDE, TITLE, UNIT_ID,
+    };
+
+    use super::*;
+
+    const ALTITUDE: &str = "/DataSets/DataSet/Units/Unit/Altitude";
+
+    #[test]
+    fn write_feature_collection() {
+        let abcd_fields = create_abcd_fields();
+        let export_settings = create_export_settings();
+        let mut output = Vec::new();
+
+        let mut writer =
+            GeoJsonWriter::new(&mut output, &export_settings, &abcd_fields, false).unwrap();
+        assert_eq!(
+            writer
+                .insert_dataset(&create_dataset(), create_units())
+                .unwrap(),
+            2
+        );
+        writer.finish().unwrap();
+
+        let feature_collection: Value = serde_json::from_slice(&output).unwrap();
+
+        assert_eq!(
+            feature_collection,
+            json!({
+                "type": "FeatureCollection",
+                "features": [
+                    {
+                        "type": "Feature",
+                        "geometry": { "type": "Point", "coordinates": [10.5, 50.25] },
+                        "properties": {
+                            UNIT_ID: "UNIT_1",
+                            ALTITUDE: [100.0, 120.0],
+                            TITLE: "TITLE",
+                        },
+                    },
+                    {
+                        "type": "Feature",
+                        "geometry": null,
+                        "properties": {
+                            UNIT_ID: "UNIT_2",
+                            TITLE: "TITLE",
+                        },
+                    },
+                ],
+            })
+        );
+    }
+
+    #[test]
+    fn write_newline_delimited_features() {
+        let abcd_fields = create_abcd_fields();
+        let export_settings = create_export_settings();
+        let mut output = Vec::new();
+
+        let mut writer =
+            GeoJsonWriter::new(&mut output, &export_settings, &abcd_fields, true).unwrap();
+        writer
+            .insert_dataset(&create_dataset(), create_units())
+            .unwrap();
+        writer.finish().unwrap();
+
+        let lines: Vec<Value> = String::from_utf8(output)
+            .unwrap()
+            .lines()
+            .map(|line| serde_json::from_str(line).unwrap())
+            .collect();
+
+        assert_eq!(lines.len(), 2);
+        assert_eq!(lines[0]["type"], "Feature");
+        assert_eq!(lines[1]["properties"][UNIT_ID], "UNIT_2");
+    }
+
+    #[test]
+    fn write_empty_feature_collection() {
+        let abcd_fields = create_abcd_fields();
+        let export_settings = create_export_settings();
+        let mut output = Vec::new();
+
+        GeoJsonWriter::new(&mut output, &export_settings, &abcd_fields, false)
+            .unwrap()
+            .finish()
+            .unwrap();
+
+        assert_eq!(
+            serde_json::from_slice::<Value>(&output).unwrap(),
+            json!({ "type": "FeatureCollection", "features": [] })
+        );
+    }
+
+    #[test]
+    fn close_feature_collection_on_abort() {
+        let abcd_fields = create_abcd_fields();
+        let export_settings = create_export_settings();
+        let mut output = Vec::new();
+
+        let mut writer =
+            GeoJsonWriter::new(&mut output, &export_settings, &abcd_fields, false).unwrap();
+        writer
+            .insert_dataset(&create_dataset(), create_units())
+            .unwrap();
+        Sink::abort(&mut writer).unwrap();
+
+        let feature_collection: Value = serde_json::from_slice(&output).unwrap();
+
+        assert_eq!(feature_collection["features"].as_array().unwrap().len(), 2);
+    }
+
+    fn create_units() -> Vec<Result<ValueMap, Error>> {
+        vec![
+            Ok([
+                (UNIT_ID.to_string(), VatType::from("UNIT_1")),
+                (LONGITUDE.to_string(), VatType::from(10.5)),
+                (LATITUDE.to_string(), VatType::from(50.25)),
+                (ALTITUDE.to_string(), VatType::NumericList(vec![100., 120.])),
+            ]
+            .into_iter()
+            .collect()),
+            Ok([(UNIT_ID.to_string(), VatType::from("UNIT_2"))]
+                .into_iter()
+                .collect()),
+        ]
+    }
+
+    fn create_abcd_fields() -> AbcdFields {
+        test_utils::create_abcd_fields(&[
+            test_utils::abcd_field(TITLE, false, true),
+            test_utils::abcd_field(UNIT_ID, false, false),
+            test_utils::abcd_field(ALTITUDE, true, false),
+            test_utils::abcd_field(LONGITUDE, true, false),
+            test_utils::abcd_field(LATITUDE, true, false),
+        ])
+    }
+}
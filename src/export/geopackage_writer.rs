@@ -0,0 +1,604 @@
+use std::path::Path;
+
+use failure::Error;
+use rusqlite::types::Value as SqlValue;
+use rusqlite::{params, params_from_iter, Connection};
+
+use crate::abcd::{AbcdField, AbcdFields, AbcdResult, ValueMap};
+use crate::settings::ExportSettings;
+use crate::sink::Sink;
+use crate::vat_type::VatType;
+
+/// This writer stores datasets and their units in a GeoPackage, i.e., an SQLite database.
+/// Units are features with a point geometry, while datasets are stored in an attribute table.
+pub struct GeoPackageWriter<'s> {
+    connection: Connection,
+    export_settings: &'s ExportSettings,
+    dataset_fields: Vec<&'s AbcdField>,
+    unit_fields: Vec<&'s AbcdField>,
+    bounds: Option<BoundingBox>,
+}
+
+/// The extent of the unit geometries.
+#[derive(Clone, Copy, Debug, PartialEq)]
+struct BoundingBox {
+    min_x: f64,
+    min_y: f64,
+    max_x: f64,
+    max_y: f64,
+}
+
+impl<'s> GeoPackageWriter<'s> {
+    /// `GPKG` in ASCII
+    const APPLICATION_ID: i32 = 0x4750_4B47;
+    /// GeoPackage 1.3.0
+    const USER_VERSION: i32 = 10300;
+    /// WGS 84
+    const SRS_ID: i32 = 4326;
+    const GEOMETRY_COLUMN: &'static str = "geom";
+
+    /// Create a GeoPackage at `path`, replacing an existing file.
+    /// All inserts are part of a single transaction that is committed by `finish`.
+    pub fn create(
+        path: &Path,
+        export_settings: &'s ExportSettings,
+        abcd_fields: &'s AbcdFields,
+    ) -> Result<Self, Error> {
+        if path.exists() {
+            std::fs::remove_file(path)?;
+        }
+
+        let mut dataset_fields: Vec<&AbcdField> = abcd_fields
+            .into_iter()
+            .filter(|field| field.global_field)
+            .collect();
+        dataset_fields.sort_by(|a, b| a.name.cmp(&b.name));
+
+        let mut unit_fields: Vec<&AbcdField> = abcd_fields
+            .into_iter()
+            .filter(|field| !field.global_field)
+            .collect();
+        unit_fields.sort_by(|a, b| a.name.cmp(&b.name));
+
+        let writer = Self {
+            connection: Connection::open(path)?,
+            export_settings,
+            dataset_fields,
+            unit_fields,
+            bounds: None,
+        };
+
+        writer.create_metadata_tables()?;
+        writer.create_data_tables()?;
+
+        writer.connection.execute_batch("BEGIN;")?;
+
+        Ok(writer)
+    }
+
+    /// Create the tables and reference systems that every GeoPackage requires.
+    fn create_metadata_tables(&self) -> Result<(), Error> {
+        self.connection
+            .pragma_update(None, "application_id", Self::APPLICATION_ID)?;
+        self.connection
+            .pragma_update(None, "user_version", Self::USER_VERSION)?;
+
+        self.connection.execute_batch(
+            r#"
+            CREATE TABLE gpkg_spatial_ref_sys (
+                srs_name TEXT NOT NULL,
+                srs_id INTEGER NOT NULL PRIMARY KEY,
+                organization TEXT NOT NULL,
+                organization_coordsys_id INTEGER NOT NULL,
+                definition TEXT NOT NULL,
+                description TEXT
+            );
+            CREATE TABLE gpkg_contents (
+                table_name TEXT NOT NULL PRIMARY KEY,
+                data_type TEXT NOT NULL,
+                identifier TEXT UNIQUE,
+                description TEXT DEFAULT '',
+                last_change DATETIME NOT NULL DEFAULT (strftime('%Y-%m-%dT%H:%M:%fZ', 'now')),
+                min_x DOUBLE,
+                min_y DOUBLE,
+                max_x DOUBLE,
+                max_y DOUBLE,
+                srs_id INTEGER,
+                CONSTRAINT fk_gc_r_srs_id FOREIGN KEY (srs_id) REFERENCES gpkg_spatial_ref_sys(srs_id)
+            );
+            CREATE TABLE gpkg_geometry_columns (
+                table_name TEXT NOT NULL,
+                column_name TEXT NOT NULL,
+                geometry_type_name TEXT NOT NULL,
+                srs_id INTEGER NOT NULL,
+                z TINYINT NOT NULL,
+                m TINYINT NOT NULL,
+                CONSTRAINT pk_geom_cols PRIMARY KEY (table_name, column_name),
+                CONSTRAINT uk_gc_table_name UNIQUE (table_name),
+                CONSTRAINT fk_gc_tn FOREIGN KEY (table_name) REFERENCES gpkg_contents(table_name),
+                CONSTRAINT fk_gc_srs FOREIGN KEY (srs_id) REFERENCES gpkg_spatial_ref_sys(srs_id)
+            );
+            INSERT INTO gpkg_spatial_ref_sys VALUES (
+                'Undefined cartesian SRS', -1, 'NONE', -1, 'undefined',
+                'undefined cartesian coordinate reference system'
+            );
+            INSERT INTO gpkg_spatial_ref_sys VALUES (
+                'Undefined geographic SRS', 0, 'NONE', 0, 'undefined',
+                'undefined geographic coordinate reference system'
+            );
+            INSERT INTO gpkg_spatial_ref_sys VALUES (
+                'WGS 84 geodetic', 4326, 'EPSG', 4326,
+                'GEOGCS["WGS 84",DATUM["WGS_1984",SPHEROID["WGS 84",6378137,298.257223563,AUTHORITY["EPSG","7030"]],AUTHORITY["EPSG","6326"]],PRIMEM["Greenwich",0,AUTHORITY["EPSG","8901"]],UNIT["degree",0.0174532925199433,AUTHORITY["EPSG","9122"]],AUTHORITY["EPSG","4326"]]',
+                'longitude/latitude coordinates in decimal degrees on the WGS 84 spheroid'
+            );
+            "#,
+        )?;
+
+        Ok(())
+    }
+
+    /// Create the dataset and unit tables and register them as contents.
+    fn create_data_tables(&self) -> Result<(), Error> {
+        let settings = &self.export_settings.geopackage;
+
+        self.connection.execute_batch(&format!(
+            "CREATE TABLE {table} (\
+                 fid INTEGER PRIMARY KEY AUTOINCREMENT, \
+                 dataset_id TEXT NOT NULL, \
+                 dataset_path TEXT NOT NULL, \
+                 landing_page TEXT NOT NULL, \
+                 provider TEXT NOT NULL, \
+                 abcd_version TEXT NOT NULL\
+                 {columns}\
+             );",
+            table = quote(&settings.dataset_table),
+            columns = column_definitions(&self.dataset_fields),
+        ))?;
+
+        self.connection.execute_batch(&format!(
+            "CREATE TABLE {table} (\
+                 fid INTEGER PRIMARY KEY AUTOINCREMENT, \
+                 {geometry_column} POINT, \
+                 dataset_fid INTEGER NOT NULL REFERENCES {dataset_table}(fid)\
+                 {columns}\
+             );",
+            table = quote(&settings.unit_table),
+            geometry_column = Self::GEOMETRY_COLUMN,
+            dataset_table = quote(&settings.dataset_table),
+            columns = column_definitions(&self.unit_fields),
+        ))?;
+
+        self.connection.execute(
+            "INSERT INTO gpkg_contents (table_name, data_type, identifier) VALUES (?1, 'attributes', ?1);",
+            params![settings.dataset_table],
+        )?;
+        self.connection.execute(
+            "INSERT INTO gpkg_contents (table_name, data_type, identifier, srs_id) VALUES (?1, 'features', ?1, ?2);",
+            params![settings.unit_table, Self::SRS_ID],
+        )?;
+        self.connection.execute(
+            "INSERT INTO gpkg_geometry_columns VALUES (?1, ?2, 'POINT', ?3, 0, 0);",
+            params![settings.unit_table, Self::GEOMETRY_COLUMN, Self::SRS_ID],
+        )?;
+
+        Ok(())
+    }
+
+    /// Insert a dataset and its units.
+    /// Units get a point geometry if they contain numeric longitude and latitude fields.
+    /// On failure, the dataset and all of its units are rolled back to a savepoint.
+    /// Returns the number of inserted units.
+    pub fn insert_dataset<I, E>(&mut self, dataset: &AbcdResult, units: I) -> Result<usize, Error>
+    where
+        I: IntoIterator<Item = Result<ValueMap, E>>,
+        E: Into<Error>,
+    {
+        let bounds = self.bounds;
+
+        self.connection.execute_batch("SAVEPOINT dataset;")?;
+
+        match self.insert_dataset_rows(dataset, units) {
+            Ok(number_of_units) => {
+                self.connection.execute_batch("RELEASE dataset;")?;
+
+                Ok(number_of_units)
+            }
+            Err(error) => {
+                self.bounds = bounds;
+                self.connection
+                    .execute_batch("ROLLBACK TO dataset; RELEASE dataset;")?;
+
+                Err(error)
+            }
+        }
+    }
+
+    /// Insert the rows of a dataset and its units.
+    fn insert_dataset_rows<I, E>(&mut self, dataset: &AbcdResult, units: I) -> Result<usize, Error>
+    where
+        I: IntoIterator<Item = Result<ValueMap, E>>,
+        E: Into<Error>,
+    {
+        let settings = &self.export_settings.geopackage;
+
+        let mut dataset_values = vec![
+            SqlValue::Text(dataset.dataset_id.clone()),
+            SqlValue::Text(dataset.dataset_path.clone()),
+            SqlValue::Text(dataset.landing_page.clone()),
+            SqlValue::Text(dataset.provider_name.clone()),
+            SqlValue::Text(dataset.abcd_version.to_string()),
+        ];
+        dataset_values.extend(field_values(&self.dataset_fields, &dataset.dataset));
+
+        self.connection.execute(
+            &format!(
+                "INSERT INTO {table} VALUES (NULL{parameters});",
+                table = quote(&settings.dataset_table),
+                parameters = ", ?".repeat(dataset_values.len()),
+            ),
+            params_from_iter(dataset_values),
+        )?;
+        let dataset_fid = self.connection.last_insert_rowid();
+
+        let mut statement = self.connection.prepare(&format!(
+            "INSERT INTO {table} VALUES (NULL, ?, ?{parameters});",
+            table = quote(&settings.unit_table),
+            parameters = ", ?".repeat(self.unit_fields.len()),
+        ))?;
+
+        let mut number_of_units = 0;
+        for unit in units {
+            let unit = unit.map_err(Into::into)?;
+
+            let coordinates = match (
+                unit.get(&self.export_settings.longitude_field),
+                unit.get(&self.export_settings.latitude_field),
+            ) {
+                (Some(VatType::Numeric(x)), Some(VatType::Numeric(y))) => Some((*x, *y)),
+                _ => None,
+            };
+
+            let mut unit_values = vec![
+                coordinates.map_or(SqlValue::Null, |(x, y)| {
+                    SqlValue::Blob(point_geometry(x, y, Self::SRS_ID))
+                }),
+                SqlValue::Integer(dataset_fid),
+            ];
+            unit_values.extend(field_values(&self.unit_fields, &unit));
+
+            statement.execute(params_from_iter(unit_values))?;
+
+            if let Some((x, y)) = coordinates {
+                self.bounds = Some(match self.bounds {
+                    Some(bounds) => bounds.extend(x, y),
+                    None => BoundingBox::from_point(x, y),
+                });
+            }
+
+            number_of_units += 1;
+        }
+
+        Ok(number_of_units)
+    }
+}
+
+impl<'s> Sink for GeoPackageWriter<'s> {
+    fn insert_dataset(
+        &mut self,
+        dataset: &AbcdResult,
+        units: &mut dyn Iterator<Item = Result<ValueMap, Error>>,
+    ) -> Result<usize, Error> {
+        GeoPackageWriter::insert_dataset(self, dataset, units)
+    }
+
+    /// Store the extent of the units, create the indexes and commit all inserts.
+    fn finish(&mut self) -> Result<(), Error> {
+        let settings = &self.export_settings.geopackage;
+
+        if let Some(bounds) = self.bounds {
+            self.connection.execute(
+                "UPDATE gpkg_contents SET min_x = ?1, min_y = ?2, max_x = ?3, max_y = ?4 WHERE table_name = ?5;",
+                params![
+                    bounds.min_x,
+                    bounds.min_y,
+                    bounds.max_x,
+                    bounds.max_y,
+                    settings.unit_table
+                ],
+            )?;
+        }
+
+        for (index, fields) in settings.indexes.iter().enumerate() {
+            if let Some(field) = fields
+                .iter()
+                .find(|field| !self.unit_fields.iter().any(|f| &f.name == *field))
+            {
+                return Err(failure::err_msg(format!(
+                    "Unable to create index for unknown unit field `{}`",
+                    field
+                )));
+            }
+
+            self.connection.execute_batch(&format!(
+                "CREATE INDEX {index_name} ON {table} ({columns});",
+                index_name = quote(&format!("{}_index_{}", settings.unit_table, index)),
+                table = quote(&settings.unit_table),
+                columns = fields
+                    .iter()
+                    .map(|field| quote(field))
+                    .collect::<Vec<_>>()
+                    .join(", "),
+            ))?;
+        }
+
+        self.connection.execute_batch("COMMIT;")?;
+
+        Ok(())
+    }
+
+    /// Discard all inserts.
+    fn abort(&mut self) -> Result<(), Error> {
+        self.connection.execute_batch("ROLLBACK;")?;
+
+        Ok(())
+    }
+}
+
+impl BoundingBox {
+    fn from_point(x: f64, y: f64) -> Self {
+        Self {
+            min_x: x,
+            min_y: y,
+            max_x: x,
+            max_y: y,
+        }
+    }
+
+    fn extend(self, x: f64, y: f64) -> Self {
+        Self {
+            min_x: self.min_x.min(x),
+            min_y: self.min_y.min(y),
+            max_x: self.max_x.max(x),
+            max_y: self.max_y.max(y),
+        }
+    }
+}
+
+/// Quote an identifier for SQLite.
+fn quote(identifier: &str) -> String {
+    format!("\"{}\"", identifier.replace('"', "\"\""))
+}
+
+/// Define a column per field, each with a leading comma.
+fn column_definitions(fields: &[&AbcdField]) -> String {
+    fields
+        .iter()
+        .map(|field| {
+            format!(
+                ", {} {}",
+                quote(&field.name),
+                if field.numeric { "REAL" } else { "TEXT" }
+            )
+        })
+        .collect()
+}
+
+/// Look up the values of the fields, where lists are joined to text.
+fn field_values<'v>(
+    fields: &'v [&AbcdField],
+    values: &'v ValueMap,
+) -> impl Iterator<Item = SqlValue> + 'v {
+    fields
+        .iter()
+        .map(move |field| match values.get(&field.name) {
+            Some(VatType::Textual(value)) => SqlValue::Text(value.clone()),
+            Some(VatType::Numeric(value)) => SqlValue::Real(*value),
+            Some(value) => SqlValue::Text(value.to_string()),
+            None => SqlValue::Null,
+        })
+}
+
+/// Encode a point as a GeoPackage geometry blob, i.e., a header without envelope followed by the WKB geometry.
+fn point_geometry(x: f64, y: f64, srs_id: i32) -> Vec<u8> {
+    let mut blob = Vec::with_capacity(29);
+
+    blob.extend_from_slice(b"GP");
+    blob.push(0); // version 1
+    blob.push(0b0000_0001); // little endian, no envelope
+    blob.extend_from_slice(&srs_id.to_le_bytes());
+
+    blob.push(1); // little endian
+    blob.extend_from_slice(&1_u32.to_le_bytes()); // point
+    blob.extend_from_slice(&x.to_le_bytes());
+    blob.extend_from_slice(&y.to_le_bytes());
+
+    blob
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::test_utils::{
+        self, create_dataset, create_export_settings, LATITUDE, LONGITUDE, TITLE, UNIT_ID,
+    };
+
+    use super::*;
+
+    #[test]
+    fn write_geopackage() {
+        let abcd_fields = create_abcd_fields();
+        let mut export_settings = create_export_settings();
+        export_settings.geopackage.indexes = vec![vec![UNIT_ID.into()]];
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("units.gpkg");
+
+        let mut writer = GeoPackageWriter::create(&path, &export_settings, &abcd_fields).unwrap();
+        let number_of_units = writer
+            .insert_dataset(
+                &create_dataset(),
+                vec![
+                    Ok::<_, Error>(
+                        [
+                            (UNIT_ID.to_string(), VatType::from("UNIT_1")),
+                            (LONGITUDE.to_string(), VatType::from(10.5)),
+                            (LATITUDE.to_string(), VatType::from(50.25)),
+                        ]
+                        .into_iter()
+                        .collect(),
+                    ),
+                    Ok([(UNIT_ID.to_string(), VatType::from("UNIT_2"))]
+                        .into_iter()
+                        .collect()),
+                ],
+            )
+            .unwrap();
+        writer.finish().unwrap();
+
+        assert_eq!(number_of_units, 2);
+
+        let connection = Connection::open(&path).unwrap();
+        let query_value =
+            |sql: &str| -> SqlValue { connection.query_row(sql, [], |row| row.get(0)).unwrap() };
+
+        assert_eq!(
+            query_value("PRAGMA application_id;"),
+            SqlValue::Integer(0x4750_4B47)
+        );
+        assert_eq!(
+            query_value("SELECT count(*) FROM gpkg_spatial_ref_sys WHERE srs_id IN (-1, 0, 4326);"),
+            SqlValue::Integer(3)
+        );
+        assert_eq!(
+            query_value(
+                "SELECT column_name FROM gpkg_geometry_columns WHERE table_name = 'units';"
+            ),
+            SqlValue::Text("geom".into())
+        );
+        assert_eq!(
+            query_value("SELECT data_type FROM gpkg_contents WHERE table_name = 'datasets';"),
+            SqlValue::Text("attributes".into())
+        );
+        assert_eq!(
+            query_value("SELECT max_x FROM gpkg_contents WHERE table_name = 'units';"),
+            SqlValue::Real(10.5)
+        );
+
+        assert_eq!(
+            query_value(&format!("SELECT \"{}\" FROM datasets;", TITLE)),
+            SqlValue::Text("TITLE".into())
+        );
+        assert_eq!(
+            query_value("SELECT abcd_version FROM datasets;"),
+            SqlValue::Text("2.06".into())
+        );
+
+        assert_eq!(
+            query_value("SELECT geom FROM units WHERE fid = 1;"),
+            SqlValue::Blob(point_geometry(10.5, 50.25, 4326))
+        );
+        assert_eq!(
+            query_value("SELECT geom FROM units WHERE fid = 2;"),
+            SqlValue::Null
+        );
+        assert_eq!(
+            query_value(&format!(
+                "SELECT \"{}\" FROM units WHERE fid = 2 AND dataset_fid = 1;",
+                UNIT_ID
+            )),
+            SqlValue::Text("UNIT_2".into())
+        );
+        assert_eq!(
+            query_value("SELECT count(*) FROM sqlite_master WHERE type = 'index' AND name = 'units_index_0';"),
+            SqlValue::Integer(1)
+        );
+    }
+
+    #[test]
+    fn roll_back_failed_dataset() {
+        let abcd_fields = create_abcd_fields();
+        let export_settings = create_export_settings();
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("units.gpkg");
+
+        let unit = |id: &str, x: f64| -> ValueMap {
+            [
+                (UNIT_ID.to_string(), VatType::from(id)),
+                (LONGITUDE.to_string(), VatType::from(x)),
+                (LATITUDE.to_string(), VatType::from(50.0)),
+            ]
+            .into_iter()
+            .collect()
+        };
+
+        let mut writer = GeoPackageWriter::create(&path, &export_settings, &abcd_fields).unwrap();
+        writer
+            .insert_dataset(
+                &create_dataset(),
+                vec![Ok::<_, Error>(unit("UNIT_1", 10.0))],
+            )
+            .unwrap();
+        assert!(writer
+            .insert_dataset(
+                &create_dataset(),
+                vec![
+                    Ok(unit("UNIT_2", 20.0)),
+                    Err(failure::err_msg("unparsable unit")),
+                ],
+            )
+            .is_err());
+        writer.finish().unwrap();
+
+        let connection = Connection::open(&path).unwrap();
+        let query_value =
+            |sql: &str| -> SqlValue { connection.query_row(sql, [], |row| row.get(0)).unwrap() };
+
+        assert_eq!(
+            query_value("SELECT count(*) FROM datasets;"),
+            SqlValue::Integer(1)
+        );
+        assert_eq!(
+            query_value("SELECT count(*) FROM units;"),
+            SqlValue::Integer(1)
+        );
+        assert_eq!(
+            query_value("SELECT max_x FROM gpkg_contents WHERE table_name = 'units';"),
+            SqlValue::Real(10.0)
+        );
+    }
+
+    #[test]
+    fn encode_point_geometry() {
+        let blob = point_geometry(1.0, 2.0, 4326);
+
+        assert_eq!(&blob[..4], b"GP\x00\x01");
+        assert_eq!(&blob[4..8], &4326_i32.to_le_bytes());
+        assert_eq!(&blob[8..13], &[1, 1, 0, 0, 0]);
+        assert_eq!(&blob[13..21], &1.0_f64.to_le_bytes());
+        assert_eq!(&blob[21..], &2.0_f64.to_le_bytes());
+    }
+
+    #[test]
+    fn reject_index_of_unknown_field() {
+        let abcd_fields = create_abcd_fields();
+        let mut export_settings = create_export_settings();
+        export_settings.geopackage.indexes = vec![vec!["UNKNOWN".into()]];
+        let dir = tempfile::tempdir().unwrap();
+
+        let mut writer = GeoPackageWriter::create(
+            &dir.path().join("units.gpkg"),
+            &export_settings,
+            &abcd_fields,
+        )
+        .unwrap();
+
+        assert!(writer.finish().is_err());
+    }
+
+    fn create_abcd_fields() -> AbcdFields {
+        test_utils::create_abcd_fields(&[
+            test_utils::abcd_field(TITLE, false, true),
+            test_utils::abcd_field(UNIT_ID, false, false),
+            test_utils::abcd_field(LONGITUDE, true, false),
+            test_utils::abcd_field(LATITUDE, true, false),
+        ])
+    }
+}
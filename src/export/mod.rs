@@ -0,0 +1,11 @@
+mod csv_writer;
+mod dwc_archive_writer;
+mod geojson_writer;
+mod geopackage_writer;
+mod parquet_writer;
+
+pub use self::csv_writer::CsvWriter;
+pub use self::dwc_archive_writer::{DwcArchiveWriter, DwcMapping};
+pub use self::geojson_writer::GeoJsonWriter;
+pub use self::geopackage_writer::GeoPackageWriter;
+pub use self::parquet_writer::ParquetWriter;
@@ -0,0 +1,377 @@
+use std::io::Write;
+use std::sync::Arc;
+
+use arrow_array::builder::{Float64Builder, ListBuilder, StringBuilder};
+use arrow_array::{ArrayRef, RecordBatch};
+use arrow_schema::{DataType, Field, Schema, SchemaRef};
+use failure::Error;
+use parquet::arrow::ArrowWriter;
+use parquet::basic::Compression;
+use parquet::file::properties::WriterProperties;
+
+use crate::abcd::{AbcdField, AbcdFields, AbcdResult, FieldMultiplicity, ValueMap};
+use crate::settings::ExportSettings;
+use crate::sink::Sink;
+use crate::vat_type::VatType;
+
+/// This writer stores units as rows of a Parquet file, where each row group contains a batch of units.
+/// The columns are the dataset metadata and all fields, where dataset fields are repeated for each unit.
+pub struct ParquetWriter<'s, W: Write + Send> {
+    writer: ArrowWriter<W>,
+    schema: SchemaRef,
+    fields: Vec<&'s AbcdField>,
+    metadata_columns: Vec<StringBuilder>,
+    field_columns: Vec<ColumnBuilder>,
+    row_group_size: usize,
+    rows: usize,
+}
+
+/// A builder for the column of a field, depending on its type and multiplicity.
+enum ColumnBuilder {
+    Numeric(Float64Builder),
+    Textual(StringBuilder),
+    NumericList(ListBuilder<Float64Builder>),
+    TextualList(ListBuilder<StringBuilder>),
+}
+
+impl<'s, W: Write + Send> ParquetWriter<'s, W> {
+    const METADATA_COLUMNS: [&'static str; 5] = [
+        "dataset_id",
+        "dataset_path",
+        "landing_page",
+        "provider",
+        "abcd_version",
+    ];
+
+    pub fn new(
+        output: W,
+        export_settings: &'s ExportSettings,
+        abcd_fields: &'s AbcdFields,
+    ) -> Result<Self, Error> {
+        let mut fields: Vec<&AbcdField> = abcd_fields.into_iter().collect();
+        fields.sort_by(|a, b| a.name.cmp(&b.name));
+
+        let schema = Arc::new(Schema::new(
+            Self::METADATA_COLUMNS
+                .iter()
+                .map(|name| Field::new(*name, DataType::Utf8, false))
+                .chain(
+                    fields
+                        .iter()
+                        .map(|field| Field::new(&field.name, data_type(field), true)),
+                )
+                .collect::<Vec<_>>(),
+        ));
+
+        let row_group_size = export_settings.parquet.row_group_size.max(1);
+        let properties = WriterProperties::builder()
+            .set_compression(Compression::SNAPPY)
+            .set_max_row_group_size(row_group_size)
+            .build();
+
+        Ok(Self {
+            writer: ArrowWriter::try_new(output, schema.clone(), Some(properties))?,
+            schema,
+            metadata_columns: Self::METADATA_COLUMNS
+                .iter()
+                .map(|_| StringBuilder::new())
+                .collect(),
+            field_columns: fields
+                .iter()
+                .map(|field| ColumnBuilder::new(field))
+                .collect(),
+            fields,
+            row_group_size,
+            rows: 0,
+        })
+    }
+
+    /// Append the units of a dataset and write a row group whenever a batch is complete.
+    /// Returns the number of written units.
+    pub fn insert_dataset<I, E>(&mut self, dataset: &AbcdResult, units: I) -> Result<usize, Error>
+    where
+        I: IntoIterator<Item = Result<ValueMap, E>>,
+        E: Into<Error>,
+    {
+        let abcd_version = dataset.abcd_version.to_string();
+        let metadata = [
+            dataset.dataset_id.as_str(),
+            dataset.dataset_path.as_str(),
+            dataset.landing_page.as_str(),
+            dataset.provider_name.as_str(),
+            abcd_version.as_str(),
+        ];
+
+        let mut number_of_units = 0;
+        for unit in units {
+            let unit = unit.map_err(Into::into)?;
+
+            for (column, value) in self.metadata_columns.iter_mut().zip(metadata) {
+                column.append_value(value);
+            }
+
+            for (column, field) in self.field_columns.iter_mut().zip(&self.fields) {
+                column.append(
+                    unit.get(&field.name)
+                        .or_else(|| dataset.dataset.get(&field.name)),
+                );
+            }
+
+            self.rows += 1;
+            number_of_units += 1;
+
+            if self.rows >= self.row_group_size {
+                self.write_batch()?;
+            }
+        }
+
+        Ok(number_of_units)
+    }
+
+    /// Write the buffered rows as a row group.
+    fn write_batch(&mut self) -> Result<(), Error> {
+        let columns: Vec<ArrayRef> = self
+            .metadata_columns
+            .iter_mut()
+            .map(|column| Arc::new(column.finish()) as ArrayRef)
+            .chain(self.field_columns.iter_mut().map(ColumnBuilder::finish))
+            .collect();
+
+        self.writer
+            .write(&RecordBatch::try_new(self.schema.clone(), columns)?)?;
+        self.writer.flush()?;
+
+        self.rows = 0;
+
+        Ok(())
+    }
+}
+
+impl<'s, W: Write + Send> Sink for ParquetWriter<'s, W> {
+    fn insert_dataset(
+        &mut self,
+        dataset: &AbcdResult,
+        units: &mut dyn Iterator<Item = Result<ValueMap, Error>>,
+    ) -> Result<usize, Error> {
+        ParquetWriter::insert_dataset(self, dataset, units)
+    }
+
+    /// Write the remaining rows and the file footer.
+    fn finish(&mut self) -> Result<(), Error> {
+        if self.rows > 0 {
+            self.write_batch()?;
+        }
+
+        self.writer.finish()?;
+
+        Ok(())
+    }
+
+    /// Write the footer, so the rows inserted so far remain a readable Parquet file.
+    fn abort(&mut self) -> Result<(), Error> {
+        Sink::finish(self)
+    }
+}
+
+impl ColumnBuilder {
+    fn new(field: &AbcdField) -> Self {
+        match (field.multiplicity, field.numeric) {
+            (FieldMultiplicity::Join, _) => ColumnBuilder::Textual(StringBuilder::new()),
+            (FieldMultiplicity::All, true) => {
+                ColumnBuilder::NumericList(ListBuilder::new(Float64Builder::new()))
+            }
+            (FieldMultiplicity::All, false) => {
+                ColumnBuilder::TextualList(ListBuilder::new(StringBuilder::new()))
+            }
+            (_, true) => ColumnBuilder::Numeric(Float64Builder::new()),
+            (_, false) => ColumnBuilder::Textual(StringBuilder::new()),
+        }
+    }
+
+    /// Append a value, where values that do not match the column type are converted or left out.
+    fn append(&mut self, value: Option<&VatType>) {
+        match (self, value) {
+            (ColumnBuilder::Numeric(builder), Some(VatType::Numeric(value))) => {
+                builder.append_value(*value)
+            }
+            (ColumnBuilder::Numeric(builder), _) => builder.append_null(),
+            (ColumnBuilder::Textual(builder), Some(VatType::Textual(value))) => {
+                builder.append_value(value)
+            }
+            (ColumnBuilder::Textual(builder), Some(value)) => {
+                builder.append_value(value.to_string())
+            }
+            (ColumnBuilder::Textual(builder), None) => builder.append_null(),
+            (ColumnBuilder::NumericList(builder), Some(VatType::NumericList(values))) => {
+                builder.values().append_slice(values);
+                builder.append(true);
+            }
+            (ColumnBuilder::NumericList(builder), Some(VatType::Numeric(value))) => {
+                builder.values().append_value(*value);
+                builder.append(true);
+            }
+            (ColumnBuilder::NumericList(builder), _) => builder.append(false),
+            (ColumnBuilder::TextualList(builder), Some(VatType::TextualList(values))) => {
+                for value in values {
+                    builder.values().append_value(value);
+                }
+                builder.append(true);
+            }
+            (ColumnBuilder::TextualList(builder), Some(value)) => {
+                builder.values().append_value(value.to_string());
+                builder.append(true);
+            }
+            (ColumnBuilder::TextualList(builder), None) => builder.append(false),
+        }
+    }
+
+    fn finish(&mut self) -> ArrayRef {
+        match self {
+            ColumnBuilder::Numeric(builder) => Arc::new(builder.finish()),
+            ColumnBuilder::Textual(builder) => Arc::new(builder.finish()),
+            ColumnBuilder::NumericList(builder) => Arc::new(builder.finish()),
+            ColumnBuilder::TextualList(builder) => Arc::new(builder.finish()),
+        }
+    }
+}
+
+/// The Arrow type of a field's column.
+fn data_type(field: &AbcdField) -> DataType {
+    let list_of = |data_type| DataType::List(Arc::new(Field::new("item", data_type, true)));
+
+    match (field.multiplicity, field.numeric) {
+        (FieldMultiplicity::Join, _) => DataType::Utf8,
+        (FieldMultiplicity::All, true) => list_of(DataType::Float64),
+        (FieldMultiplicity::All, false) => list_of(DataType::Utf8),
+        (_, true) => DataType::Float64,
+        (_, false) => DataType::Utf8,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs::File;
+
+    use arrow_array::cast::AsArray;
+    use arrow_array::types::Float64Type;
+    use arrow_array::Array;
+    use parquet::arrow::arrow_reader::ParquetRecordBatchReaderBuilder;
+    use serde_json::json;
+
+    use crate::test_utils::{
+        self, create_dataset, create_export_settings, LONGITUDE, TITLE, UNIT_ID,
+    };
+
+    use super::*;
+
+    const ALTITUDE: &str = "/DataSets/DataSet/Units/Unit/Altitude";
+
+    #[test]
+    fn write_row_groups() {
+        let abcd_fields = create_abcd_fields();
+        let mut export_settings = create_export_settings();
+        export_settings.parquet.row_group_size = 2;
+        let file = tempfile::NamedTempFile::new().unwrap();
+
+        let mut writer =
+            ParquetWriter::new(file.reopen().unwrap(), &export_settings, &abcd_fields).unwrap();
+        let units: Vec<Result<ValueMap, Error>> = vec![
+            Ok([
+                (UNIT_ID.to_string(), VatType::from("UNIT_1")),
+                (LONGITUDE.to_string(), VatType::from(10.5)),
+                (ALTITUDE.to_string(), VatType::NumericList(vec![100., 120.])),
+            ]
+            .into_iter()
+            .collect()),
+            Ok([(UNIT_ID.to_string(), VatType::from("UNIT_2"))]
+                .into_iter()
+                .collect()),
+            Ok([(UNIT_ID.to_string(), VatType::from("UNIT_3"))]
+                .into_iter()
+                .collect()),
+        ];
+        assert_eq!(writer.insert_dataset(&create_dataset(), units).unwrap(), 3);
+        writer.finish().unwrap();
+
+        let reader_builder =
+            ParquetRecordBatchReaderBuilder::try_new(File::open(file.path()).unwrap()).unwrap();
+        assert_eq!(reader_builder.metadata().num_row_groups(), 2);
+
+        let schema = reader_builder.schema().clone();
+        assert_eq!(
+            schema.field_with_name(LONGITUDE).unwrap().data_type(),
+            &DataType::Float64
+        );
+        assert_eq!(
+            schema.field_with_name(UNIT_ID).unwrap().data_type(),
+            &DataType::Utf8
+        );
+        assert!(matches!(
+            schema.field_with_name(ALTITUDE).unwrap().data_type(),
+            DataType::List(_)
+        ));
+
+        let batches = reader_builder
+            .build()
+            .unwrap()
+            .collect::<Result<Vec<_>, _>>()
+            .unwrap();
+        assert_eq!(batches.iter().map(RecordBatch::num_rows).sum::<usize>(), 3);
+
+        let batch = &batches[0];
+        let column = |name: &str| batch.column(schema.index_of(name).unwrap()).clone();
+
+        assert_eq!(
+            column("dataset_id").as_string::<i32>().value(1),
+            "DATASET_ID"
+        );
+        assert_eq!(column("abcd_version").as_string::<i32>().value(0), "2.06");
+        assert_eq!(column(TITLE).as_string::<i32>().value(1), "TITLE");
+        assert_eq!(column(UNIT_ID).as_string::<i32>().value(1), "UNIT_2");
+
+        let longitude = column(LONGITUDE);
+        let longitude = longitude.as_primitive::<Float64Type>();
+        assert_eq!(longitude.value(0), 10.5);
+        assert!(longitude.is_null(1));
+
+        let altitude = column(ALTITUDE);
+        let altitude = altitude.as_list::<i32>();
+        assert_eq!(
+            altitude.value(0).as_primitive::<Float64Type>().values(),
+            &[100., 120.]
+        );
+        assert!(altitude.is_null(1));
+    }
+
+    #[test]
+    fn write_footer_on_abort() {
+        let abcd_fields = create_abcd_fields();
+        let export_settings = create_export_settings();
+        let file = tempfile::NamedTempFile::new().unwrap();
+
+        let mut writer =
+            ParquetWriter::new(file.reopen().unwrap(), &export_settings, &abcd_fields).unwrap();
+        let units: Vec<Result<ValueMap, Error>> =
+            vec![Ok([(UNIT_ID.to_string(), VatType::from("UNIT_1"))]
+                .into_iter()
+                .collect())];
+        writer.insert_dataset(&create_dataset(), units).unwrap();
+        Sink::abort(&mut writer).unwrap();
+
+        let reader_builder =
+            ParquetRecordBatchReaderBuilder::try_new(File::open(file.path()).unwrap()).unwrap();
+        assert_eq!(reader_builder.metadata().file_metadata().num_rows(), 1);
+    }
+
+    fn create_abcd_fields() -> AbcdFields {
+        let mut altitude = test_utils::abcd_field(ALTITUDE, true, false);
+        altitude["multiplicity"] = json!("all");
+
+        test_utils::create_abcd_fields(&[
+            test_utils::abcd_field(TITLE, false, true),
+            test_utils::abcd_field(UNIT_ID, false, false),
+            test_utils::abcd_field(LONGITUDE, true, false),
+            altitude,
+        ])
+    }
+}
@@ -1,53 +1,443 @@
-use failure::Error;
-use std::{fs::File, path::Path};
+use crate::http_client::HttpClient;
+use failure::{Error, Fail};
+use log::warn;
+use reqwest::{header, StatusCode};
+use serde::{Deserialize, Serialize};
+use std::{
+    collections::BTreeMap,
+    fs::File,
+    io::Read,
+    path::{Path, PathBuf},
+};
 
-pub struct FileDownloader {
+/// The local file headers and the end of central directory record of ZIP archives start with these bytes.
+const ZIP_MAGIC_BYTES: [&[u8; 4]; 2] = [b"PK\x03\x04", b"PK\x05\x06"];
+
+pub struct FileDownloader<'c> {
+    http_client: &'c HttpClient,
     url: String,
+    cached_file: Option<PathBuf>,
+    max_size: Option<u64>,
+}
+
+/// This struct contains the caching headers of a downloaded file.
+#[derive(Clone, Debug, Default, PartialEq, Eq, Deserialize, Serialize)]
+pub struct DownloadInfo {
+    pub etag: Option<String>,
+    pub last_modified: Option<String>,
+    /// The id of the dataset of a stored archive, since its file name is derived from the id.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub dataset_id: Option<String>,
+    /// The publisher of the dataset of a stored archive.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub publisher: Option<String>,
+    /// The URL that a stored archive was downloaded from.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub url: Option<String>,
+    /// The landing page of the dataset of a stored archive.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub landing_page: Option<String>,
+    /// Values of the dataset metadata columns of a stored archive.
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub metadata: BTreeMap<String, String>,
+}
+
+/// This enum reports whether a file was transferred or taken from the cache.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum DownloadOutcome {
+    /// The file was transferred from the webserver.
+    Downloaded(DownloadInfo),
+    /// The webserver reported that the cached file is still up to date.
+    NotModified(DownloadInfo),
+}
+
+impl DownloadInfo {
+    /// Load the info that is stored alongside a cached file.
+    pub fn load_for(cached_file: &Path) -> Result<Self, Error> {
+        let file = File::open(Self::sidecar_path(cached_file))?;
+        Ok(serde_json::from_reader(file)?)
+    }
+
+    /// Store the info alongside a cached file, so its next download can be conditional.
+    pub fn store_for(&self, cached_file: &Path) -> Result<(), Error> {
+        let file = File::create(Self::sidecar_path(cached_file))?;
+        Ok(serde_json::to_writer(file, self)?)
+    }
+
+    /// The path of the info file, i.e., the cached file's path with an additional `.json` extension.
+    fn sidecar_path(cached_file: &Path) -> PathBuf {
+        let mut path = cached_file.as_os_str().to_owned();
+        path.push(".json");
+        path.into()
+    }
 }
 
-impl FileDownloader {
-    pub fn from_url(url: &str) -> Self {
-        Self { url: url.into() }
+impl<'c> FileDownloader<'c> {
+    pub fn from_url(http_client: &'c HttpClient, url: &str) -> Self {
+        Self {
+            http_client,
+            url: url.into(),
+            cached_file: None,
+            max_size: None,
+        }
+    }
+
+    /// Reject files that are larger than `max_size` bytes.
+    pub fn with_max_size(mut self, max_size: u64) -> Self {
+        self.max_size = Some(max_size);
+        self
+    }
+
+    /// Use a cached copy of the file for a conditional request.
+    /// The copy is used if the webserver responds with `304 Not Modified`.
+    pub fn with_cached_file(mut self, cached_file: &Path) -> Self {
+        self.cached_file = Some(cached_file.into());
+        self
     }
 
-    pub fn to_path(&self, path: &Path) -> Result<(), Error> {
-        let mut response = reqwest::blocking::get(&self.url)?;
+    pub fn to_path(&self, path: &Path) -> Result<DownloadOutcome, Error> {
+        let cached = self.cached_file.as_ref().and_then(|cached_file| {
+            if !cached_file.is_file() {
+                return None;
+            }
+
+            match DownloadInfo::load_for(cached_file) {
+                Ok(info) => Some((cached_file, info)),
+                Err(e) => {
+                    warn!(
+                        "Unable to load download info of {}: {}",
+                        cached_file.display(),
+                        e
+                    );
+                    None
+                }
+            }
+        });
+
+        let mut request = self.http_client.get(&self.url);
+        if let Some((_, info)) = &cached {
+            if let Some(etag) = &info.etag {
+                request = request.header(header::IF_NONE_MATCH, etag);
+            }
+            if let Some(last_modified) = &info.last_modified {
+                request = request.header(header::IF_MODIFIED_SINCE, last_modified);
+            }
+        }
+
+        let mut response = self.http_client.send(request)?;
+
+        if let (StatusCode::NOT_MODIFIED, Some((cached_file, info))) = (response.status(), cached) {
+            std::fs::copy(cached_file, path)?;
+            return Ok(DownloadOutcome::NotModified(info));
+        }
 
         if !response.status().is_success() {
-            return Err(failure::err_msg(format!(
-                "Webserver responded with code: {}",
-                response.status(),
-            )));
+            return Err(FileDownloaderError::UnexpectedStatus(response.status()).into());
+        }
+
+        let header = |name: header::HeaderName| {
+            response
+                .headers()
+                .get(name)
+                .and_then(|value| value.to_str().ok())
+                .map(ToString::to_string)
+        };
+        let download_info = DownloadInfo {
+            etag: header(header::ETAG),
+            last_modified: header(header::LAST_MODIFIED),
+            ..Default::default()
+        };
+
+        if let Some(content_type) = header(header::CONTENT_TYPE) {
+            if !Self::is_archive_content_type(&content_type) {
+                return Err(FileDownloaderError::UnexpectedContentType(content_type).into());
+            }
+        }
+
+        let content_length = response.content_length();
+        if let (Some(content_length), Some(max_size)) = (content_length, self.max_size) {
+            if content_length > max_size {
+                return Err(FileDownloaderError::TooLarge { max_size }.into());
+            }
+        }
+
+        // a file at `path` is always complete
+        let part_path = Self::part_path(path);
+        let result = self
+            .write_part(&mut response, &part_path, content_length)
+            .and_then(|_| Ok(std::fs::rename(&part_path, path)?));
+        if result.is_err() {
+            let _ = std::fs::remove_file(&part_path);
+        }
+        result?;
+
+        Ok(DownloadOutcome::Downloaded(download_info))
+    }
+
+    /// Write the response body to the part file and validate it.
+    fn write_part(
+        &self,
+        body: &mut impl Read,
+        part_path: &Path,
+        content_length: Option<u64>,
+    ) -> Result<(), Error> {
+        let mut part_file = File::create(part_path)?;
+
+        let received = match self.max_size {
+            Some(max_size) => {
+                let received =
+                    std::io::copy(&mut body.take(max_size.saturating_add(1)), &mut part_file)?;
+                if received > max_size {
+                    return Err(FileDownloaderError::TooLarge { max_size }.into());
+                }
+                received
+            }
+            None => std::io::copy(body, &mut part_file)?,
+        };
+
+        if let Some(expected) = content_length {
+            if received != expected {
+                return Err(FileDownloaderError::Incomplete { expected, received }.into());
+            }
         }
 
-        let mut output_file = File::create(&path)?;
+        let mut magic_bytes = [0; 4];
+        let is_zip_archive = File::open(part_path)?.read_exact(&mut magic_bytes).is_ok()
+            && ZIP_MAGIC_BYTES.contains(&&magic_bytes);
+        if !is_zip_archive {
+            return Err(FileDownloaderError::NotAZipArchive.into());
+        }
+
+        Ok(part_file.sync_all()?)
+    }
 
-        response.copy_to(&mut output_file)?;
+    /// Webservers may send archives as `application/zip`, `application/octet-stream` or similar,
+    /// but documents like HTML error pages indicate that the download went wrong.
+    fn is_archive_content_type(content_type: &str) -> bool {
+        let media_type = content_type
+            .split(';')
+            .next()
+            .unwrap_or_default()
+            .trim()
+            .to_ascii_lowercase();
 
-        Ok(())
+        !(media_type.starts_with("text/")
+            || media_type == "application/json"
+            || media_type == "application/xhtml+xml")
+    }
+
+    /// The path of the incomplete download, i.e., the path with an additional `.part` extension.
+    fn part_path(path: &Path) -> PathBuf {
+        let mut part_path = path.as_os_str().to_owned();
+        part_path.push(".part");
+        part_path.into()
     }
 }
 
+/// This enum contains the reasons for rejecting a download.
+#[derive(Debug, Fail)]
+pub enum FileDownloaderError {
+    /// This error occurs when the webserver does not respond with a success code.
+    #[fail(display = "Webserver responded with code: {}", _0)]
+    UnexpectedStatus(StatusCode),
+    /// This error occurs when the webserver sends a document instead of an archive, e.g., an HTML error page.
+    #[fail(display = "Unexpected content type: {}", _0)]
+    UnexpectedContentType(String),
+    /// This error occurs when the file exceeds the maximum size.
+    #[fail(display = "File is larger than {} bytes", max_size)]
+    TooLarge { max_size: u64 },
+    /// This error occurs when the transfer ended before the announced content length was received.
+    #[fail(
+        display = "Incomplete file: received {} of {} bytes",
+        received, expected
+    )]
+    Incomplete { expected: u64, received: u64 },
+    /// This error occurs when the file is not a ZIP archive.
+    #[fail(display = "File is not a ZIP archive")]
+    NotAZipArchive,
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
 
-    use crate::test_utils::{create_empty_temp_file, MockWebserver};
+    use crate::test_utils::{
+        create_empty_temp_file, create_http_client, create_zip_file, MockWebserver,
+    };
     use std::fs;
 
+    fn zip_archive(content: &str) -> Vec<u8> {
+        fs::read(create_zip_file(&[("file.xml", content)])).unwrap()
+    }
+
     #[test]
     fn download_file() {
-        const CONTENT: &str = "foobar";
+        let content = zip_archive("foobar");
+
+        let webserver = MockWebserver::from_bytes("/", "GET", &content);
+        let download_file = create_empty_temp_file();
+        let http_client = create_http_client();
+
+        FileDownloader::from_url(&http_client, &webserver.webserver_root_url())
+            .to_path(&download_file)
+            .unwrap();
+
+        let file_content = fs::read(download_file).unwrap();
+
+        assert_eq!(content, file_content);
+    }
+
+    #[test]
+    fn download_file_with_caching_headers() {
+        let _mock = mockito::mock("GET", "/cached")
+            .with_header("etag", "\"ETAG\"")
+            .with_header("last-modified", "Wed, 21 Oct 2015 07:28:00 GMT")
+            .with_body(zip_archive("foobar"))
+            .create();
+        let download_file = create_empty_temp_file();
+        let http_client = create_http_client();
+
+        let outcome =
+            FileDownloader::from_url(&http_client, &format!("{}/cached", mockito::server_url()))
+                .to_path(&download_file)
+                .unwrap();
+
+        assert_eq!(
+            outcome,
+            DownloadOutcome::Downloaded(DownloadInfo {
+                etag: Some("\"ETAG\"".into()),
+                last_modified: Some("Wed, 21 Oct 2015 07:28:00 GMT".into()),
+                ..Default::default()
+            })
+        );
+    }
+
+    #[test]
+    fn use_cached_file_if_not_modified() {
+        let new_archive = zip_archive("new");
+        let _not_modified_mock = mockito::mock("GET", "/conditional")
+            .match_header("if-none-match", "\"ETAG\"")
+            .match_header("if-modified-since", "Wed, 21 Oct 2015 07:28:00 GMT")
+            .with_status(304)
+            .create();
+        let _modified_mock = mockito::mock("GET", "/conditional")
+            .match_header("if-none-match", mockito::Matcher::Missing)
+            .with_header("etag", "\"NEW_ETAG\"")
+            .with_body(&new_archive)
+            .create();
 
-        let webserver = MockWebserver::from_text("/", "GET", CONTENT);
+        let url = format!("{}/conditional", mockito::server_url());
+        let cache_dir = tempfile::tempdir().unwrap();
+        let cached_file = cache_dir.path().join("cached.zip");
         let download_file = create_empty_temp_file();
+        let http_client = create_http_client();
 
-        FileDownloader::from_url(&webserver.webserver_root_url())
+        // no cached copy
+        let outcome = FileDownloader::from_url(&http_client, &url)
+            .with_cached_file(&cached_file)
             .to_path(&download_file)
             .unwrap();
+        assert!(matches!(outcome, DownloadOutcome::Downloaded(_)));
+        assert_eq!(fs::read(&download_file).unwrap(), new_archive);
+
+        // cached copy with validators
+        let info = DownloadInfo {
+            etag: Some("\"ETAG\"".into()),
+            last_modified: Some("Wed, 21 Oct 2015 07:28:00 GMT".into()),
+            ..Default::default()
+        };
+        fs::write(&cached_file, "cached").unwrap();
+        info.store_for(&cached_file).unwrap();
+        assert_eq!(DownloadInfo::load_for(&cached_file).unwrap(), info);
+
+        let outcome = FileDownloader::from_url(&http_client, &url)
+            .with_cached_file(&cached_file)
+            .to_path(&download_file)
+            .unwrap();
+        assert_eq!(outcome, DownloadOutcome::NotModified(info));
+        assert_eq!(fs::read_to_string(&download_file).unwrap(), "cached");
+    }
 
-        let file_content = fs::read_to_string(download_file).unwrap();
+    /// Download a file that is rejected and check that no file is left behind.
+    fn rejected_download(path: &str, max_size: u64) -> FileDownloaderError {
+        let download_dir = tempfile::tempdir().unwrap();
+        let download_file = download_dir.path().join("download.zip");
+        fs::write(&download_file, "previous").unwrap();
+        let http_client = create_http_client();
 
-        assert_eq!(CONTENT, file_content);
+        let error =
+            FileDownloader::from_url(&http_client, &format!("{}{}", mockito::server_url(), path))
+                .with_max_size(max_size)
+                .to_path(&download_file)
+                .unwrap_err();
+
+        // the previous file is untouched and no partial download remains
+        assert_eq!(fs::read_to_string(&download_file).unwrap(), "previous");
+        assert_eq!(fs::read_dir(download_dir.path()).unwrap().count(), 1);
+
+        error.downcast::<FileDownloaderError>().unwrap()
+    }
+
+    #[test]
+    fn reject_html_error_page() {
+        let _mock = mockito::mock("GET", "/error-page")
+            .with_header("content-type", "text/html; charset=utf-8")
+            .with_body("<html><body>Service unavailable</body></html>")
+            .create();
+
+        assert!(matches!(
+            rejected_download("/error-page", 1024),
+            FileDownloaderError::UnexpectedContentType(content_type) if content_type.starts_with("text/html")
+        ));
+    }
+
+    #[test]
+    fn reject_non_zip_content() {
+        let _webserver = MockWebserver::from_text(
+            "/no-zip",
+            "GET",
+            "<html><body>Service unavailable</body></html>",
+        );
+
+        assert!(matches!(
+            rejected_download("/no-zip", 1024),
+            FileDownloaderError::NotAZipArchive
+        ));
+    }
+
+    #[test]
+    fn reject_too_large_file() {
+        let content = zip_archive("foobar");
+
+        let _mock = mockito::mock("GET", "/too-large")
+            .with_body(&content)
+            .create();
+
+        assert!(matches!(
+            rejected_download("/too-large", content.len() as u64 - 1),
+            FileDownloaderError::TooLarge { .. }
+        ));
+    }
+
+    #[test]
+    fn reject_unsuccessful_response() {
+        let _mock = mockito::mock("GET", "/missing").with_status(404).create();
+
+        assert!(matches!(
+            rejected_download("/missing", 1024),
+            FileDownloaderError::UnexpectedStatus(StatusCode::NOT_FOUND)
+        ));
+    }
+
+    #[test]
+    fn accept_archive_content_types() {
+        assert!(FileDownloader::is_archive_content_type("application/zip"));
+        assert!(FileDownloader::is_archive_content_type(
+            "application/octet-stream"
+        ));
+        assert!(!FileDownloader::is_archive_content_type(
+            "TEXT/HTML; charset=UTF-8"
+        ));
+        assert!(!FileDownloader::is_archive_content_type("application/json"));
     }
 }
@@ -0,0 +1,475 @@
+use std::collections::HashMap;
+use std::io::{BufReader, Read};
+use std::ops::{Deref, DerefMut};
+use std::sync::{Condvar, Mutex, MutexGuard, PoisonError};
+use std::time::{Duration, Instant, SystemTime};
+
+use failure::Error;
+use log::warn;
+use reqwest::blocking::{Client, RequestBuilder, Response};
+use reqwest::header::RETRY_AFTER;
+use reqwest::StatusCode;
+use serde::de::DeserializeOwned;
+
+use crate::settings::HttpSettings;
+
+/// This HTTP client is shared by all components that access remote services.
+/// It applies timeouts, retries transient failures with exponential backoff and
+/// limits the number of concurrent connections per host.
+pub struct HttpClient {
+    client: Client,
+    timeout: Option<Duration>,
+    max_retries: u32,
+    initial_backoff: Duration,
+    max_backoff: Duration,
+    max_connections_per_host: usize,
+    connections: Mutex<HashMap<String, usize>>,
+    connection_released: Condvar,
+}
+
+/// A response that occupies a connection of its host until it is dropped.
+/// Reading its body fails once the `deadline` of the request has passed.
+pub struct HttpResponse<'c> {
+    response: Response,
+    deadline: Option<Instant>,
+    _permit: HostPermit<'c>,
+}
+
+/// A permit for a connection to a host.
+struct HostPermit<'c> {
+    http_client: &'c HttpClient,
+    host: String,
+}
+
+impl HttpClient {
+    /// Create a new `HttpClient` from settings.
+    pub fn new(http_settings: &HttpSettings) -> Result<Self, Error> {
+        // the timeout of the blocking client applies to waiting for the response and to each read of its body
+        let client = Client::builder()
+            .connect_timeout(Duration::from_secs(http_settings.connect_timeout_seconds))
+            .timeout(Duration::from_secs(http_settings.read_timeout_seconds))
+            .build()?;
+
+        Ok(Self {
+            client,
+            timeout: http_settings.timeout_seconds.map(Duration::from_secs),
+            max_retries: http_settings.max_retries,
+            initial_backoff: Duration::from_millis(http_settings.initial_backoff_milliseconds),
+            max_backoff: Duration::from_millis(http_settings.max_backoff_milliseconds),
+            max_connections_per_host: http_settings.max_connections_per_host.max(1),
+            connections: Default::default(),
+            connection_released: Default::default(),
+        })
+    }
+
+    /// Create an `HttpClient` with the default timeouts of `reqwest` that never retries.
+    /// It serves to report that the configured client could not be created.
+    pub fn fallback() -> Result<Self, Error> {
+        Ok(Self {
+            client: Client::builder().build()?,
+            timeout: None,
+            max_retries: 0,
+            initial_backoff: Duration::ZERO,
+            max_backoff: Duration::ZERO,
+            max_connections_per_host: 1,
+            connections: Default::default(),
+            connection_released: Default::default(),
+        })
+    }
+
+    /// Start building a `GET` request.
+    pub fn get(&self, url: &str) -> RequestBuilder {
+        self.client.get(url)
+    }
+
+    /// Start building a `POST` request.
+    pub fn post(&self, url: &str) -> RequestBuilder {
+        self.client.post(url)
+    }
+
+    /// Start building a `DELETE` request.
+    pub fn delete(&self, url: &str) -> RequestBuilder {
+        self.client.delete(url)
+    }
+
+    /// Send a request and retry it on connection errors, timeouts, `429` and `5xx` responses.
+    /// The delay between attempts grows exponentially unless the server demands one via `Retry-After`.
+    /// A demanded delay that exceeds the maximum backoff is not waited for, but the response is returned.
+    /// Returns the last response if all retries fail with an error status.
+    pub fn send(&self, request: RequestBuilder) -> Result<HttpResponse<'_>, Error> {
+        let request = request.build()?;
+        let host = request.url().host_str().unwrap_or_default().to_string();
+
+        let mut attempt = 0;
+        loop {
+            let attempt_request = request
+                .try_clone()
+                .ok_or_else(|| failure::err_msg("Unable to retry a streaming request"))?;
+
+            let permit = self.acquire(&host);
+            let deadline = self.timeout.map(|timeout| Instant::now() + timeout);
+            let result = self.client.execute(attempt_request);
+
+            let retry = match &result {
+                Ok(response) if Self::is_transient(response.status()) => {
+                    match Self::retry_after(response) {
+                        Some(delay) if delay > self.max_backoff => {
+                            warn!(
+                                "Request to {url} failed ({reason}), not retrying, since the server demands a delay of {delay:?}",
+                                url = request.url(),
+                                reason = response.status(),
+                                delay = delay,
+                            );
+                            None
+                        }
+                        delay => Some((
+                            response.status().to_string(),
+                            delay.unwrap_or_else(|| self.backoff(attempt)),
+                        )),
+                    }
+                }
+                Err(e) if e.is_timeout() || e.is_connect() => {
+                    Some((e.to_string(), self.backoff(attempt)))
+                }
+                _ => None,
+            };
+
+            match retry {
+                Some((reason, delay)) if attempt < self.max_retries => {
+                    drop(permit);
+
+                    warn!(
+                        "Request to {url} failed ({reason}), retrying in {delay:?}",
+                        url = request.url(),
+                        reason = reason,
+                        delay = delay,
+                    );
+                    std::thread::sleep(delay);
+
+                    attempt += 1;
+                }
+                _ => {
+                    return Ok(HttpResponse {
+                        response: result?,
+                        deadline,
+                        _permit: permit,
+                    })
+                }
+            }
+        }
+    }
+
+    /// Responses that indicate a temporary problem of the server.
+    fn is_transient(status: StatusCode) -> bool {
+        status == StatusCode::TOO_MANY_REQUESTS || status.is_server_error()
+    }
+
+    /// Parse the `Retry-After` header, which is either a number of seconds or an HTTP date.
+    fn retry_after(response: &Response) -> Option<Duration> {
+        let value = response.headers().get(RETRY_AFTER)?.to_str().ok()?.trim();
+
+        if let Ok(seconds) = value.parse::<u64>() {
+            return Some(Duration::from_secs(seconds));
+        }
+
+        httpdate::parse_http_date(value).ok().map(|date| {
+            date.duration_since(SystemTime::now())
+                .unwrap_or(Duration::ZERO)
+        })
+    }
+
+    /// Compute the delay before the next attempt: an exponential backoff with jitter.
+    /// The delay lies between half and all of the exponential backoff.
+    fn backoff(&self, attempt: u32) -> Duration {
+        let backoff = self
+            .initial_backoff
+            .saturating_mul(2_u32.saturating_pow(attempt))
+            .min(self.max_backoff);
+
+        let half = backoff / 2;
+        let jitter_millis = fastrand::u64(0..=half.as_millis() as u64);
+
+        half + Duration::from_millis(jitter_millis)
+    }
+
+    /// Wait until a connection to the host is available.
+    fn acquire(&self, host: &str) -> HostPermit<'_> {
+        let mut connections = self.lock_connections();
+
+        while connections.get(host).copied().unwrap_or_default() >= self.max_connections_per_host {
+            connections = self
+                .connection_released
+                .wait(connections)
+                .unwrap_or_else(PoisonError::into_inner);
+        }
+
+        *connections.entry(host.to_string()).or_default() += 1;
+
+        HostPermit {
+            http_client: self,
+            host: host.to_string(),
+        }
+    }
+
+    fn lock_connections(&self) -> MutexGuard<'_, HashMap<String, usize>> {
+        self.connections
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+    }
+}
+
+impl<'c> HttpResponse<'c> {
+    /// Deserialize the JSON body of the response.
+    pub fn json<T: DeserializeOwned>(self) -> Result<T, Error> {
+        Ok(serde_json::from_reader(BufReader::new(self))?)
+    }
+}
+
+impl<'c> Read for HttpResponse<'c> {
+    fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+        if let Some(deadline) = self.deadline {
+            if Instant::now() >= deadline {
+                return Err(std::io::Error::new(
+                    std::io::ErrorKind::TimedOut,
+                    "The request exceeded its timeout",
+                ));
+            }
+        }
+
+        self.response.read(buf)
+    }
+}
+
+impl<'c> Deref for HttpResponse<'c> {
+    type Target = Response;
+
+    fn deref(&self) -> &Self::Target {
+        &self.response
+    }
+}
+
+impl<'c> DerefMut for HttpResponse<'c> {
+    fn deref_mut(&mut self) -> &mut Self::Target {
+        &mut self.response
+    }
+}
+
+impl<'c> Drop for HostPermit<'c> {
+    fn drop(&mut self) {
+        let mut connections = self.http_client.lock_connections();
+
+        if let Some(number_of_connections) = connections.get_mut(&self.host) {
+            *number_of_connections = number_of_connections.saturating_sub(1);
+        }
+
+        self.http_client.connection_released.notify_all();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::test_utils;
+    use std::io::Write;
+    use std::sync::mpsc;
+
+    #[test]
+    fn retry_on_server_error() {
+        let _error_mock = mockito::mock("GET", "/flaky")
+            .with_status(503)
+            .expect(2)
+            .create();
+        let _ok_mock = mockito::mock("GET", "/flaky").with_body("ok").create();
+
+        let http_client = test_utils::create_http_client();
+
+        let response = http_client
+            .send(http_client.get(&format!("{}/flaky", mockito::server_url())))
+            .unwrap();
+
+        assert_eq!(response.status(), StatusCode::OK);
+    }
+
+    #[test]
+    fn return_last_response_after_retries() {
+        let _mock = mockito::mock("GET", "/unavailable")
+            .with_status(503)
+            .expect(3)
+            .create();
+
+        let http_client = test_utils::create_http_client();
+
+        let response = http_client
+            .send(http_client.get(&format!("{}/unavailable", mockito::server_url())))
+            .unwrap();
+
+        assert_eq!(response.status(), StatusCode::SERVICE_UNAVAILABLE);
+    }
+
+    #[test]
+    fn no_retry_on_client_error() {
+        let _mock = mockito::mock("GET", "/not-found")
+            .with_status(404)
+            .expect(1)
+            .create();
+
+        let http_client = test_utils::create_http_client();
+
+        let response = http_client
+            .send(http_client.get(&format!("{}/not-found", mockito::server_url())))
+            .unwrap();
+
+        assert_eq!(response.status(), StatusCode::NOT_FOUND);
+    }
+
+    #[test]
+    fn honor_retry_after() {
+        let _error_mock = mockito::mock("GET", "/throttled")
+            .with_status(429)
+            .with_header("retry-after", "1")
+            .expect(1)
+            .create();
+        let _ok_mock = mockito::mock("GET", "/throttled").create();
+
+        let mut http_settings = test_utils::create_http_settings();
+        http_settings.max_backoff_milliseconds = 2000;
+        let http_client = HttpClient::new(&http_settings).unwrap();
+
+        let start = std::time::Instant::now();
+        let response = http_client
+            .send(http_client.get(&format!("{}/throttled", mockito::server_url())))
+            .unwrap();
+
+        assert_eq!(response.status(), StatusCode::OK);
+        assert!(start.elapsed() >= Duration::from_secs(1));
+    }
+
+    #[test]
+    fn no_retry_if_retry_after_exceeds_max_backoff() {
+        let mock = mockito::mock("GET", "/long-throttled")
+            .with_status(429)
+            .with_header("retry-after", "86400")
+            .expect(1)
+            .create();
+
+        let http_client = test_utils::create_http_client();
+
+        let start = std::time::Instant::now();
+        let response = http_client
+            .send(http_client.get(&format!("{}/long-throttled", mockito::server_url())))
+            .unwrap();
+
+        assert_eq!(response.status(), StatusCode::TOO_MANY_REQUESTS);
+        assert!(start.elapsed() < Duration::from_secs(5));
+        mock.assert();
+    }
+
+    #[test]
+    fn backoff_grows_exponentially_with_jitter() {
+        let mut http_settings = test_utils::create_http_settings();
+        http_settings.initial_backoff_milliseconds = 100;
+        http_settings.max_backoff_milliseconds = 1000;
+        let http_client = HttpClient::new(&http_settings).unwrap();
+
+        for (attempt, backoff) in [
+            (0, 100),
+            (1, 200),
+            (2, 400),
+            (3, 800),
+            (4, 1000),
+            (10, 1000),
+        ] {
+            let delay = http_client.backoff(attempt);
+            assert!(delay >= Duration::from_millis(backoff / 2));
+            assert!(delay <= Duration::from_millis(backoff));
+        }
+    }
+
+    #[test]
+    fn limit_connections_per_host() {
+        let mut http_settings = test_utils::create_http_settings();
+        http_settings.max_connections_per_host = 1;
+        let http_client = HttpClient::new(&http_settings).unwrap();
+
+        let permit = http_client.acquire("host");
+        let other_permit = http_client.acquire("other-host");
+
+        let (sender, receiver) = mpsc::channel();
+        std::thread::scope(|scope| {
+            scope.spawn(|| {
+                let _permit = http_client.acquire("host");
+                sender.send(()).unwrap();
+            });
+
+            assert!(receiver.recv_timeout(Duration::from_millis(100)).is_err());
+
+            drop(permit);
+
+            assert!(receiver.recv_timeout(Duration::from_secs(5)).is_ok());
+        });
+
+        drop(other_permit);
+    }
+
+    #[test]
+    fn detect_stalled_transfer() {
+        let url = serve_slowly(Duration::from_secs(3));
+
+        let mut http_settings = test_utils::create_http_settings();
+        http_settings.read_timeout_seconds = 1;
+        let http_client = HttpClient::new(&http_settings).unwrap();
+
+        let start = Instant::now();
+        let mut response = http_client.send(http_client.get(&url)).unwrap();
+        let mut body = Vec::new();
+
+        assert!(response.read_to_end(&mut body).is_err());
+        assert!(start.elapsed() < Duration::from_secs(3));
+    }
+
+    #[test]
+    fn limit_total_request_time() {
+        let url = serve_slowly(Duration::from_millis(200));
+
+        let mut http_settings = test_utils::create_http_settings();
+        http_settings.timeout_seconds = Some(1);
+        let http_client = HttpClient::new(&http_settings).unwrap();
+
+        let mut response = http_client.send(http_client.get(&url)).unwrap();
+        let mut body = Vec::new();
+
+        assert_eq!(
+            response.read_to_end(&mut body).unwrap_err().kind(),
+            std::io::ErrorKind::TimedOut
+        );
+    }
+
+    /// Serve a single response whose body arrives a byte per `interval`, which takes ten intervals in total.
+    fn serve_slowly(interval: Duration) -> String {
+        let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+        let url = format!("http://{}/slow", listener.local_addr().unwrap());
+
+        std::thread::spawn(move || {
+            let (mut stream, _) = listener.accept().unwrap();
+
+            let mut request = Vec::new();
+            let mut buf = [0; 1024];
+            while !request.ends_with(b"\r\n\r\n") {
+                match stream.read(&mut buf) {
+                    Ok(0) | Err(_) => return,
+                    Ok(n) => request.extend_from_slice(&buf[..n]),
+                }
+            }
+
+            let _ = stream.write_all(b"HTTP/1.1 200 OK\r\nContent-Length: 10\r\n\r\n");
+            for _ in 0..10 {
+                let _ = stream.write_all(b"x");
+                let _ = stream.flush();
+                std::thread::sleep(interval);
+            }
+        });
+
+        url
+    }
+}
@@ -1,25 +1,53 @@
+// `failure_derive` expands to impl blocks inside anonymous constants.
+#![allow(non_local_definitions)]
+
+use std::collections::{HashMap, VecDeque};
 use std::fs::File;
+use std::io::{BufWriter, Write};
 use std::path::{Path, PathBuf};
+use std::process::ExitCode;
+use std::sync::mpsc::{self, Receiver};
+use std::sync::Mutex;
+use std::time::Instant;
 
 use clap::{crate_authors, crate_description, crate_version, Arg, Command};
 use failure::Error;
 use log::{error, info, trace, warn};
-use pangaea::PangaeaSearchResult;
 use simplelog::{CombinedLogger, SharedLogger, TermLogger, WriteLogger};
 
 use settings::Settings;
 
-use crate::abcd::{AbcdFields, AbcdParser, ArchiveReader};
-use crate::file_downloader::FileDownloader;
-use crate::pangaea::PangaeaSearchResultEntry;
+use crate::abcd::{AbcdFields, AbcdParser, AbcdParserError, ArchiveReader};
+use crate::archive_summary::{ArchiveSummary, ValidationError};
+use crate::crawl_report::{
+    CrawlReport, CrawlReportError, DatasetReport, DownloadStatus, SchemaMigration,
+};
+use crate::dataset_source::{
+    ArchiveLocation, DatasetDescription, DatasetSource, LocalSource, PangaeaSource,
+};
+use crate::export::{
+    CsvWriter, DwcArchiveWriter, DwcMapping, GeoJsonWriter, GeoPackageWriter, ParquetWriter,
+};
+use crate::file_downloader::DownloadInfo;
+use crate::http_client::HttpClient;
+use crate::notifier::Notifier;
+use crate::pipeline::{ArchiveMessage, ArchiveSource, DatasetJob, DatasetWorker, JobQueue};
 use crate::settings::TerminologyServiceSettings;
-use crate::storage::DatabaseSink;
-use crate::vat_type::VatType;
+use crate::sink::Sink;
+use crate::storage::{DatabaseSink, MigrationCheckError};
 
 mod abcd;
+mod archive_summary;
+mod crawl_report;
+mod dataset_source;
+mod export;
 mod file_downloader;
+mod http_client;
+mod notifier;
 mod pangaea;
+mod pipeline;
 mod settings;
+mod sink;
 mod storage;
 #[cfg(test)]
 mod test_utils;
@@ -27,251 +55,896 @@ mod vat_type;
 
 #[derive(Debug)]
 pub enum Cmd {
-    All,
-    FileToCsv(PathBuf),
+    Crawl {
+        full: bool,
+        /// A local directory or manifest of archives that replaces the Pangaea search.
+        source: Option<PathBuf>,
+        /// Only print the datasets that would be crawled.
+        dry_run: bool,
+        format: ExportFormat,
+        output: Option<PathBuf>,
+    },
+    Convert {
+        archive: PathBuf,
+        format: ExportFormat,
+        output: Option<PathBuf>,
+    },
+    Validate {
+        archives: Vec<PathBuf>,
+    },
+    Inspect {
+        archive: PathBuf,
+    },
+    Migrate {
+        force: bool,
+    },
+    ListDatasets {
+        json: bool,
+    },
+    Rollback {
+        generation: Option<String>,
+    },
+}
+
+/// The backends that crawled or converted archives can be written to.
+#[derive(Clone, Copy, Debug)]
+pub enum ExportFormat {
+    /// The tables of the PostgreSQL database
+    Postgres,
+    Csv,
+    GeoPackage,
+    GeoJson,
+    /// Newline-delimited GeoJSON
+    NdJson,
+    Parquet,
+    /// Darwin Core Archive
+    DwcArchive,
+}
+
+impl ExportFormat {
+    const NAMES: [&'static str; 7] = [
+        "postgres", "csv", "gpkg", "geojson", "ndjson", "parquet", "dwca",
+    ];
+
+    fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "postgres" => Some(ExportFormat::Postgres),
+            "csv" => Some(ExportFormat::Csv),
+            "gpkg" => Some(ExportFormat::GeoPackage),
+            "geojson" => Some(ExportFormat::GeoJson),
+            "ndjson" => Some(ExportFormat::NdJson),
+            "parquet" => Some(ExportFormat::Parquet),
+            "dwca" => Some(ExportFormat::DwcArchive),
+            _ => None,
+        }
+    }
 }
 
-fn main() -> Result<(), Error> {
-    let (cmd, settings) = initialize_settings().expect("Unable to load settings file.");
+/// The command failed, e.g., because a file or the database was not accessible.
+const EXIT_ERROR: u8 = 1;
+/// The command completed, but found problems, e.g., invalid archives or an incomplete crawl.
+/// Usage errors exit with code 2.
+const EXIT_PROBLEMS_FOUND: u8 = 3;
+
+fn main() -> ExitCode {
+    let (cmd, settings) = match initialize_settings() {
+        Ok(cmd_and_settings) => cmd_and_settings,
+        Err(e) => {
+            eprintln!("Unable to load settings file: {}", e);
+            return ExitCode::from(EXIT_ERROR);
+        }
+    };
+
+    if let Err(e) = initialize_logger(Path::new(&settings.general.log_file), &settings) {
+        eprintln!("Unable to initialize logger: {}", e);
+        return ExitCode::from(EXIT_ERROR);
+    }
 
-    initialize_logger(Path::new(&settings.general.log_file), &settings)
-        .expect("Unable to initialize logger.");
+    let fields_files: Vec<&Path> = std::iter::once(&settings.abcd.fields_file)
+        .chain(&settings.abcd.extension_fields_files)
+        .map(Path::new)
+        .collect();
 
-    let abcd_fields = match AbcdFields::from_path(Path::new(&settings.abcd.fields_file)) {
+    let abcd_fields = match AbcdFields::from_paths(&fields_files) {
         Ok(fields) => fields,
         Err(e) => {
             error!("Unable to load ABCD file: {}", e);
-            return Err(e); // stop program
+            return ExitCode::from(EXIT_ERROR); // stop program
         }
     };
 
-    match cmd {
-        Cmd::All => main_all(&settings, &abcd_fields),
-        Cmd::FileToCsv(file) => main_single_file(&settings, &abcd_fields, &file),
+    let result = match cmd {
+        Cmd::Crawl {
+            full,
+            source,
+            dry_run,
+            format,
+            output,
+        } => main_crawl(
+            &settings,
+            &abcd_fields,
+            full,
+            source.as_deref(),
+            dry_run,
+            format,
+            output.as_deref(),
+        ),
+        Cmd::Convert {
+            archive,
+            format,
+            output,
+        } => main_convert(&settings, &abcd_fields, &archive, format, output.as_deref()),
+        Cmd::Validate { archives } => main_validate(&settings, &abcd_fields, &archives),
+        Cmd::Inspect { archive } => main_inspect(&settings, &abcd_fields, &archive),
+        Cmd::Migrate { force } => main_migrate(&settings, &abcd_fields, force),
+        Cmd::ListDatasets { json } => main_list_datasets(&settings, json),
+        Cmd::Rollback { generation } => main_rollback(&settings, &abcd_fields, generation),
+    };
+
+    match result {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(e) => {
+            error!("{}", e);
+            ExitCode::from(exit_code(&e))
+        }
     }
 }
 
-fn main_single_file(
+/// Distinguish problems that a command found from errors that kept it from completing.
+fn exit_code(error: &Error) -> u8 {
+    if error.downcast_ref::<CrawlReportError>().is_some()
+        || error.downcast_ref::<MigrationCheckError>().is_some()
+        || error.downcast_ref::<ValidationError>().is_some()
+    {
+        EXIT_PROBLEMS_FOUND
+    } else {
+        EXIT_ERROR
+    }
+}
+
+/// Swap a previous generation of tables back in.
+fn main_rollback(
     settings: &Settings,
     abcd_fields: &AbcdFields,
-    file: &Path,
+    generation: Option<String>,
 ) -> Result<(), Error> {
-    let mut archive_reader = ArchiveReader::from_path(file)?;
+    let mut database_sink = DatabaseSink::open(&settings.database, abcd_fields)?;
 
-    let mut abcd_parser = AbcdParser::new(&settings.abcd, abcd_fields);
+    info!(
+        "Available generations: {}",
+        database_sink.schema_generations()?.join(", ")
+    );
 
-    let mut writer = csv::WriterBuilder::new()
-        .delimiter(b',')
-        .has_headers(true)
-        .from_writer(std::io::stdout());
+    let generation = database_sink.rollback(generation.as_deref())?;
+    info!("Restored generation {}.", generation);
 
-    // header
-    let field_names: Vec<&str> = abcd_fields
-        .into_iter()
-        .map(|field| field.name.as_str())
-        .collect();
-    writer.write_record(&field_names)?;
+    Ok(())
+}
 
-    for xml_bytes_result in archive_reader.bytes_iter() {
-        let xml_bytes = match xml_bytes_result {
-            Ok(bytes) => bytes,
-            Err(e) => {
-                warn!("Unable to read file from zip archive: {}", e);
-                continue;
-            }
-        };
+/// Replace the persistent tables with the temporary tables of a previous crawl.
+fn main_migrate(settings: &Settings, abcd_fields: &AbcdFields, force: bool) -> Result<(), Error> {
+    let mut database_sink = DatabaseSink::open(&settings.database, abcd_fields)?;
+
+    if force {
+        warn!("Skipping the migration checks.");
+    } else {
+        database_sink.check_migration(&settings.migration)?;
+    }
+
+    database_sink.migrate_schema()?;
+    info!("Schema migration complete.");
+
+    Ok(())
+}
+
+/// Print the datasets of the Pangaea search as CSV or JSON.
+fn main_list_datasets(settings: &Settings, json: bool) -> Result<(), Error> {
+    let http_client = HttpClient::new(&settings.http)?;
+    let datasets = PangaeaSource::new(&settings.pangaea, &http_client).datasets()?;
+
+    print_datasets(&datasets, json)
+}
+
+fn print_datasets(datasets: &[DatasetDescription], json: bool) -> Result<(), Error> {
+    let stdout = std::io::stdout();
+    if json {
+        let datasets: Vec<serde_json::Value> = datasets
+            .iter()
+            .map(|dataset| {
+                serde_json::json!({
+                    "id": dataset.id,
+                    "publisher": dataset.publisher,
+                    "archive": dataset.archive.to_string(),
+                    "landing_page": dataset.landing_page,
+                })
+            })
+            .collect();
+        serde_json::to_writer_pretty(stdout.lock(), &datasets)?;
+        println!();
+    } else {
+        let mut writer = csv::Writer::from_writer(stdout.lock());
+        writer.write_record(["id", "publisher", "archive", "landing_page"])?;
+        for dataset in datasets {
+            writer.write_record([
+                dataset.id.as_str(),
+                dataset.publisher.as_str(),
+                &dataset.archive.to_string(),
+                dataset.landing_page.as_deref().unwrap_or_default(),
+            ])?;
+        }
+        writer.flush()?;
+    }
 
-        let abcd_data = match abcd_parser.parse("", &file.to_string_lossy(), "", "", &xml_bytes) {
-            Ok(data) => data,
+    Ok(())
+}
+
+/// Check archives against the field specification and print their problems.
+fn main_validate(
+    settings: &Settings,
+    abcd_fields: &AbcdFields,
+    archives: &[PathBuf],
+) -> Result<(), Error> {
+    let mut invalid_archives = 0;
+
+    for archive in archives {
+        let summary = match ArchiveSummary::from_path(archive, &settings.abcd, abcd_fields) {
+            Ok(summary) => summary,
             Err(e) => {
-                warn!("Unable to retrieve ABCD data: {}", e);
+                println!("{}: {}", archive.display(), e);
+                invalid_archives += 1;
                 continue;
             }
         };
 
-        trace!("{:?}", abcd_data.dataset);
-
-        for row in abcd_data.units {
-            for field in abcd_fields {
-                match row.get(&field.name) {
-                    Some(VatType::Textual(value)) => {
-                        writer.write_field(value)?;
-                    }
-                    Some(VatType::Numeric(value)) => {
-                        writer.write_field(value.to_string())?;
-                    }
-                    None => {
-                        writer.write_field(&[])?;
+        match summary.validate(abcd_fields) {
+            Ok(()) => println!("{}: valid", archive.display()),
+            Err(e) => {
+                println!("{}: {}", archive.display(), e);
+                for file in &summary.files {
+                    for problem in file.problems(abcd_fields) {
+                        println!("  {}: {}", file.name, problem);
                     }
                 }
+                invalid_archives += 1;
             }
+        }
+    }
 
-            // finish line
-            writer.write_record(None::<&[u8]>)?;
+    if invalid_archives > 0 {
+        return Err(ValidationError::InvalidArchives {
+            invalid_archives,
+            archives: archives.len(),
         }
+        .into());
     }
 
     Ok(())
 }
 
-fn main_all(settings: &Settings, abcd_fields: &AbcdFields) -> Result<(), Error> {
-    let mut database_sink = match DatabaseSink::new(&settings.database, abcd_fields) {
-        Ok(sink) => sink,
-        Err(e) => {
-            error!("Unable to create storage sink: {}", e);
-            return Err(e); // stop program
-        }
-    };
-
-    let datasets = match PangaeaSearchResult::retrieve_all_entries(&settings.pangaea) {
-        Ok(search_entries) => search_entries,
-        Err(e) => {
-            error!("Unable to download dataset metadata from Pangaea: {}", e);
-            return Err(e); // stop program
-        }
-    };
+/// Print the dataset metadata and field coverage of an archive.
+fn main_inspect(
+    settings: &Settings,
+    abcd_fields: &AbcdFields,
+    archive: &Path,
+) -> Result<(), Error> {
+    let summary = ArchiveSummary::from_path(archive, &settings.abcd, abcd_fields)?;
 
-    if let Err(e) = process_datasets(settings, abcd_fields, &mut database_sink, &datasets) {
-        error!("Error processing datasets: {}", e);
-    };
+    print!("{}", summary);
 
     Ok(())
 }
 
-fn process_datasets(
+/// Convert an archive, or a directory or manifest of local archives, into the sink of an output format.
+fn main_convert(
     settings: &Settings,
     abcd_fields: &AbcdFields,
-    database_sink: &mut DatabaseSink<'_>,
-    datasets: &[PangaeaSearchResultEntry],
+    archive: &Path,
+    format: ExportFormat,
+    output: Option<&Path>,
 ) -> Result<(), Error> {
-    let temp_dir = tempfile::tempdir()?;
-    let storage_dir = Path::new(&settings.abcd.storage_dir);
+    let datasets = LocalSource::new(archive).datasets()?;
 
-    create_or_check_for_directory(&storage_dir);
+    let mut sink = create_sink(settings, abcd_fields, format, output)?;
 
-    let mut abcd_parser = AbcdParser::new(&settings.abcd, abcd_fields);
+    sink.begin()?;
 
-    for dataset in datasets
-        .iter()
-        .skip(
-            settings
-                .debug
-                .dataset_start
-                .filter(|_| settings.general.debug)
-                .unwrap_or(std::usize::MIN),
-        )
-        .take(
-            settings
-                .debug
-                .dataset_limit
-                .filter(|_| settings.general.debug)
-                .unwrap_or(std::usize::MAX),
-        )
-    {
-        let file_name = dataset
-            .id()
-            .chars()
-            .map(|c| match c {
-                'a'..='z' | 'A'..='Z' | '-' => c,
-                _ => '_',
-            })
-            .collect::<String>();
-        let temp_file_path = temp_dir.path().join(&file_name).with_extension("zip");
-        let storage_file_path = storage_dir.join(&file_name).with_extension("zip");
-
-        if let Err(e) = FileDownloader::from_url(dataset.download_url()).to_path(&temp_file_path) {
-            warn!(
-                "Unable to download file {url} to {path}: {error}",
-                url = dataset.download_url(),
-                path = temp_file_path.display(),
-                error = e,
-            );
-
-            let recovery_file_path = storage_file_path.as_path();
-            match std::fs::copy(recovery_file_path, &temp_file_path) {
-                Ok(_) => info!("Recovered file {file}", file = file_name),
-                Err(e) => {
-                    warn!(
-                        "Recovery of file {file} failed: {error}",
-                        file = file_name,
-                        error = e,
-                    );
+    convert_archives(settings, abcd_fields, &datasets, sink.as_mut())?;
 
-                    continue; // skip processing this dataset
-                }
-            };
+    if let Err(e) = sink.check_migration(&settings.migration) {
+        if let Err(e) = sink.abort() {
+            warn!("Unable to abort the conversion: {}", e);
         }
+        return Err(e);
+    }
 
-        trace!("Temp file: {}", temp_file_path.display());
-        info!(
-            "Processing `{}` @ `{}` ({})",
-            dataset.id(),
-            dataset.publisher(),
-            dataset.download_url(),
-        );
+    sink.finish()
+}
 
-        let landing_page_url: String =
-            propose_landing_page(&settings.terminology_service, dataset.download_url());
+/// Create the sink of an output format.
+/// GeoPackages, Parquet files and Darwin Core Archives can only be written to files,
+/// while the other file formats are written to stdout if no `output` is given.
+fn create_sink<'s>(
+    settings: &'s Settings,
+    abcd_fields: &'s AbcdFields,
+    format: ExportFormat,
+    output: Option<&Path>,
+) -> Result<Box<dyn Sink + 's>, Error> {
+    let output_writer = || -> Result<Box<dyn Write>, Error> {
+        Ok(match output {
+            Some(path) => Box::new(BufWriter::new(File::create(path)?)),
+            None => Box::new(std::io::stdout()),
+        })
+    };
+    let output_file = |format_name: &str| {
+        output.ok_or_else(|| {
+            failure::err_msg(format!(
+                "The {} format requires an output file",
+                format_name
+            ))
+        })
+    };
+
+    Ok(match format {
+        ExportFormat::Postgres => Box::new(
+            DatabaseSink::open(&settings.database, abcd_fields)
+                .map_err(|e| failure::err_msg(format!("Unable to create storage sink: {}", e)))?,
+        ),
+        ExportFormat::Csv => Box::new(CsvWriter::new(output_writer()?, abcd_fields)?),
+        ExportFormat::GeoPackage => Box::new(GeoPackageWriter::create(
+            output_file("GeoPackage")?,
+            &settings.export,
+            abcd_fields,
+        )?),
+        ExportFormat::GeoJson | ExportFormat::NdJson => Box::new(GeoJsonWriter::new(
+            output_writer()?,
+            &settings.export,
+            abcd_fields,
+            matches!(format, ExportFormat::NdJson),
+        )?),
+        ExportFormat::Parquet => Box::new(ParquetWriter::new(
+            BufWriter::new(File::create(output_file("Parquet")?)?),
+            &settings.export,
+            abcd_fields,
+        )?),
+        ExportFormat::DwcArchive => Box::new(DwcArchiveWriter::create(
+            output_file("Darwin Core Archive")?,
+            DwcMapping::from_path(Path::new(&settings.export.dwca.mapping_file))?,
+        )?),
+    })
+}
+
+/// Parse the files of all archives and insert each dataset with its units into the `sink`.
+/// Unreadable archives and files are skipped.
+fn convert_archives(
+    settings: &Settings,
+    abcd_fields: &AbcdFields,
+    datasets: &[DatasetDescription],
+    sink: &mut dyn Sink,
+) -> Result<(), Error> {
+    let mut abcd_parser = AbcdParser::new(&settings.abcd, abcd_fields);
 
-        let mut archive_reader = match ArchiveReader::from_path(&temp_file_path) {
+    for dataset in datasets {
+        let path = match &dataset.archive {
+            ArchiveLocation::Local(path) => path,
+            ArchiveLocation::Remote(url) => {
+                warn!("Unable to convert remote archive {}", url);
+                continue;
+            }
+        };
+
+        let mut archive_reader = match ArchiveReader::from_path(path) {
             Ok(reader) => reader,
             Err(e) => {
-                warn!("Unable to read dataset archive: {}", e);
+                warn!("Unable to read zip archive {}: {}", path.display(), e);
                 continue;
             }
         };
 
-        let mut all_inserts_successful = true;
-
-        for xml_bytes_result in archive_reader.bytes_iter() {
-            let xml_bytes = match xml_bytes_result {
-                Ok(bytes) => bytes,
+        for index in 0..archive_reader.len() {
+            let xml_reader = match archive_reader.file_reader(index) {
+                Ok(reader) => reader,
                 Err(e) => {
                     warn!("Unable to read file from zip archive: {}", e);
-                    all_inserts_successful = false;
                     continue;
                 }
             };
 
-            let abcd_data = match abcd_parser.parse(
-                dataset.id(),
-                dataset.download_url(),
-                &landing_page_url,
-                dataset.publisher(),
-                &xml_bytes,
+            let abcd_units = match abcd_parser.parse_stream(
+                &dataset.id,
+                &dataset.dataset_path(),
+                dataset.landing_page.as_deref().unwrap_or_default(),
+                &dataset.publisher,
+                xml_reader,
             ) {
-                Ok(data) => data,
+                Ok(units) => units,
                 Err(e) => {
                     warn!("Unable to retrieve ABCD data: {}", e);
-                    all_inserts_successful = false;
                     continue;
                 }
             };
 
-            trace!("{:?}", abcd_data.dataset);
+            trace!("{:?}", abcd_units.dataset().dataset);
 
-            match database_sink.insert_dataset(&abcd_data) {
-                Ok(_) => (),
-                Err(e) => {
-                    warn!("Unable to insert dataset into storage: {}", e);
-                    all_inserts_successful = false;
+            let abcd_data = abcd_units.dataset().clone();
+            let mut units = abcd_units.map(|unit| unit.map_err(Error::from));
+            if let Err(e) = sink.insert_dataset(&abcd_data, &mut units) {
+                warn!("Unable to insert dataset into sink: {}", e);
+            }
+        }
+    }
+
+    Ok(())
+}
+
+/// Crawl all datasets of the Pangaea search, or the archives of a local `source`, and replace the persistent tables.
+fn main_crawl(
+    settings: &Settings,
+    abcd_fields: &AbcdFields,
+    full: bool,
+    source: Option<&Path>,
+    dry_run: bool,
+    format: ExportFormat,
+    output: Option<&Path>,
+) -> Result<(), Error> {
+    let http_client = match HttpClient::new(&settings.http) {
+        Ok(http_client) => http_client,
+        Err(e) => {
+            let error = failure::err_msg(format!("Unable to create HTTP client: {}", e));
+            match HttpClient::fallback() {
+                Ok(http_client) => notify_failure(settings, &http_client, &error),
+                Err(e) => warn!("Unable to send notification: {}", e),
+            }
+            return Err(error);
+        }
+    };
+
+    let dataset_source: Box<dyn DatasetSource> = match source {
+        Some(path) => Box::new(LocalSource::new(path)),
+        None => Box::new(PangaeaSource::new(&settings.pangaea, &http_client)),
+    };
+
+    if dry_run {
+        let datasets = dataset_source.datasets()?;
+        return print_datasets(debug_selection(settings, &datasets), false);
+    }
+
+    let report = create_sink(settings, abcd_fields, format, output)
+        .and_then(|mut sink| {
+            process_datasets(
+                settings,
+                abcd_fields,
+                &http_client,
+                sink.as_mut(),
+                dataset_source.as_ref(),
+                full,
+            )
+        })
+        .map_err(|e| failure::err_msg(format!("Error processing datasets: {}", e)));
+    let report = match report {
+        Ok(report) => report,
+        Err(e) => {
+            notify_failure(settings, &http_client, &e);
+            return Err(e);
+        }
+    };
+
+    info!("{}", report);
+
+    if let Err(e) = report.write_json(Path::new(&settings.report.file)) {
+        warn!("Unable to write crawl report: {}", e);
+    }
+
+    let notifier = Notifier::new(&http_client, &settings.notifications);
+    if notifier.is_enabled() {
+        if let Err(e) = notifier.notify(&report) {
+            warn!("Unable to send notification: {}", e);
+        }
+    }
+
+    report.check_failure_rate(settings.report.max_failure_rate)?;
+
+    match &report.schema_migration {
+        SchemaMigration::Aborted { reason } => Err(CrawlReportError::MigrationAborted {
+            reason: reason.clone(),
+        }
+        .into()),
+        SchemaMigration::Failed { error } => Err(failure::err_msg(format!(
+            "Unable to migrate schema: {}",
+            error
+        ))),
+        _ => Ok(()),
+    }
+}
+
+/// Post a crawl that failed before its report was complete to the webhook.
+fn notify_failure(settings: &Settings, http_client: &HttpClient, error: &Error) {
+    let notifier = Notifier::new(http_client, &settings.notifications);
+    if notifier.is_enabled() {
+        if let Err(e) = notifier.notify_failure(error) {
+            warn!("Unable to send notification: {}", e);
+        }
+    }
+}
+
+/// Retrieve and parse the datasets of a source with a pool of `workers` and insert them into the sink.
+/// A single writer inserts the datasets in their original order, so surrogate keys are deterministic.
+/// Unless the crawl is `full`, datasets with unchanged archives are carried over from the sink's previous crawl.
+/// The sink's run is finished if the crawl is complete and aborted otherwise.
+fn process_datasets(
+    settings: &Settings,
+    abcd_fields: &AbcdFields,
+    http_client: &HttpClient,
+    sink: &mut dyn Sink,
+    dataset_source: &dyn DatasetSource,
+    full: bool,
+) -> Result<CrawlReport, Error> {
+    let datasets = dataset_source
+        .datasets()
+        .map_err(|e| failure::err_msg(format!("Unable to retrieve the datasets: {}", e)))?;
+
+    sink.begin()?;
+
+    let previous_states = if full {
+        HashMap::new()
+    } else {
+        match sink.previous_dataset_states() {
+            Ok(states) => states,
+            Err(e) => {
+                warn!(
+                    "Unable to retrieve previous dataset states, crawling all datasets: {}",
+                    e
+                );
+                HashMap::new()
+            }
+        }
+    };
+    info!(
+        "Found {} datasets of the previous crawl that can be carried over.",
+        previous_states.len()
+    );
+    let temp_dir = tempfile::tempdir()?;
+    let storage_dir = Path::new(&settings.abcd.storage_dir);
+
+    create_or_check_for_directory(&storage_dir);
+
+    let workers = settings.general.workers.max(1);
+    let crawl_start = Instant::now();
+    let mut report = CrawlReport::default();
+    let mut abcd_parser = AbcdParser::new(&settings.abcd, abcd_fields);
+
+    let mut jobs = debug_selection(settings, &datasets).iter().map(|dataset| {
+        let file_name = DatasetJob::archive_file_name(&dataset.id);
+
+        DatasetJob {
+            id: dataset.id.clone(),
+            publisher: dataset.publisher.clone(),
+            archive: dataset.archive.clone(),
+            dataset_path: dataset.dataset_path(),
+            metadata: dataset.metadata.clone(),
+            landing_page_url: dataset
+                .landing_page
+                .clone()
+                .or_else(|| {
+                    dataset
+                        .archive_url()
+                        .map(|url| propose_landing_page(&settings.terminology_service, url))
+                })
+                .unwrap_or_default(),
+            temp_file_path: temp_dir.path().join(&file_name).with_extension("zip"),
+            storage_file_path: storage_dir.join(&file_name).with_extension("zip"),
+            previous_state: previous_states.get(&dataset.id).cloned(),
+        }
+    });
+
+    let (job_sender, job_receiver) = mpsc::channel();
+    let job_queue: JobQueue = Mutex::new(job_receiver);
+
+    std::thread::scope(|scope| {
+        for _ in 0..workers {
+            scope.spawn(|| {
+                DatasetWorker::new(
+                    http_client,
+                    &settings.abcd,
+                    abcd_fields,
+                    settings.database.unit_batch_size,
+                )
+                .run(&job_queue)
+            });
+        }
+
+        // at most `workers` datasets are processed ahead of the writer
+        let mut pending_jobs: VecDeque<(DatasetJob, Receiver<ArchiveMessage>)> = VecDeque::new();
+        loop {
+            while pending_jobs.len() < workers {
+                let job = match jobs.next() {
+                    Some(job) => job,
+                    None => break,
+                };
+                let (message_sender, message_receiver) = mpsc::channel();
+                if job_sender.send((job.clone(), message_sender)).is_err() {
+                    break; // all workers stopped
                 }
+                pending_jobs.push_back((job, message_receiver));
+            }
+
+            let (job, messages) = match pending_jobs.pop_front() {
+                Some(pending_job) => pending_job,
+                None => break,
             };
+
+            report
+                .datasets
+                .push(store_dataset(sink, &mut abcd_parser, &job, &messages));
+        }
+
+        drop(job_sender); // let the workers finish
+    });
+
+    report.set_duration(crawl_start.elapsed());
+
+    let migration_check = report
+        .check_failure_rate(settings.report.max_failure_rate)
+        .map_err(Error::from)
+        .and_then(|_| sink.check_migration(&settings.migration));
+    if let Err(e) = migration_check {
+        warn!(
+            "Keeping the previous tables, since the crawl seems incomplete: {}",
+            e
+        );
+        if let Err(e) = sink.abort() {
+            warn!("Unable to abort the crawl: {}", e);
         }
+        report.schema_migration = SchemaMigration::Aborted {
+            reason: e.to_string(),
+        };
+        return Ok(report);
+    }
 
-        if all_inserts_successful && archive_reader.len() > 0 {
-            if let Err(e) = std::fs::copy(&temp_file_path, storage_file_path) {
-                warn!("Unable to store ABCD file: {}", e);
+    report.schema_migration = match sink.finish() {
+        Ok(_) => {
+            info!("Schema migration complete.");
+            SchemaMigration::Completed
+        }
+        Err(e) => {
+            warn!("Unable to migrate schema: {}", e);
+            SchemaMigration::Failed {
+                error: e.to_string(),
             }
         }
+    };
+
+    Ok(report)
+}
+
+/// Restrict the datasets to the configured range in debug mode.
+fn debug_selection<'d>(
+    settings: &Settings,
+    datasets: &'d [DatasetDescription],
+) -> &'d [DatasetDescription] {
+    if !settings.general.debug {
+        return datasets;
     }
 
-    match database_sink.migrate_schema() {
-        Ok(_) => info!("Schema migration complete."),
-        Err(e) => warn!("Unable to migrate schema: {}", e),
+    let start = settings
+        .debug
+        .dataset_start
+        .unwrap_or(usize::MIN)
+        .min(datasets.len());
+    let end = settings
+        .debug
+        .dataset_limit
+        .map_or(datasets.len(), |limit| start.saturating_add(limit))
+        .min(datasets.len());
+
+    &datasets[start..end]
+}
+
+/// Insert the files of a dataset archive as they are sent by a worker.
+/// An unchanged dataset that cannot be carried over is parsed with the `abcd_parser` and inserted instead.
+/// A downloaded archive is kept in the storage directory and its state is recorded if all files were inserted successfully.
+fn store_dataset(
+    sink: &mut dyn Sink,
+    abcd_parser: &mut AbcdParser,
+    job: &DatasetJob,
+    messages: &Receiver<ArchiveMessage>,
+) -> DatasetReport {
+    let mut report = DatasetReport::new(&job.id, &job.archive.to_string());
+    let mut processing_start = None;
+    let mut all_inserts_successful = true;
+    let mut completed_state = None;
+
+    for message in messages {
+        match message {
+            ArchiveMessage::File { dataset, units } => {
+                let mut units = units.iter().map(|unit| unit.map_err(Error::from));
+                match sink.insert_dataset(&dataset, &mut units) {
+                    Ok(number_of_units) => {
+                        trace!("Inserted {} units", number_of_units);
+                        report.inserted_units += number_of_units;
+                    }
+                    Err(e) if e.downcast_ref::<AbcdParserError>().is_some() => {
+                        warn!(
+                            "Unable to retrieve ABCD data of `{}`, skipping file: {}",
+                            job.id, e
+                        );
+                        report.errors.push(e.to_string());
+                        all_inserts_successful = false;
+                    }
+                    Err(e) => {
+                        warn!("Unable to insert dataset into storage: {}", e);
+                        report.errors.push(e.to_string());
+                        all_inserts_successful = false;
+                    }
+                };
+            }
+            ArchiveMessage::Retrieved {
+                source,
+                download_duration,
+            } => {
+                processing_start = Some(Instant::now());
+                report.download_seconds = Some(download_duration.as_secs_f64());
+                report.download_status = match source {
+                    ArchiveSource::Downloaded => DownloadStatus::Fresh,
+                    ArchiveSource::Cached => DownloadStatus::Cached,
+                    ArchiveSource::Recovered => DownloadStatus::Recovered,
+                    ArchiveSource::Local => DownloadStatus::Local,
+                };
+            }
+            ArchiveMessage::DownloadFailed { error } => {
+                report.download_error = Some(error.to_string());
+            }
+            ArchiveMessage::FailedFile { error } => {
+                report.errors.push(error.to_string());
+                all_inserts_successful = false;
+            }
+            ArchiveMessage::FailedArchive { error } => {
+                report.errors.push(error.to_string());
+            }
+            ArchiveMessage::Unchanged { state } => {
+                match sink.carry_over_dataset(&job.id, &job.metadata) {
+                    Ok(number_of_units) => {
+                        trace!("Carried over {} units", number_of_units);
+                        report.inserted_units = number_of_units;
+                        report.carried_over = true;
+                        completed_state = Some(state);
+                    }
+                    Err(e) => {
+                        warn!(
+                            "Unable to carry over dataset `{}`, inserting its archive instead: {}",
+                            job.id, e
+                        );
+                        if insert_archive(sink, abcd_parser, job, &mut report) {
+                            completed_state = Some(state);
+                        } else {
+                            all_inserts_successful = false;
+                        }
+                    }
+                }
+            }
+            ArchiveMessage::Completed {
+                number_of_files,
+                state,
+            } => {
+                report.number_of_files = number_of_files;
+                if number_of_files > 0 {
+                    completed_state = Some(state);
+                }
+            }
+        }
+    }
+
+    if let Some(processing_start) = processing_start {
+        report.processing_seconds = processing_start.elapsed().as_secs_f64();
+    }
+
+    let state = match completed_state {
+        Some(state) if all_inserts_successful => state,
+        _ => return report,
     };
+    report.complete = true;
 
-    Ok(())
+    // local archives need no copy for recovering failed downloads
+    if let ArchiveLocation::Remote(url) = &job.archive {
+        if let Err(e) = store_archive(&job.temp_file_path, &job.storage_file_path) {
+            warn!("Unable to store ABCD file: {}", e);
+        }
+
+        // describe the dataset, so the storage directory can be crawled as a local source
+        let download_info = DownloadInfo {
+            etag: state.etag.clone(),
+            last_modified: state.last_modified.clone(),
+            dataset_id: Some(job.id.clone()),
+            publisher: Some(job.publisher.clone()),
+            url: Some(url.clone()),
+            landing_page: Some(job.landing_page_url.clone()),
+            metadata: job.metadata.clone(),
+        };
+        if let Err(e) = download_info.store_for(&job.storage_file_path) {
+            warn!("Unable to store download info of ABCD file: {}", e);
+        }
+    }
+
+    if let Err(e) = sink.insert_dataset_state(&state) {
+        warn!("Unable to record state of dataset `{}`: {}", job.id, e);
+    }
+
+    report
+}
+
+/// Parse the retrieved archive of a job and insert its files into the `sink`.
+/// Returns whether all files were inserted successfully.
+fn insert_archive(
+    sink: &mut dyn Sink,
+    abcd_parser: &mut AbcdParser,
+    job: &DatasetJob,
+    report: &mut DatasetReport,
+) -> bool {
+    let mut archive_reader = match ArchiveReader::from_path(&job.temp_file_path) {
+        Ok(reader) => reader,
+        Err(e) => {
+            warn!("Unable to read dataset archive: {}", e);
+            report.errors.push(e.to_string());
+            return false;
+        }
+    };
+    report.number_of_files = archive_reader.len();
+
+    let mut all_inserts_successful = archive_reader.len() > 0;
+    for index in 0..archive_reader.len() {
+        let xml_reader = match archive_reader.file_reader(index) {
+            Ok(reader) => reader,
+            Err(e) => {
+                warn!("Unable to read file from zip archive: {}", e);
+                report.errors.push(e.to_string());
+                all_inserts_successful = false;
+                continue;
+            }
+        };
+
+        let abcd_units = match abcd_parser.parse_stream(
+            &job.id,
+            &job.dataset_path,
+            &job.landing_page_url,
+            &job.publisher,
+            xml_reader,
+        ) {
+            Ok(units) => units,
+            Err(e) => {
+                warn!(
+                    "Unable to retrieve ABCD data of `{}`, skipping file: {}",
+                    job.id, e
+                );
+                report.errors.push(e.to_string());
+                all_inserts_successful = false;
+                continue;
+            }
+        };
+
+        let mut dataset = abcd_units.dataset().clone();
+        dataset.source_metadata = job.metadata.clone();
+
+        let mut units = abcd_units.map(|unit| unit.map_err(Error::from));
+        match sink.insert_dataset(&dataset, &mut units) {
+            Ok(number_of_units) => report.inserted_units += number_of_units,
+            Err(e) => {
+                warn!("Unable to insert dataset into storage: {}", e);
+                report.errors.push(e.to_string());
+                all_inserts_successful = false;
+            }
+        }
+    }
+
+    all_inserts_successful
+}
+
+/// Copy the archive into the storage directory.
+/// The copy is renamed after it is complete, so an interrupted copy never replaces a stored archive.
+fn store_archive(temp_file_path: &Path, storage_file_path: &Path) -> std::io::Result<()> {
+    let mut part_path = storage_file_path.as_os_str().to_owned();
+    part_path.push(".part");
+
+    std::fs::copy(temp_file_path, &part_path)?;
+    std::fs::rename(&part_path, storage_file_path)
 }
 
 fn create_or_check_for_directory(storage_dir: &&Path) {
@@ -281,15 +954,34 @@ fn create_or_check_for_directory(storage_dir: &&Path) {
             "ABCD storage directory path is not a directory",
         );
     } else {
-        std::fs::create_dir(&storage_dir).expect("ABCD storage directory is not creatable");
+        std::fs::create_dir(storage_dir).expect("ABCD storage directory is not creatable");
     }
 }
 
 fn initialize_settings() -> Result<(Cmd, Settings), Error> {
+    let archive_arg = Arg::new("archive")
+        .value_name("ARCHIVE")
+        .help("A ZIP archive of ABCD XML files")
+        .required(true);
+    let format_arg = Arg::new("format")
+        .short('f')
+        .long("format")
+        .value_name("FORMAT")
+        .help("The output format")
+        .possible_values(ExportFormat::NAMES);
+    let output_arg = Arg::new("output")
+        .short('o')
+        .long("output")
+        .value_name("FILE")
+        .help("The output file, stdout if omitted")
+        .required(false)
+        .takes_value(true);
+
     let matches = Command::new("VAT ABCD Crawler")
         .version(crate_version!())
         .author(crate_authors!())
         .about(crate_description!())
+        .subcommand_required(true)
         .arg(
             Arg::new("settings")
                 .short('s')
@@ -297,25 +989,158 @@ fn initialize_settings() -> Result<(Cmd, Settings), Error> {
                 .value_name("SETTINGS")
                 .help("Specify the settings file")
                 .required(false)
-                .takes_value(true),
+                .takes_value(true)
+                .global(true),
         )
-        .arg(
-            Arg::new("file-to-csv")
-                .long("file-to-csv")
-                .value_name("FILE_TO_CSV")
-                .help("Specify a single file archive (zipped ABCD XMLs) that is converted to CSV")
-                .required(false)
-                .takes_value(true),
+        .subcommand(
+            Command::new("crawl")
+                .about("Crawl all datasets and replace the current tables")
+                .arg(
+                    Arg::new("full")
+                        .long("full")
+                        .help("Re-ingest all datasets instead of carrying over unchanged ones")
+                        .required(false)
+                        .takes_value(false),
+                )
+                .arg(
+                    Arg::new("from")
+                        .long("from")
+                        .value_name("PATH")
+                        .help(
+                            "Crawl the archives of a directory or manifest file instead of Pangaea",
+                        )
+                        .required(false)
+                        .takes_value(true),
+                )
+                .arg(
+                    Arg::new("dry-run")
+                        .long("dry-run")
+                        .help("Print the datasets that would be crawled as CSV and exit")
+                        .required(false)
+                        .takes_value(false),
+                )
+                .arg(format_arg.clone().default_value("postgres"))
+                .arg(output_arg.clone()),
+        )
+        .subcommand(
+            Command::new("convert")
+                .about("Convert an archive, or a directory or manifest of archives, to a file")
+                .arg(
+                    archive_arg
+                        .clone()
+                        .help("An archive, or a directory or manifest of local archives"),
+                )
+                .arg(format_arg.default_value("csv"))
+                .arg(output_arg),
+        )
+        .subcommand(
+            Command::new("validate")
+                .about("Check archives against the field specification")
+                .arg(archive_arg.clone().multiple_values(true)),
+        )
+        .subcommand(
+            Command::new("inspect")
+                .about("Print the dataset metadata and field coverage of an archive")
+                .arg(archive_arg),
+        )
+        .subcommand(
+            Command::new("migrate")
+                .about("Replace the current tables with the tables of the last crawl")
+                .arg(
+                    Arg::new("force")
+                        .long("force")
+                        .help("Skip the checks for an incomplete crawl")
+                        .required(false)
+                        .takes_value(false),
+                ),
+        )
+        .subcommand(
+            Command::new("list-datasets")
+                .about("Print the datasets of the Pangaea search as CSV")
+                .arg(
+                    Arg::new("json")
+                        .long("json")
+                        .help("Print JSON instead of CSV")
+                        .required(false)
+                        .takes_value(false),
+                ),
+        )
+        .subcommand(
+            Command::new("rollback")
+                .about("Replace the current tables with a previous generation")
+                .arg(
+                    Arg::new("generation")
+                        .value_name("GENERATION")
+                        .help("The generation to restore, the newest one if omitted")
+                        .required(false),
+                ),
         )
         .get_matches();
 
-    let cmd = if let Some(file) = matches.value_of("file-to-csv") {
-        Cmd::FileToCsv(PathBuf::from(file))
-    } else {
-        Cmd::All
+    let (cmd, cmd_matches) = match matches.subcommand() {
+        Some(("crawl", cmd_matches)) => (
+            Cmd::Crawl {
+                full: cmd_matches.is_present("full"),
+                source: cmd_matches.value_of("from").map(PathBuf::from),
+                dry_run: cmd_matches.is_present("dry-run"),
+                format: cmd_matches
+                    .value_of("format")
+                    .and_then(ExportFormat::from_name)
+                    .unwrap_or(ExportFormat::Postgres),
+                output: cmd_matches.value_of("output").map(PathBuf::from),
+            },
+            cmd_matches,
+        ),
+        Some(("convert", cmd_matches)) => (
+            Cmd::Convert {
+                archive: cmd_matches.value_of("archive").unwrap_or_default().into(),
+                format: cmd_matches
+                    .value_of("format")
+                    .and_then(ExportFormat::from_name)
+                    .unwrap_or(ExportFormat::Csv),
+                output: cmd_matches.value_of("output").map(PathBuf::from),
+            },
+            cmd_matches,
+        ),
+        Some(("validate", cmd_matches)) => (
+            Cmd::Validate {
+                archives: cmd_matches
+                    .values_of("archive")
+                    .into_iter()
+                    .flatten()
+                    .map(PathBuf::from)
+                    .collect(),
+            },
+            cmd_matches,
+        ),
+        Some(("inspect", cmd_matches)) => (
+            Cmd::Inspect {
+                archive: cmd_matches.value_of("archive").unwrap_or_default().into(),
+            },
+            cmd_matches,
+        ),
+        Some(("migrate", cmd_matches)) => (
+            Cmd::Migrate {
+                force: cmd_matches.is_present("force"),
+            },
+            cmd_matches,
+        ),
+        Some(("list-datasets", cmd_matches)) => (
+            Cmd::ListDatasets {
+                json: cmd_matches.is_present("json"),
+            },
+            cmd_matches,
+        ),
+        Some(("rollback", cmd_matches)) => (
+            Cmd::Rollback {
+                generation: cmd_matches.value_of("generation").map(Into::into),
+            },
+            cmd_matches,
+        ),
+        _ => unreachable!("a subcommand is required"),
     };
 
-    let settings_path = matches.value_of("settings").map(Path::new);
+    let settings_path = cmd_matches.value_of("settings").map(Path::new);
 
     Ok((cmd, Settings::new(settings_path)?))
 }
@@ -333,7 +1158,7 @@ fn initialize_logger(file_path: &Path, settings: &Settings) -> Result<(), Error>
     let term_logger = TermLogger::new(
         log_level,
         simplelog::Config::default(),
-        simplelog::TerminalMode::default(),
+        simplelog::TerminalMode::Stderr,
         simplelog::ColorChoice::Auto,
     );
 
@@ -362,3 +1187,275 @@ fn propose_landing_page(
         dataset_url = dataset_url,
     )
 }
+
+#[cfg(test)]
+mod tests {
+    use crate::test_utils::{self, MemorySink};
+    use crate::vat_type::VatType;
+
+    use super::*;
+
+    #[test]
+    fn crawl_into_sink() {
+        let storage_dir = tempfile::tempdir().unwrap();
+        let (settings, abcd_fields) = create_settings_and_fields(storage_dir.path());
+        let archive_dir = create_archive_dir(&["UNIT_A", "UNIT_B"]);
+        let http_client = test_utils::create_http_client();
+
+        let mut sink = MemorySink::default();
+        let report = process_datasets(
+            &settings,
+            &abcd_fields,
+            &http_client,
+            &mut sink,
+            &LocalSource::new(archive_dir.path()),
+            false,
+        )
+        .unwrap();
+
+        assert!(sink.begun);
+        assert!(sink.finished);
+        assert!(!sink.aborted);
+        assert!(matches!(
+            report.schema_migration,
+            SchemaMigration::Completed
+        ));
+
+        assert_eq!(
+            sink.datasets
+                .iter()
+                .map(|(dataset, units)| (dataset.dataset_id.as_str(), units.len()))
+                .collect::<Vec<_>>(),
+            vec![("UNIT_A", 1), ("UNIT_B", 1)]
+        );
+        assert_eq!(
+            sink.states
+                .iter()
+                .map(|state| state.dataset_id.as_str())
+                .collect::<Vec<_>>(),
+            vec!["UNIT_A", "UNIT_B"]
+        );
+        assert!(report.datasets.iter().all(|dataset| dataset.complete));
+
+        // unchanged archives are carried over in the next crawl
+        let mut next_sink = MemorySink {
+            previous_states: sink
+                .states
+                .iter()
+                .map(|state| (state.dataset_id.clone(), state.clone()))
+                .collect(),
+            previous_units: [("UNIT_A".to_string(), 1), ("UNIT_B".to_string(), 1)]
+                .into_iter()
+                .collect(),
+            ..Default::default()
+        };
+        let report = process_datasets(
+            &settings,
+            &abcd_fields,
+            &http_client,
+            &mut next_sink,
+            &LocalSource::new(archive_dir.path()),
+            false,
+        )
+        .unwrap();
+
+        assert!(next_sink.datasets.is_empty());
+        assert_eq!(next_sink.carried_over, vec!["UNIT_A", "UNIT_B"]);
+        assert!(report.datasets.iter().all(|dataset| dataset.carried_over));
+        assert!(next_sink.finished);
+    }
+
+    #[test]
+    fn insert_unchanged_archive_missing_in_previous_crawl() {
+        let storage_dir = tempfile::tempdir().unwrap();
+        let (settings, abcd_fields) = create_settings_and_fields(storage_dir.path());
+        let archive_dir = create_archive_dir(&["UNIT_A", "UNIT_B"]);
+        let http_client = test_utils::create_http_client();
+
+        let mut sink = MemorySink::default();
+        process_datasets(
+            &settings,
+            &abcd_fields,
+            &http_client,
+            &mut sink,
+            &LocalSource::new(archive_dir.path()),
+            false,
+        )
+        .unwrap();
+
+        // the previous crawl recorded both states, but lacks the units of `UNIT_B`
+        let mut next_sink = MemorySink {
+            previous_states: sink
+                .states
+                .iter()
+                .map(|state| (state.dataset_id.clone(), state.clone()))
+                .collect(),
+            previous_units: [("UNIT_A".to_string(), 1)].into_iter().collect(),
+            ..Default::default()
+        };
+        let report = process_datasets(
+            &settings,
+            &abcd_fields,
+            &http_client,
+            &mut next_sink,
+            &LocalSource::new(archive_dir.path()),
+            false,
+        )
+        .unwrap();
+
+        assert_eq!(next_sink.carried_over, vec!["UNIT_A"]);
+        assert_eq!(
+            next_sink
+                .datasets
+                .iter()
+                .map(|(dataset, units)| (dataset.dataset_id.as_str(), units.len()))
+                .collect::<Vec<_>>(),
+            vec![("UNIT_B", 1)]
+        );
+        assert_eq!(
+            next_sink
+                .states
+                .iter()
+                .map(|state| state.dataset_id.as_str())
+                .collect::<Vec<_>>(),
+            vec!["UNIT_A", "UNIT_B"]
+        );
+        assert!(report.datasets.iter().all(|dataset| dataset.complete));
+        assert_eq!(report.datasets[1].inserted_units, 1);
+        assert!(next_sink.finished);
+    }
+
+    #[test]
+    fn abort_incomplete_crawl() {
+        let storage_dir = tempfile::tempdir().unwrap();
+        let (settings, abcd_fields) = create_settings_and_fields(storage_dir.path());
+        let archive_dir = create_archive_dir(&["UNIT_A"]);
+        std::fs::write(
+            archive_dir.path().join("broken.zip"),
+            "PK\x03\x04 truncated",
+        )
+        .unwrap();
+
+        let mut sink = MemorySink::default();
+        let report = process_datasets(
+            &settings,
+            &abcd_fields,
+            &test_utils::create_http_client(),
+            &mut sink,
+            &LocalSource::new(archive_dir.path()),
+            true,
+        )
+        .unwrap();
+
+        assert!(sink.aborted);
+        assert!(!sink.finished);
+        assert!(matches!(
+            report.schema_migration,
+            SchemaMigration::Aborted { .. }
+        ));
+    }
+
+    #[test]
+    fn crawl_storage_dir_with_download_info() {
+        let storage_dir = tempfile::tempdir().unwrap();
+        let (settings, abcd_fields) = create_settings_and_fields(storage_dir.path());
+        let archive_dir = create_archive_dir(&["UNIT_A", "UNIT_B"]);
+        let http_client = test_utils::create_http_client();
+
+        DownloadInfo {
+            dataset_id: Some("DATASET_A".into()),
+            publisher: Some("PUBLISHER".into()),
+            url: Some("http://example.org/a.zip".into()),
+            landing_page: Some("http://example.org/a".into()),
+            ..Default::default()
+        }
+        .store_for(&archive_dir.path().join("UNIT_A.zip"))
+        .unwrap();
+
+        let mut sink = MemorySink::default();
+        process_datasets(
+            &settings,
+            &abcd_fields,
+            &http_client,
+            &mut sink,
+            &LocalSource::new(archive_dir.path()),
+            false,
+        )
+        .unwrap();
+
+        let (dataset_a, _) = &sink.datasets[0];
+        assert_eq!(dataset_a.dataset_id, "DATASET_A");
+        assert_eq!(dataset_a.dataset_path, "http://example.org/a.zip");
+        assert_eq!(dataset_a.landing_page, "http://example.org/a");
+        assert_eq!(dataset_a.provider_name, "PUBLISHER");
+
+        // no landing page is proposed for the local path of an archive
+        let (dataset_b, _) = &sink.datasets[1];
+        assert_eq!(dataset_b.dataset_id, "UNIT_B");
+        assert_eq!(
+            dataset_b.dataset_path,
+            archive_dir.path().join("UNIT_B.zip").display().to_string()
+        );
+        assert_eq!(dataset_b.landing_page, "");
+    }
+
+    #[test]
+    fn convert_into_sink() {
+        let storage_dir = tempfile::tempdir().unwrap();
+        let (settings, abcd_fields) = create_settings_and_fields(storage_dir.path());
+        let archive_dir = create_archive_dir(&["UNIT_A", "UNIT_B"]);
+
+        let mut sink = MemorySink::default();
+        convert_archives(
+            &settings,
+            &abcd_fields,
+            &LocalSource::new(archive_dir.path()).datasets().unwrap(),
+            &mut sink,
+        )
+        .unwrap();
+
+        assert_eq!(sink.datasets.len(), 2);
+        assert_eq!(
+            sink.datasets[1].1[0].get("/DataSets/DataSet/Units/Unit/UnitID"),
+            Some(&VatType::from("UNIT_B"))
+        );
+    }
+
+    fn create_settings_and_fields(storage_dir: &Path) -> (Settings, AbcdFields) {
+        let mut settings = Settings::new(None).unwrap();
+        settings.general.debug = false;
+        settings.abcd.storage_dir = storage_dir.to_string_lossy().into_owned();
+
+        let abcd_fields = AbcdFields::from_path(Path::new(&settings.abcd.fields_file)).unwrap();
+
+        (settings, abcd_fields)
+    }
+
+    /// Create a directory with an archive per unit id, which also names the archive.
+    fn create_archive_dir(unit_ids: &[&str]) -> tempfile::TempDir {
+        let dir = tempfile::tempdir().unwrap();
+
+        for unit_id in unit_ids {
+            let archive = test_utils::create_zip_file(&[(
+                "a.xml",
+                &format!(
+                    r#"<?xml version="1.0" encoding="UTF-8"?>
+                    <abcd:DataSets xmlns:abcd="http://www.tdwg.org/schemas/abcd/2.06">
+                    <abcd:DataSet>
+                        <abcd:Metadata><abcd:Description><abcd:Representation>
+                            <abcd:Title>Title</abcd:Title>
+                        </abcd:Representation></abcd:Description></abcd:Metadata>
+                        <abcd:Units>
+                            <abcd:Unit><abcd:UnitID>{}</abcd:UnitID></abcd:Unit>
+                        </abcd:Units>
+                    </abcd:DataSet>
+                    </abcd:DataSets>"#,
+                    unit_id
+                ),
+            )]);
+            std::fs::copy(&archive, dir.path().join(unit_id).with_extension("zip")).unwrap();
+        }
+
+        dir
+    }
+}
@@ -0,0 +1,234 @@
+use failure::Error;
+use serde_json::{json, Value};
+
+use crate::crawl_report::{CrawlReport, SchemaMigration};
+use crate::http_client::HttpClient;
+use crate::settings::NotificationSettings;
+
+const TITLE: &str = "VAT ABCD Crawler";
+
+/// This notifier posts the summary of a crawl to a webhook.
+/// The payload is compatible with Slack's incoming webhooks.
+pub struct Notifier<'a> {
+    http_client: &'a HttpClient,
+    notification_settings: &'a NotificationSettings,
+}
+
+impl<'a> Notifier<'a> {
+    pub fn new(
+        http_client: &'a HttpClient,
+        notification_settings: &'a NotificationSettings,
+    ) -> Self {
+        Self {
+            http_client,
+            notification_settings,
+        }
+    }
+
+    /// Notifications are disabled if no webhook url is configured.
+    pub fn is_enabled(&self) -> bool {
+        !self.notification_settings.webhook_url.is_empty()
+    }
+
+    /// Post the summary of a crawl to the webhook.
+    pub fn notify(&self, report: &CrawlReport) -> Result<(), Error> {
+        self.post(self.payload(report))
+    }
+
+    /// Post a crawl that failed before its summary was available to the webhook.
+    pub fn notify_failure(&self, error: &Error) -> Result<(), Error> {
+        let text = format!("The crawl failed: {}", error);
+
+        self.post(self.with_channel(json!({
+            "username": self.notification_settings.username,
+            "icon_emoji": self.notification_settings.icon_emoji,
+            "attachments": [
+                {
+                    "fallback": format!("{}: {}", TITLE, text),
+                    "color": "danger",
+                    "title": TITLE,
+                    "text": text,
+                }
+            ],
+        })))
+    }
+
+    fn post(&self, payload: Value) -> Result<(), Error> {
+        let response = self.http_client.send(
+            self.http_client
+                .post(&self.notification_settings.webhook_url)
+                .json(&payload),
+        )?;
+
+        if !response.status().is_success() {
+            return Err(failure::err_msg(format!(
+                "Webhook responded with code: {}",
+                response.status(),
+            )));
+        }
+
+        Ok(())
+    }
+
+    fn with_channel(&self, mut payload: Value) -> Value {
+        if !self.notification_settings.channel.is_empty() {
+            payload["channel"] = format!("#{}", self.notification_settings.channel).into();
+        }
+
+        payload
+    }
+
+    fn payload(&self, report: &CrawlReport) -> Value {
+        let color = match report.schema_migration {
+            SchemaMigration::Completed if report.number_of_failures() == 0 => "good",
+            SchemaMigration::Completed => "warning",
+            _ => "danger",
+        };
+
+        self.with_channel(json!({
+            "username": self.notification_settings.username,
+            "icon_emoji": self.notification_settings.icon_emoji,
+            "attachments": [
+                {
+                    "fallback": format!(
+                        "{}: {} datasets, {} failed",
+                        TITLE,
+                        report.datasets.len(),
+                        report.number_of_failures(),
+                    ),
+                    "color": color,
+                    "title": TITLE,
+                    "text": report.to_string(),
+                    "fields": [
+                        {
+                            "title": "Datasets",
+                            "value": report.datasets.len().to_string(),
+                            "short": true,
+                        },
+                        {
+                            "title": "Failures",
+                            "value": report.number_of_failures().to_string(),
+                            "short": true,
+                        },
+                        {
+                            "title": "Schema migration",
+                            "value": report.schema_migration.to_string(),
+                            "short": false,
+                        },
+                    ],
+                }
+            ],
+        }))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::crawl_report::DatasetReport;
+    use crate::test_utils::{self, MockWebserver};
+
+    fn create_notification_settings(webhook_url: &str) -> NotificationSettings {
+        NotificationSettings {
+            webhook_url: webhook_url.into(),
+            channel: "vat_status".into(),
+            username: "VAT Notifications".into(),
+            icon_emoji: ":volcano:".into(),
+        }
+    }
+
+    #[test]
+    fn post_summary() {
+        let webserver = MockWebserver::from_text_with_partial_json_condition(
+            "/webhook",
+            "POST",
+            &json!({
+                "channel": "#vat_status",
+                "username": "VAT Notifications",
+                "attachments": [
+                    {
+                        "color": "warning",
+                        "fields": [
+                            { "title": "Datasets", "value": "2" },
+                            { "title": "Failures", "value": "1" },
+                            { "title": "Schema migration", "value": "completed" },
+                        ],
+                    }
+                ],
+            })
+            .to_string(),
+            "ok",
+        );
+
+        let mut complete = DatasetReport::new("COMPLETE", "http://example.org/complete.zip");
+        complete.complete = true;
+        let report = CrawlReport {
+            duration_seconds: 1.,
+            schema_migration: SchemaMigration::Completed,
+            datasets: vec![
+                complete,
+                DatasetReport::new("FAILED", "http://example.org/failed.zip"),
+            ],
+        };
+
+        let http_client = test_utils::create_http_client();
+        let notification_settings =
+            create_notification_settings(&format!("{}/webhook", webserver.webserver_root_url()));
+        let notifier = Notifier::new(&http_client, &notification_settings);
+
+        assert!(notifier.is_enabled());
+        notifier.notify(&report).unwrap();
+    }
+
+    #[test]
+    fn post_failure() {
+        let webserver = MockWebserver::from_text_with_partial_json_condition(
+            "/webhook",
+            "POST",
+            &json!({
+                "channel": "#vat_status",
+                "username": "VAT Notifications",
+                "attachments": [
+                    {
+                        "color": "danger",
+                        "text": "The crawl failed: Unable to retrieve the datasets",
+                    }
+                ],
+            })
+            .to_string(),
+            "ok",
+        );
+
+        let http_client = test_utils::create_http_client();
+        let notification_settings =
+            create_notification_settings(&format!("{}/webhook", webserver.webserver_root_url()));
+        let notifier = Notifier::new(&http_client, &notification_settings);
+
+        notifier
+            .notify_failure(&failure::err_msg("Unable to retrieve the datasets"))
+            .unwrap();
+    }
+
+    #[test]
+    fn report_webhook_errors() {
+        let _mock = mockito::mock("POST", "/broken-webhook")
+            .with_status(404)
+            .create();
+
+        let http_client = test_utils::create_http_client();
+        let notification_settings =
+            create_notification_settings(&format!("{}/broken-webhook", mockito::server_url()));
+        let notifier = Notifier::new(&http_client, &notification_settings);
+
+        assert!(notifier.notify(&CrawlReport::default()).is_err());
+    }
+
+    #[test]
+    fn disabled_without_webhook_url() {
+        let http_client = test_utils::create_http_client();
+        let notification_settings = create_notification_settings("");
+
+        assert!(!Notifier::new(&http_client, &notification_settings).is_enabled());
+    }
+}
@@ -1,3 +1,3 @@
 mod search_result;
 
-pub use self::search_result::{PangaeaSearchResult, PangaeaSearchResultEntry};
+pub use self::search_result::PangaeaSearchResult;
@@ -1,117 +1,386 @@
-use crate::settings::PangaeaSettings;
+use crate::http_client::HttpClient;
+use crate::settings::{PangaeaPagination, PangaeaSettings};
 use failure::Error;
-use log::info;
-use serde::Deserialize;
-use serde_json::json;
-use std::collections::HashMap;
+use log::{info, warn};
+use reqwest::StatusCode;
+use serde::{Deserialize, Deserializer};
+use serde_json::{json, Value};
+use std::collections::{BTreeMap, HashMap};
+use std::fs::File;
 
 #[derive(Clone, Debug, Deserialize, PartialEq)]
 pub struct PangaeaSearchResult {
-    #[serde(rename = "_scroll_id")]
-    scroll_id: String,
+    /// Only scroll requests return a scroll id.
+    #[serde(rename = "_scroll_id", default)]
+    scroll_id: Option<String>,
+    /// Searches of a point in time return its current id.
+    #[serde(default)]
+    pit_id: Option<String>,
     hits: PangaeaSearchResultHits,
 }
 
+#[derive(Clone, Debug, Deserialize, PartialEq)]
+struct PointInTime {
+    id: String,
+}
+
 #[derive(Clone, Debug, Deserialize, PartialEq)]
 struct PangaeaSearchResultHits {
+    #[serde(deserialize_with = "deserialize_total_hits")]
     total: u64,
     hits: Vec<PangaeaSearchResultEntry>,
 }
 
+/// Elasticsearch reports the total number of hits as a number or, since version 7, as an object with its `value`.
+fn deserialize_total_hits<'de, D: Deserializer<'de>>(deserializer: D) -> Result<u64, D::Error> {
+    #[derive(Deserialize)]
+    #[serde(untagged)]
+    enum TotalHits {
+        Number(u64),
+        Object { value: u64 },
+    }
+
+    match TotalHits::deserialize(deserializer)? {
+        TotalHits::Number(value) | TotalHits::Object { value } => Ok(value),
+    }
+}
+
 #[derive(Clone, Debug, Deserialize, PartialEq)]
 pub struct PangaeaSearchResultEntry {
     #[serde(rename = "_id")]
     id: String,
     #[serde(rename = "_source")]
     source: PangaeaSearchResultEntrySource,
+    /// The sort values of sorted requests, which continue the `search_after` pagination.
+    #[serde(default)]
+    sort: Vec<Value>,
 }
 
 #[derive(Clone, Debug, Deserialize, PartialEq)]
 struct PangaeaSearchResultEntrySource {
     citation_publisher: String,
     datalink: String,
+    /// The remaining fields of the search document.
+    #[serde(flatten)]
+    additional_fields: serde_json::Map<String, Value>,
 }
 
 impl PangaeaSearchResult {
-    const SCROLL_TIMEOUT: &'static str = "1m";
-
-    fn from_url(url: &str) -> Result<Self, Error> {
-        let body = json!({
-            "query": {
-                "bool": {
-                    "filter": [
-                        {
-                            "term": {
-                                "internal-source": "gfbio-abcd-push"
-                            }
-                        },
-                        {
-                            "match_phrase": {
-                                "type": "ABCD_Dataset"
-                            }
-                        },
-                        {
-                            "term": {
-                                "accessRestricted": false
-                            }
-                        }
-                    ]
-                }
-            }
-        });
-
-        let response = reqwest::blocking::Client::new()
-            .post(&format!(
+    /// Search and open a scroll context if a `scroll_timeout` is given.
+    fn from_url(
+        http_client: &HttpClient,
+        url: &str,
+        scroll_timeout: Option<&str>,
+        body: &Value,
+    ) -> Result<Self, Error> {
+        let url = match scroll_timeout {
+            Some(scroll_timeout) => format!(
                 "{url}?scroll={scroll_timeout}",
                 url = url,
-                scroll_timeout = Self::SCROLL_TIMEOUT,
-            ))
-            .json(&body)
-            .send()?;
+                scroll_timeout = scroll_timeout,
+            ),
+            None => url.to_string(),
+        };
+
+        let response = http_client.send(http_client.post(&url).json(body))?;
 
-        response.json::<Self>().map_err(Into::into)
+        response.json::<Self>()
     }
 
-    fn from_scroll_url(url: &str, scroll_id: &str) -> Result<Self, Error> {
+    /// Retrieve the next page of a scroll.
+    /// Returns `None` if the scroll context expired.
+    fn from_scroll_url(
+        http_client: &HttpClient,
+        url: &str,
+        scroll_timeout: &str,
+        scroll_id: &str,
+    ) -> Result<Option<Self>, Error> {
         let mut body = HashMap::new();
-        body.insert("scroll", Self::SCROLL_TIMEOUT);
+        body.insert("scroll", scroll_timeout);
         body.insert("scroll_id", scroll_id);
 
-        let response = reqwest::blocking::Client::new()
-            .post(url)
-            .json(&body)
-            .send()?;
+        let response = http_client.send(http_client.post(url).json(&body))?;
+
+        if response.status() == StatusCode::NOT_FOUND {
+            return Ok(None);
+        }
+
+        response.json::<Self>().map(Some)
+    }
+
+    /// Delete the scroll context instead of waiting for it to expire.
+    fn clear_scroll(http_client: &HttpClient, url: &str, scroll_id: &str) -> Result<(), Error> {
+        let response = http_client.send(
+            http_client
+                .delete(url)
+                .json(&json!({ "scroll_id": [scroll_id] })),
+        )?;
+
+        // the context is gone anyway if it expired
+        if !response.status().is_success() && response.status() != StatusCode::NOT_FOUND {
+            return Err(failure::err_msg(format!(
+                "Unable to clear scroll: {}",
+                response.status()
+            )));
+        }
+
+        Ok(())
+    }
+
+    /// Open a point in time of the dataset index and return its id.
+    fn open_point_in_time(
+        http_client: &HttpClient,
+        pangaea_settings: &PangaeaSettings,
+    ) -> Result<String, Error> {
+        let response = http_client.send(http_client.post(&format!(
+            "{url}?keep_alive={keep_alive}",
+            url = pangaea_settings.point_in_time_url,
+            keep_alive = pangaea_settings.scroll_timeout,
+        )))?;
+
+        Ok(response.json::<PointInTime>()?.id)
+    }
 
-        response.json::<Self>().map_err(Into::into)
+    /// Close a point in time instead of waiting for it to expire.
+    fn close_point_in_time(http_client: &HttpClient, url: &str, id: &str) -> Result<(), Error> {
+        let response = http_client.send(http_client.delete(url).json(&json!({ "id": id })))?;
+
+        if !response.status().is_success() && response.status() != StatusCode::NOT_FOUND {
+            return Err(failure::err_msg(format!(
+                "Unable to close point in time: {}",
+                response.status()
+            )));
+        }
+
+        Ok(())
     }
 
+    /// Create the body of the search request from the filters or query file of the settings.
+    fn search_body(pangaea_settings: &PangaeaSettings) -> Result<Value, Error> {
+        let query: Value = if pangaea_settings.query_file.is_empty() {
+            let filters = pangaea_settings
+                .filters
+                .iter()
+                .map(|filter| serde_json::from_str(filter))
+                .collect::<Result<Vec<Value>, _>>()?;
+
+            json!({
+                "bool": {
+                    "filter": filters
+                }
+            })
+        } else {
+            serde_json::from_reader(File::open(&pangaea_settings.query_file)?)?
+        };
+
+        Ok(json!({
+            "size": pangaea_settings.page_size,
+            "query": query,
+        }))
+    }
+
+    /// Retrieve all datasets of the search, restricted to the publishers of the settings.
+    /// If the scroll context expires, the retrieval starts over with `search_after` pagination.
     pub fn retrieve_all_entries(
         pangaea_settings: &PangaeaSettings,
+        http_client: &HttpClient,
     ) -> Result<Vec<PangaeaSearchResultEntry>, Error> {
+        let body = Self::search_body(pangaea_settings)?;
+
+        let mut entries = match pangaea_settings.pagination {
+            PangaeaPagination::Scroll => {
+                match Self::scroll_entries(pangaea_settings, http_client, &body)? {
+                    Some(entries) => entries,
+                    None => {
+                        warn!("The pangaea scroll context expired, retrieving all items again with `search_after`.");
+                        Self::search_after_entries(pangaea_settings, http_client, &body)?
+                    }
+                }
+            }
+            PangaeaPagination::PointInTime => {
+                Self::point_in_time_entries(pangaea_settings, http_client, &body)?
+            }
+        };
+
+        info!("Retrieved {} items from pangaea.", entries.len());
+
+        entries.retain(|entry| entry.is_published_by_one_of(pangaea_settings));
+
+        Ok(entries)
+    }
+
+    /// Retrieve all datasets with the scroll API and clear the scroll context afterwards.
+    /// Returns `None` if the scroll context expired.
+    fn scroll_entries(
+        pangaea_settings: &PangaeaSettings,
+        http_client: &HttpClient,
+        body: &Value,
+    ) -> Result<Option<Vec<PangaeaSearchResultEntry>>, Error> {
         let mut entries = Vec::new();
+        let mut scroll_id = None;
+
+        let completed = Self::scroll_pages(
+            pangaea_settings,
+            http_client,
+            body,
+            &mut entries,
+            &mut scroll_id,
+        );
+
+        if let Some(scroll_id) = scroll_id {
+            if let Err(e) =
+                Self::clear_scroll(http_client, &pangaea_settings.scroll_url, &scroll_id)
+            {
+                warn!("Unable to clear the pangaea scroll context: {}", e);
+            }
+        }
 
-        let mut result = Self::from_url(&pangaea_settings.search_url)?;
-        let mut number_of_results = result.hits.hits.len();
+        Ok(if completed? { Some(entries) } else { None })
+    }
+
+    /// Append all pages of a scroll to `entries` and keep track of the latest scroll id.
+    /// Returns `false` if the scroll context expired.
+    fn scroll_pages(
+        pangaea_settings: &PangaeaSettings,
+        http_client: &HttpClient,
+        body: &Value,
+        entries: &mut Vec<PangaeaSearchResultEntry>,
+        scroll_id: &mut Option<String>,
+    ) -> Result<bool, Error> {
+        let mut result = Self::from_url(
+            http_client,
+            &pangaea_settings.search_url,
+            Some(&pangaea_settings.scroll_timeout),
+            body,
+        )?;
+
+        loop {
+            if let Some(next_scroll_id) = result.scroll_id.take() {
+                *scroll_id = Some(next_scroll_id);
+            }
+
+            if result.hits.hits.is_empty() {
+                return Ok(true);
+            }
 
-        while number_of_results > 0 {
             info!(
                 "Retrieved {} items from pangaea (continuing - {} total).",
-                number_of_results, result.hits.total,
+                result.hits.hits.len(),
+                result.hits.total,
             );
             entries.append(&mut result.hits.hits);
 
-            result = Self::from_scroll_url(&pangaea_settings.scroll_url, &result.scroll_id)?;
-            number_of_results = result.hits.hits.len();
+            let current_scroll_id = scroll_id
+                .as_deref()
+                .ok_or_else(|| failure::err_msg("The search result contains no scroll id"))?;
+
+            result = match Self::from_scroll_url(
+                http_client,
+                &pangaea_settings.scroll_url,
+                &pangaea_settings.scroll_timeout,
+                current_scroll_id,
+            )? {
+                Some(result) => result,
+                None => return Ok(false),
+            };
         }
+    }
 
-        info!("Retrieved {} items from pangaea.", number_of_results);
-        entries.append(&mut result.hits.hits);
+    /// Retrieve all datasets with `search_after` pagination of the dataset index.
+    fn search_after_entries(
+        pangaea_settings: &PangaeaSettings,
+        http_client: &HttpClient,
+        body: &Value,
+    ) -> Result<Vec<PangaeaSearchResultEntry>, Error> {
+        let mut body = body.clone();
+        body["sort"] = serde_json::from_str(&pangaea_settings.sort)?;
 
-        Ok(entries)
+        Self::search_after_pages(http_client, &pangaea_settings.search_url, &mut body)
+    }
+
+    /// Retrieve all datasets with `search_after` pagination of a point in time and close it afterwards.
+    /// The datasets are ordered by `_shard_doc`, which is unique within a point in time.
+    fn point_in_time_entries(
+        pangaea_settings: &PangaeaSettings,
+        http_client: &HttpClient,
+        body: &Value,
+    ) -> Result<Vec<PangaeaSearchResultEntry>, Error> {
+        let mut body = body.clone();
+        body["sort"] = json!([{ "_shard_doc": "asc" }]);
+        body["pit"] = json!({
+            "id": Self::open_point_in_time(http_client, pangaea_settings)?,
+            "keep_alive": pangaea_settings.scroll_timeout,
+        });
+
+        let entries = Self::search_after_pages(
+            http_client,
+            &pangaea_settings.point_in_time_search_url,
+            &mut body,
+        );
+
+        if let Some(id) = body["pit"]["id"].as_str() {
+            if let Err(e) = Self::close_point_in_time(
+                http_client,
+                &pangaea_settings.close_point_in_time_url,
+                id,
+            ) {
+                warn!("Unable to close the pangaea point in time: {}", e);
+            }
+        }
+
+        entries
+    }
+
+    /// Retrieve all pages of a sorted search, each continuing after the sort values of the previous page.
+    /// The point-in-time id of the `body` is replaced by the latest one.
+    fn search_after_pages(
+        http_client: &HttpClient,
+        url: &str,
+        body: &mut Value,
+    ) -> Result<Vec<PangaeaSearchResultEntry>, Error> {
+        let mut entries = Vec::new();
+        loop {
+            let mut result = Self::from_url(http_client, url, None, body)?;
+
+            if let Some(pit_id) = result.pit_id.take() {
+                body["pit"]["id"] = Value::String(pit_id);
+            }
+
+            let search_after = match result.hits.hits.last() {
+                Some(entry) if entry.sort.is_empty() => {
+                    return Err(failure::err_msg(
+                        "The search result contains no sort values",
+                    ))
+                }
+                Some(entry) => entry.sort.clone(),
+                None => return Ok(entries),
+            };
+
+            info!(
+                "Retrieved {} items from pangaea (continuing - {} total).",
+                result.hits.hits.len(),
+                result.hits.total,
+            );
+            entries.append(&mut result.hits.hits);
+
+            body["search_after"] = Value::Array(search_after);
+        }
     }
 }
 
 impl PangaeaSearchResultEntry {
+    /// Check the publisher against the allow and deny lists of the settings.
+    fn is_published_by_one_of(&self, pangaea_settings: &PangaeaSettings) -> bool {
+        let publisher = self.publisher();
+
+        (pangaea_settings.publishers.is_empty()
+            || pangaea_settings.publishers.iter().any(|p| p == publisher))
+            && !pangaea_settings
+                .excluded_publishers
+                .iter()
+                .any(|p| p == publisher)
+    }
+
     pub fn id(&self) -> &str {
         &self.id
     }
@@ -120,6 +389,28 @@ impl PangaeaSearchResultEntry {
         &self.source.citation_publisher
     }
 
+    /// Look up the `metadata_fields` of the settings in the search document.
+    /// Missing fields are left out, while lists and objects are kept as JSON.
+    pub fn metadata(&self, pangaea_settings: &PangaeaSettings) -> BTreeMap<String, String> {
+        pangaea_settings
+            .metadata_fields
+            .iter()
+            .filter_map(|(column, field)| {
+                let mut path = field.split('.');
+                let mut value = self.source.additional_fields.get(path.next()?)?;
+                for key in path {
+                    value = value.get(key)?;
+                }
+
+                match value {
+                    Value::Null => None,
+                    Value::String(value) => Some((column.clone(), value.clone())),
+                    value => Some((column.clone(), value.to_string())),
+                }
+            })
+            .collect()
+    }
+
     pub fn download_url(&self) -> &str {
         &self.source.datalink
     }
@@ -129,7 +420,8 @@ impl PangaeaSearchResultEntry {
 mod tests {
     use super::*;
 
-    use crate::test_utils::MockWebserver;
+    use crate::test_utils::{self, MockWebserver};
+    use mockito::Matcher;
     use serde_json::Value as JsonValue;
 
     const CITATION_PUBLISHER: &str = "Test Publisher";
@@ -141,6 +433,7 @@ mod tests {
     const SEARCH_RESULT_HITS: u64 = 64;
     const SCROLL_ID: &str = "SCROLL_ID_SCROLL_ID";
     const SCROLL_ID_2: &str = "SCROLL_ID_SCROLL_ID_2";
+    const SCROLL_TIMEOUT: &str = "1m";
 
     const SEARCH_RESULT_ENTRY_SOURCE_JSON: fn() -> JsonValue = || {
         json!({
@@ -196,6 +489,7 @@ mod tests {
             PangaeaSearchResultEntrySource {
                 citation_publisher: CITATION_PUBLISHER.into(),
                 datalink: DATALINK.into(),
+                additional_fields: Default::default(),
             }
         )
     }
@@ -214,7 +508,9 @@ mod tests {
                 source: PangaeaSearchResultEntrySource {
                     citation_publisher: CITATION_PUBLISHER.into(),
                     datalink: DATALINK.into(),
+                    additional_fields: Default::default(),
                 },
+                sort: vec![],
             }
         )
     }
@@ -235,40 +531,64 @@ mod tests {
                         source: PangaeaSearchResultEntrySource {
                             citation_publisher: CITATION_PUBLISHER.into(),
                             datalink: DATALINK.into(),
+                            additional_fields: Default::default(),
                         },
+                        sort: vec![],
                     },
                     PangaeaSearchResultEntry {
                         id: RESULT_ID_2.to_string(),
                         source: PangaeaSearchResultEntrySource {
                             citation_publisher: CITATION_PUBLISHER_2.into(),
                             datalink: DATALINK_2.into(),
+                            additional_fields: Default::default(),
                         },
+                        sort: vec![],
                     },
                 ],
             }
         );
     }
 
+    #[test]
+    fn parse_search_result_hits_with_total_object() {
+        let search_result_hits = serde_json::from_str::<PangaeaSearchResultHits>(
+            &json!({
+                "total": { "value": SEARCH_RESULT_HITS, "relation": "eq" },
+                "hits": [],
+            })
+            .to_string(),
+        )
+        .unwrap();
+
+        assert_eq!(search_result_hits.total, SEARCH_RESULT_HITS);
+    }
+
     #[test]
     fn parse_search_result() {
         let search_result =
             serde_json::from_str::<PangaeaSearchResult>(&SEARCH_RESULT_JSON().to_string()).unwrap();
 
-        assert_eq!(search_result.scroll_id, SCROLL_ID);
+        assert_eq!(search_result.scroll_id.as_deref(), Some(SCROLL_ID));
         assert_eq!(search_result.hits.hits.len(), 2);
     }
 
     #[test]
     fn parse_webserver_result() {
         let webserver = MockWebserver::from_json(
-            &format!("/?scroll={}", PangaeaSearchResult::SCROLL_TIMEOUT),
+            &format!("/?scroll={}", SCROLL_TIMEOUT),
             "POST",
             &SEARCH_RESULT_JSON().to_string(),
         );
 
-        let search_result = PangaeaSearchResult::from_url(&webserver.webserver_root_url()).unwrap();
+        let search_result = PangaeaSearchResult::from_url(
+            &test_utils::create_http_client(),
+            &webserver.webserver_root_url(),
+            Some(SCROLL_TIMEOUT),
+            &json!({}),
+        )
+        .unwrap();
 
-        assert_eq!(search_result.scroll_id, SCROLL_ID);
+        assert_eq!(search_result.scroll_id.as_deref(), Some(SCROLL_ID));
         assert_eq!(search_result.hits.hits.len(), 2);
     }
 
@@ -276,11 +596,16 @@ mod tests {
     fn parse_scroll_result() {
         let webserver = MockWebserver::from_json("/", "POST", &SEARCH_RESULT_JSON().to_string());
 
-        let search_result =
-            PangaeaSearchResult::from_scroll_url(&webserver.webserver_root_url(), SCROLL_ID)
-                .unwrap();
+        let search_result = PangaeaSearchResult::from_scroll_url(
+            &test_utils::create_http_client(),
+            &webserver.webserver_root_url(),
+            SCROLL_TIMEOUT,
+            SCROLL_ID,
+        )
+        .unwrap()
+        .unwrap();
 
-        assert_eq!(search_result.scroll_id, SCROLL_ID);
+        assert_eq!(search_result.scroll_id.as_deref(), Some(SCROLL_ID));
         assert_eq!(search_result.hits.hits.len(), 2);
     }
 
@@ -292,7 +617,7 @@ mod tests {
             "/scroll",
             "POST",
             &json!({
-              "scroll" : PangaeaSearchResult::SCROLL_TIMEOUT,
+              "scroll" : SCROLL_TIMEOUT,
               "scroll_id" : SCROLL_ID,
             })
             .to_string(),
@@ -313,7 +638,7 @@ mod tests {
             "/scroll",
             "POST",
             &json!({
-              "scroll" : PangaeaSearchResult::SCROLL_TIMEOUT,
+              "scroll" : SCROLL_TIMEOUT,
               "scroll_id" : SCROLL_ID_2,
             })
             .to_string(),
@@ -328,19 +653,333 @@ mod tests {
             .to_string(),
         );
 
+        let clear_scroll = mockito::mock("DELETE", "/scroll")
+            .match_body(Matcher::Json(json!({ "scroll_id": [SCROLL_ID_2] })))
+            .with_status(200)
+            .expect(1)
+            .create();
+
         assert_eq!(_m2.webserver_root_url(), _m3.webserver_root_url());
 
-        let entries = PangaeaSearchResult::retrieve_all_entries(&PangaeaSettings {
-            search_url: _m1.webserver_root_url(),
-            scroll_url: format!("{}/scroll", _m2.webserver_root_url()),
-        })
+        let entries = PangaeaSearchResult::retrieve_all_entries(
+            &create_pangaea_settings(
+                _m1.webserver_root_url(),
+                format!("{}/scroll", _m2.webserver_root_url()),
+            ),
+            &test_utils::create_http_client(),
+        )
         .unwrap();
 
         assert_eq!(4, entries.len());
+        clear_scroll.assert();
 
         let entry = &entries[0];
         assert_eq!(RESULT_ID, entry.id());
         assert_eq!(DATALINK, entry.download_url());
         assert_eq!(CITATION_PUBLISHER, entry.publisher());
     }
+
+    #[test]
+    fn create_search_body() {
+        let mut pangaea_settings = create_pangaea_settings("".into(), "".into());
+
+        assert_eq!(
+            PangaeaSearchResult::search_body(&pangaea_settings).unwrap(),
+            json!({
+                "size": 10,
+                "query": {
+                    "bool": {
+                        "filter": [
+                            { "term": { "internal-source": "gfbio-abcd-push" } },
+                            { "term": { "accessRestricted": false } },
+                        ]
+                    }
+                }
+            })
+        );
+
+        let query_file =
+            test_utils::create_temp_file(r#"{ "term": { "citation_publisher": "PANGAEA" } }"#);
+        pangaea_settings.query_file = query_file.to_string_lossy().into();
+
+        assert_eq!(
+            PangaeaSearchResult::search_body(&pangaea_settings).unwrap()["query"],
+            json!({ "term": { "citation_publisher": "PANGAEA" } })
+        );
+
+        pangaea_settings.query_file = "".into();
+        pangaea_settings.filters.push("{ invalid".into());
+
+        assert!(PangaeaSearchResult::search_body(&pangaea_settings).is_err());
+    }
+
+    #[test]
+    fn filter_publishers() {
+        let _search = MockWebserver::from_json(
+            "/publishers?scroll=1m",
+            "POST",
+            &SEARCH_RESULT_JSON().to_string(),
+        );
+        let scroll = MockWebserver::from_json(
+            "/publishers-scroll",
+            "POST",
+            &json!({
+                "_scroll_id": SCROLL_ID,
+                "hits": { "total": SEARCH_RESULT_HITS, "hits": [] },
+            })
+            .to_string(),
+        );
+        let _clear_scroll = mockito::mock("DELETE", "/publishers-scroll").create();
+
+        let mut pangaea_settings = create_pangaea_settings(
+            format!("{}/publishers", scroll.webserver_root_url()),
+            format!("{}/publishers-scroll", scroll.webserver_root_url()),
+        );
+        let http_client = test_utils::create_http_client();
+        let entry_ids = |pangaea_settings: &PangaeaSettings| {
+            PangaeaSearchResult::retrieve_all_entries(pangaea_settings, &http_client)
+                .unwrap()
+                .iter()
+                .map(|entry| entry.id().to_string())
+                .collect::<Vec<_>>()
+        };
+
+        assert_eq!(entry_ids(&pangaea_settings), vec![RESULT_ID, RESULT_ID_2]);
+
+        pangaea_settings.publishers = vec![CITATION_PUBLISHER_2.into()];
+        assert_eq!(entry_ids(&pangaea_settings), vec![RESULT_ID_2]);
+
+        pangaea_settings.publishers = vec![];
+        pangaea_settings.excluded_publishers = vec![CITATION_PUBLISHER_2.into()];
+        assert_eq!(entry_ids(&pangaea_settings), vec![RESULT_ID]);
+    }
+
+    #[test]
+    fn map_metadata_fields() {
+        let entry = serde_json::from_value::<PangaeaSearchResultEntry>(json!({
+            "_id": RESULT_ID,
+            "_source": {
+                "citation_publisher": CITATION_PUBLISHER,
+                "datalink": DATALINK,
+                "citation_title": "TITLE",
+                "citation_date": null,
+                "coverage": { "spatial": { "type": "Point", "coordinates": [10, 50] } },
+            },
+        }))
+        .unwrap();
+
+        let mut pangaea_settings = create_pangaea_settings("".into(), "".into());
+        pangaea_settings.metadata_fields = [
+            ("dataset_title", "citation_title"),
+            ("dataset_citation_date", "citation_date"),
+            ("dataset_license", "licenseShort"),
+            ("dataset_spatial_coverage", "coverage.spatial"),
+        ]
+        .iter()
+        .map(|(column, field)| (column.to_string(), field.to_string()))
+        .collect();
+
+        assert_eq!(
+            entry.metadata(&pangaea_settings),
+            [
+                ("dataset_title", "TITLE"),
+                (
+                    "dataset_spatial_coverage",
+                    r#"{"coordinates":[10,50],"type":"Point"}"#
+                ),
+            ]
+            .iter()
+            .map(|(column, value)| (column.to_string(), value.to_string()))
+            .collect()
+        );
+    }
+
+    #[test]
+    fn fall_back_to_search_after_when_scroll_expired() {
+        let _search = MockWebserver::from_json(
+            "/expired?scroll=1m",
+            "POST",
+            &SEARCH_RESULT_JSON().to_string(),
+        );
+        let _scroll = mockito::mock("POST", "/expired-scroll")
+            .with_status(404)
+            .with_header("content-type", "application/json")
+            .with_body(
+                json!({
+                    "error": { "type": "search_context_missing_exception" },
+                    "status": 404,
+                })
+                .to_string(),
+            )
+            .create();
+        let clear_scroll = mockito::mock("DELETE", "/expired-scroll")
+            .match_body(Matcher::Json(json!({ "scroll_id": [SCROLL_ID] })))
+            .with_status(404)
+            .expect(1)
+            .create();
+
+        let sorted_entry = |id: &str| {
+            json!({
+                "_id": id,
+                "_source": SEARCH_RESULT_ENTRY_SOURCE_JSON(),
+                "sort": [id],
+            })
+        };
+        let first_page = mockito::mock("POST", "/expired")
+            .match_body(Matcher::Json(json!({
+                "size": 10,
+                "query": { "match_all": {} },
+                "sort": [{ "datalink": "asc" }],
+            })))
+            .with_header("content-type", "application/json")
+            .with_body(
+                json!({
+                    "hits": {
+                        "total": 3,
+                        "hits": [sorted_entry("a"), sorted_entry("b")],
+                    },
+                })
+                .to_string(),
+            )
+            .expect(1)
+            .create();
+        let second_page = mockito::mock("POST", "/expired")
+            .match_body(Matcher::PartialJson(json!({ "search_after": ["b"] })))
+            .with_header("content-type", "application/json")
+            .with_body(json!({ "hits": { "total": 3, "hits": [sorted_entry("c")] } }).to_string())
+            .expect(1)
+            .create();
+        let last_page = mockito::mock("POST", "/expired")
+            .match_body(Matcher::PartialJson(json!({ "search_after": ["c"] })))
+            .with_header("content-type", "application/json")
+            .with_body(json!({ "hits": { "total": 3, "hits": [] } }).to_string())
+            .expect(1)
+            .create();
+
+        let query_file = test_utils::create_temp_file(r#"{ "match_all": {} }"#);
+        let mut pangaea_settings = create_pangaea_settings(
+            format!("{}/expired", mockito::server_url()),
+            format!("{}/expired-scroll", mockito::server_url()),
+        );
+        pangaea_settings.query_file = query_file.to_string_lossy().into();
+
+        let entries = PangaeaSearchResult::retrieve_all_entries(
+            &pangaea_settings,
+            &test_utils::create_http_client(),
+        )
+        .unwrap();
+
+        assert_eq!(
+            entries.iter().map(|entry| entry.id()).collect::<Vec<_>>(),
+            vec!["a", "b", "c"]
+        );
+
+        clear_scroll.assert();
+        first_page.assert();
+        second_page.assert();
+        last_page.assert();
+    }
+
+    #[test]
+    fn paginate_point_in_time() {
+        let open = mockito::mock("POST", "/index/_pit?keep_alive=1m")
+            .with_header("content-type", "application/json")
+            .with_body(json!({ "id": "PIT_1" }).to_string())
+            .expect(1)
+            .create();
+        let close = mockito::mock("DELETE", "/_pit")
+            .match_body(Matcher::Json(json!({ "id": "PIT_2" })))
+            .with_status(200)
+            .expect(1)
+            .create();
+
+        let sorted_entry = |id: &str| {
+            json!({
+                "_id": id,
+                "_source": SEARCH_RESULT_ENTRY_SOURCE_JSON(),
+                "sort": [id, 0],
+            })
+        };
+        let first_page = mockito::mock("POST", "/_search")
+            .match_body(Matcher::Json(json!({
+                "size": 10,
+                "query": { "match_all": {} },
+                "sort": [{ "_shard_doc": "asc" }],
+                "pit": { "id": "PIT_1", "keep_alive": "1m" },
+            })))
+            .with_header("content-type", "application/json")
+            .with_body(
+                json!({
+                    "pit_id": "PIT_2",
+                    "hits": {
+                        "total": { "value": 2, "relation": "eq" },
+                        "hits": [sorted_entry("a"), sorted_entry("b")],
+                    },
+                })
+                .to_string(),
+            )
+            .expect(1)
+            .create();
+        let last_page = mockito::mock("POST", "/_search")
+            .match_body(Matcher::PartialJson(json!({
+                "pit": { "id": "PIT_2" },
+                "search_after": ["b", 0],
+            })))
+            .with_header("content-type", "application/json")
+            .with_body(
+                json!({
+                    "pit_id": "PIT_2",
+                    "hits": { "total": { "value": 2, "relation": "eq" }, "hits": [] },
+                })
+                .to_string(),
+            )
+            .expect(1)
+            .create();
+
+        let query_file = test_utils::create_temp_file(r#"{ "match_all": {} }"#);
+        let mut pangaea_settings = create_pangaea_settings("".into(), "".into());
+        pangaea_settings.query_file = query_file.to_string_lossy().into();
+        pangaea_settings.pagination = PangaeaPagination::PointInTime;
+        pangaea_settings.point_in_time_url = format!("{}/index/_pit", mockito::server_url());
+        pangaea_settings.point_in_time_search_url = format!("{}/_search", mockito::server_url());
+        pangaea_settings.close_point_in_time_url = format!("{}/_pit", mockito::server_url());
+
+        let entries = PangaeaSearchResult::retrieve_all_entries(
+            &pangaea_settings,
+            &test_utils::create_http_client(),
+        )
+        .unwrap();
+
+        assert_eq!(
+            entries.iter().map(|entry| entry.id()).collect::<Vec<_>>(),
+            vec!["a", "b"]
+        );
+
+        open.assert();
+        first_page.assert();
+        last_page.assert();
+        close.assert();
+    }
+
+    fn create_pangaea_settings(search_url: String, scroll_url: String) -> PangaeaSettings {
+        PangaeaSettings {
+            search_url,
+            scroll_url,
+            pagination: PangaeaPagination::Scroll,
+            point_in_time_url: "".into(),
+            point_in_time_search_url: "".into(),
+            close_point_in_time_url: "".into(),
+            scroll_timeout: SCROLL_TIMEOUT.into(),
+            sort: r#"[{ "datalink": "asc" }]"#.into(),
+            page_size: 10,
+            filters: vec![
+                r#"{ "term": { "internal-source": "gfbio-abcd-push" } }"#.into(),
+                r#"{ "term": { "accessRestricted": false } }"#.into(),
+            ],
+            query_file: "".into(),
+            publishers: vec![],
+            excluded_publishers: vec![],
+            metadata_fields: BTreeMap::new(),
+        }
+    }
 }
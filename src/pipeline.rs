@@ -0,0 +1,804 @@
+use std::collections::BTreeMap;
+use std::fs::File;
+use std::path::{Path, PathBuf};
+use std::sync::mpsc::{self, Receiver, Sender};
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+use failure::Error;
+use log::{info, trace, warn};
+use sha1::{Digest, Sha1};
+
+use crate::abcd::{AbcdFields, AbcdParser, AbcdParserError, AbcdResult, ArchiveReader, ValueMap};
+use crate::dataset_source::ArchiveLocation;
+use crate::file_downloader::{DownloadInfo, DownloadOutcome, FileDownloader};
+use crate::http_client::HttpClient;
+use crate::settings::AbcdSettings;
+use crate::storage::DatasetState;
+use crate::vat_type::VatType;
+
+const DATE_MODIFIED_FIELD: &str = "/DataSets/DataSet/Metadata/RevisionData/DateModified";
+
+/// This struct describes a dataset archive that is downloaded and parsed by a `DatasetWorker`.
+#[derive(Clone, Debug)]
+pub struct DatasetJob {
+    pub id: String,
+    pub publisher: String,
+    pub archive: ArchiveLocation,
+    /// The path that is stored for the dataset, which is the URL of its archive if known.
+    pub dataset_path: String,
+    pub landing_page_url: String,
+    /// Values of the dataset metadata columns.
+    pub metadata: BTreeMap<String, String>,
+    pub temp_file_path: PathBuf,
+    pub storage_file_path: PathBuf,
+    /// The state of the archive in the previous crawl, if it can be carried over.
+    pub previous_state: Option<DatasetState>,
+}
+
+impl DatasetJob {
+    /// The file name of a dataset's archive without extension.
+    /// It keeps the readable characters of the id and appends a hash of the id, so different ids never share a file.
+    pub fn archive_file_name(dataset_id: &str) -> String {
+        let readable_id = dataset_id
+            .chars()
+            .map(|c| match c {
+                'a'..='z' | 'A'..='Z' | '0'..='9' | '-' => c,
+                _ => '_',
+            })
+            .collect::<String>();
+        let id_hash = format!("{:x}", Sha1::digest(dataset_id.as_bytes()));
+
+        format!("{}-{}", readable_id, &id_hash[..16])
+    }
+}
+
+/// This enum describes where the archive of a dataset was retrieved from.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ArchiveSource {
+    /// The archive was downloaded.
+    Downloaded,
+    /// The webserver reported that the cached archive is up to date.
+    Cached,
+    /// The download failed and the cached archive was used instead.
+    Recovered,
+    /// The archive was read from a local file.
+    Local,
+}
+
+/// This enum reflects the results of processing a dataset archive.
+pub enum ArchiveMessage {
+    /// The archive was retrieved and is processed next.
+    Retrieved {
+        source: ArchiveSource,
+        download_duration: Duration,
+    },
+    /// An ABCD file of the archive with its stream of units.
+    /// Dropping `units` stops the worker from parsing the rest of the file.
+    File {
+        dataset: AbcdResult,
+        units: Receiver<Result<ValueMap, AbcdParserError>>,
+    },
+    /// An ABCD file of the archive could not be read or parsed.
+    FailedFile { error: Error },
+    /// The retrieved archive could not be read.
+    FailedArchive { error: Error },
+    /// The archive did not change since the previous crawl and was not parsed.
+    Unchanged { state: DatasetState },
+    /// The archive could not be downloaded or was rejected, e.g., because it is no ZIP archive.
+    /// A cached archive is used instead if possible.
+    DownloadFailed { error: Error },
+    /// All files of the archive were processed.
+    Completed {
+        number_of_files: usize,
+        state: DatasetState,
+    },
+}
+
+/// A queue of jobs that is shared between workers.
+/// Jobs are taken in the order they were sent.
+pub type JobQueue = Mutex<Receiver<(DatasetJob, Sender<ArchiveMessage>)>>;
+
+/// This worker downloads and parses dataset archives.
+/// Its results are sent to a single writer, so it never touches the storage.
+pub struct DatasetWorker<'a> {
+    http_client: &'a HttpClient,
+    abcd_parser: AbcdParser<'a>,
+    unit_buffer_size: usize,
+    max_archive_size: u64,
+}
+
+impl<'a> DatasetWorker<'a> {
+    /// Create a new `DatasetWorker`.
+    /// At most `unit_buffer_size` parsed units per file wait for the writer.
+    pub fn new(
+        http_client: &'a HttpClient,
+        abcd_settings: &'a AbcdSettings,
+        abcd_fields: &'a AbcdFields,
+        unit_buffer_size: usize,
+    ) -> Self {
+        Self {
+            http_client,
+            abcd_parser: AbcdParser::new(abcd_settings, abcd_fields),
+            unit_buffer_size: unit_buffer_size.max(1),
+            max_archive_size: abcd_settings
+                .max_archive_megabytes
+                .saturating_mul(1024 * 1024),
+        }
+    }
+
+    /// Process jobs from the queue until it is closed.
+    pub fn run(&mut self, jobs: &JobQueue) {
+        loop {
+            let job = match jobs.lock() {
+                Ok(jobs) => jobs.recv(),
+                Err(_) => return, // another worker panicked
+            };
+
+            match job {
+                Ok((job, messages)) => self.process(&job, &messages),
+                Err(_) => return, // no more jobs
+            }
+        }
+    }
+
+    /// Retrieve and parse a dataset archive and send the results to the writer.
+    /// Archives that did not change since the previous crawl are not parsed.
+    pub fn process(&mut self, job: &DatasetJob, messages: &Sender<ArchiveMessage>) {
+        let download_start = Instant::now();
+        let retrieved = match &job.archive {
+            ArchiveLocation::Remote(url) => self.download(job, url, messages),
+            ArchiveLocation::Local(path) => Self::copy(job, path, messages),
+        };
+        let (source, download_info) = match retrieved {
+            Some(retrieved) => retrieved,
+            None => return, // skip processing this dataset
+        };
+
+        let retrieved_message = ArchiveMessage::Retrieved {
+            source,
+            download_duration: download_start.elapsed(),
+        };
+        if messages.send(retrieved_message).is_err() {
+            return; // the writer stopped
+        }
+
+        let content_hash = match Self::content_hash(&job.temp_file_path) {
+            Ok(content_hash) => content_hash,
+            Err(e) => {
+                warn!("Unable to read dataset archive: {}", e);
+                // the writer may already have stopped
+                let _ = messages.send(ArchiveMessage::FailedArchive { error: e });
+                return;
+            }
+        };
+
+        let mut state = DatasetState {
+            dataset_id: job.id.clone(),
+            etag: download_info.etag,
+            last_modified: download_info.last_modified,
+            content_hash,
+            date_modified: None,
+        };
+
+        if let Some(previous_state) = &job.previous_state {
+            if state.is_unchanged_since(previous_state) {
+                info!("Skipping unchanged `{}`", job.id);
+
+                state.date_modified = previous_state.date_modified.clone();
+
+                // the writer may already have stopped
+                let _ = messages.send(ArchiveMessage::Unchanged { state });
+                return;
+            }
+        }
+
+        trace!("Temp file: {}", job.temp_file_path.display());
+        info!(
+            "Processing `{}` @ `{}` ({})",
+            job.id, job.publisher, job.archive,
+        );
+
+        let mut archive_reader = match ArchiveReader::from_path(&job.temp_file_path) {
+            Ok(reader) => reader,
+            Err(e) => {
+                warn!("Unable to read dataset archive: {}", e);
+                // the writer may already have stopped
+                let _ = messages.send(ArchiveMessage::FailedArchive { error: e });
+                return;
+            }
+        };
+
+        for index in 0..archive_reader.len() {
+            let xml_reader = match archive_reader.file_reader(index) {
+                Ok(reader) => reader,
+                Err(e) => {
+                    warn!("Unable to read file from zip archive: {}", e);
+                    let failed_message = ArchiveMessage::FailedFile { error: e };
+                    if messages.send(failed_message).is_err() {
+                        return; // the writer stopped
+                    }
+                    continue;
+                }
+            };
+
+            let abcd_units = match self.abcd_parser.parse_stream(
+                &job.id,
+                &job.dataset_path,
+                &job.landing_page_url,
+                &job.publisher,
+                xml_reader,
+            ) {
+                Ok(units) => units,
+                Err(e) => {
+                    warn!(
+                        "Unable to retrieve ABCD data of `{}`, skipping file: {}",
+                        job.id, e
+                    );
+                    let failed_message = ArchiveMessage::FailedFile { error: e.into() };
+                    if messages.send(failed_message).is_err() {
+                        return; // the writer stopped
+                    }
+                    continue;
+                }
+            };
+
+            trace!("{:?}", abcd_units.dataset().dataset);
+
+            if state.date_modified.is_none() {
+                if let Some(VatType::Textual(date_modified)) =
+                    abcd_units.dataset().dataset.get(DATE_MODIFIED_FIELD)
+                {
+                    state.date_modified = Some(date_modified.clone());
+                }
+            }
+
+            let (unit_sender, unit_receiver) = mpsc::sync_channel(self.unit_buffer_size);
+
+            let mut dataset = abcd_units.dataset().clone();
+            dataset.source_metadata = job.metadata.clone();
+
+            let file_message = ArchiveMessage::File {
+                dataset,
+                units: unit_receiver,
+            };
+            if messages.send(file_message).is_err() {
+                return; // the writer stopped
+            }
+
+            for unit in abcd_units {
+                if unit_sender.send(unit).is_err() {
+                    break; // the writer discarded the file
+                }
+            }
+        }
+
+        // the writer may already have stopped
+        let _ = messages.send(ArchiveMessage::Completed {
+            number_of_files: archive_reader.len(),
+            state,
+        });
+    }
+
+    /// Download an archive conditionally on the archive in the storage directory,
+    /// which is also used if the download fails.
+    fn download(
+        &self,
+        job: &DatasetJob,
+        url: &str,
+        messages: &Sender<ArchiveMessage>,
+    ) -> Option<(ArchiveSource, DownloadInfo)> {
+        match FileDownloader::from_url(self.http_client, url)
+            .with_cached_file(&job.storage_file_path)
+            .with_max_size(self.max_archive_size)
+            .to_path(&job.temp_file_path)
+        {
+            Ok(DownloadOutcome::Downloaded(download_info)) => {
+                Some((ArchiveSource::Downloaded, download_info))
+            }
+            Ok(DownloadOutcome::NotModified(download_info)) => {
+                Some((ArchiveSource::Cached, download_info))
+            }
+            Err(e) => {
+                warn!(
+                    "Unable to download file {url} to {path}: {error}",
+                    url = url,
+                    path = job.temp_file_path.display(),
+                    error = e,
+                );
+
+                if messages
+                    .send(ArchiveMessage::DownloadFailed { error: e })
+                    .is_err()
+                {
+                    return None; // the writer stopped
+                }
+
+                match std::fs::copy(&job.storage_file_path, &job.temp_file_path) {
+                    Ok(_) => info!("Recovered file {file}", file = job.id),
+                    Err(e) => {
+                        warn!(
+                            "Recovery of file {file} failed: {error}",
+                            file = job.id,
+                            error = e,
+                        );
+
+                        return None;
+                    }
+                };
+
+                let download_info =
+                    DownloadInfo::load_for(&job.storage_file_path).unwrap_or_default();
+
+                Some((ArchiveSource::Recovered, download_info))
+            }
+        }
+    }
+
+    /// Copy a local archive, so it is processed like a downloaded one.
+    fn copy(
+        job: &DatasetJob,
+        path: &Path,
+        messages: &Sender<ArchiveMessage>,
+    ) -> Option<(ArchiveSource, DownloadInfo)> {
+        match std::fs::copy(path, &job.temp_file_path) {
+            Ok(_) => Some((ArchiveSource::Local, DownloadInfo::default())),
+            Err(e) => {
+                warn!(
+                    "Unable to copy file {path} to {temp_path}: {error}",
+                    path = path.display(),
+                    temp_path = job.temp_file_path.display(),
+                    error = e,
+                );
+
+                // the writer may already have stopped
+                let _ = messages.send(ArchiveMessage::DownloadFailed { error: e.into() });
+
+                None
+            }
+        }
+    }
+
+    /// Compute the SHA-1 hash of a file's content.
+    fn content_hash(path: &Path) -> Result<String, Error> {
+        let mut file = File::open(path)?;
+        let mut hasher = Sha1::new();
+
+        std::io::copy(&mut file, &mut hasher)?;
+
+        Ok(format!("{:x}", hasher.finalize()))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::abcd::AbcdVersion;
+    use crate::file_downloader::FileDownloaderError;
+    use crate::test_utils;
+    use crate::vat_type::VatType;
+    use std::collections::HashMap;
+
+    #[test]
+    fn process_downloaded_archive() {
+        let archive = test_utils::create_zip_file(&[
+            ("a.xml", &abcd_file("UNIT_1")),
+            ("b.xml", "<no-abcd>"),
+            ("c.xml", &abcd_file("UNIT_2")),
+        ]);
+        let webserver = test_utils::MockWebserver::from_bytes(
+            "/archive.zip",
+            "GET",
+            &std::fs::read(&archive).unwrap(),
+        );
+
+        let temp_dir = tempfile::tempdir().unwrap();
+        let job = DatasetJob {
+            metadata: [("dataset_title".to_string(), "TITLE".to_string())].into(),
+            ..create_job(
+                &format!("{}/archive.zip", webserver.webserver_root_url()),
+                temp_dir.path(),
+            )
+        };
+
+        let mut messages = process_in_worker(&job);
+
+        assert!(matches!(
+            messages.remove(0),
+            ReceivedMessage::Retrieved(ArchiveSource::Downloaded)
+        ));
+        assert_eq!(messages.len(), 4);
+
+        match &messages[0] {
+            ReceivedMessage::File(dataset, units) => {
+                assert_eq!(dataset.dataset_id, "TEST_ID");
+                assert_eq!(dataset.provider_name, "TEST_PUBLISHER");
+                assert_eq!(dataset.landing_page, "TEST_LANDING_PAGE");
+                assert_eq!(dataset.abcd_version, AbcdVersion::Version206);
+                assert_eq!(dataset.source_metadata["dataset_title"], "TITLE");
+                assert_eq!(units, &["UNIT_1".to_string()]);
+            }
+            _ => panic!("Expected a file"),
+        }
+        match &messages[1] {
+            ReceivedMessage::FailedFile(error) => {
+                assert!(error.downcast_ref::<AbcdParserError>().is_some())
+            }
+            _ => panic!("Expected a failed file"),
+        }
+        match &messages[2] {
+            ReceivedMessage::File(_, units) => assert_eq!(units, &["UNIT_2".to_string()]),
+            _ => panic!("Expected a file"),
+        }
+        match &messages[3] {
+            ReceivedMessage::Completed {
+                number_of_files,
+                state,
+            } => {
+                assert_eq!(*number_of_files, 3);
+                assert_eq!(state.dataset_id, "TEST_ID");
+                assert_eq!(state.content_hash.len(), 40);
+                assert_eq!(state.date_modified.as_deref(), Some("2020-01-01"));
+            }
+            _ => panic!("Expected completion"),
+        }
+    }
+
+    #[test]
+    fn skip_unchanged_archive() {
+        let archive = test_utils::create_zip_file(&[("a.xml", &abcd_file("UNIT_1"))]);
+        let webserver = test_utils::MockWebserver::from_bytes(
+            "/unchanged.zip",
+            "GET",
+            &std::fs::read(&archive).unwrap(),
+        );
+
+        let temp_dir = tempfile::tempdir().unwrap();
+        let job = create_job(
+            &format!("{}/unchanged.zip", webserver.webserver_root_url()),
+            temp_dir.path(),
+        );
+
+        let state = match process_in_worker(&job).pop() {
+            Some(ReceivedMessage::Completed { state, .. }) => state,
+            _ => panic!("Expected completion"),
+        };
+
+        let job = DatasetJob {
+            previous_state: Some(state.clone()),
+            ..job
+        };
+
+        let mut messages = process_in_worker(&job);
+
+        assert!(matches!(
+            messages.remove(0),
+            ReceivedMessage::Retrieved(ArchiveSource::Downloaded)
+        ));
+        assert_eq!(messages.len(), 1);
+        match &messages[0] {
+            ReceivedMessage::Unchanged { state: new_state } => assert_eq!(new_state, &state),
+            _ => panic!("Expected an unchanged archive"),
+        }
+    }
+
+    #[test]
+    fn use_cached_archive_if_not_modified() {
+        let _mock = mockito::mock("GET", "/cached.zip")
+            .match_header("if-none-match", "\"ETAG\"")
+            .with_status(304)
+            .create();
+
+        let temp_dir = tempfile::tempdir().unwrap();
+        let storage_file_path = temp_dir.path().join("storage.zip");
+        let archive = test_utils::create_zip_file(&[("a.xml", &abcd_file("UNIT_1"))]);
+        std::fs::copy(&archive, &storage_file_path).unwrap();
+        DownloadInfo {
+            etag: Some("\"ETAG\"".into()),
+            last_modified: None,
+            ..Default::default()
+        }
+        .store_for(&storage_file_path)
+        .unwrap();
+
+        let job = create_job(
+            &format!("{}/cached.zip", mockito::server_url()),
+            temp_dir.path(),
+        );
+
+        let messages = process_in_worker(&job);
+
+        assert_eq!(messages.len(), 3);
+        assert!(matches!(
+            messages[0],
+            ReceivedMessage::Retrieved(ArchiveSource::Cached)
+        ));
+        assert!(matches!(&messages[1], ReceivedMessage::File(_, units) if units.len() == 1));
+        match &messages[2] {
+            ReceivedMessage::Completed { state, .. } => {
+                assert_eq!(state.etag.as_deref(), Some("\"ETAG\""));
+            }
+            _ => panic!("Expected completion"),
+        }
+    }
+
+    #[test]
+    fn recover_archive_from_storage() {
+        let archive = test_utils::create_zip_file(&[("a.xml", &abcd_file("UNIT_1"))]);
+
+        let temp_dir = tempfile::tempdir().unwrap();
+        let job = DatasetJob {
+            storage_file_path: archive.to_path_buf(),
+            ..create_job(
+                &format!("{}/missing.zip", mockito::server_url()),
+                temp_dir.path(),
+            )
+        };
+
+        let mut messages = process_in_worker(&job);
+
+        match messages.remove(0) {
+            ReceivedMessage::DownloadFailed(error) => assert!(matches!(
+                error.downcast_ref::<FileDownloaderError>(),
+                Some(FileDownloaderError::UnexpectedStatus(_))
+            )),
+            _ => panic!("Expected a failed download"),
+        }
+        assert!(matches!(
+            messages.remove(0),
+            ReceivedMessage::Retrieved(ArchiveSource::Recovered)
+        ));
+        assert_eq!(messages.len(), 2);
+        assert!(matches!(&messages[0], ReceivedMessage::File(_, units) if units.len() == 1));
+        assert!(matches!(
+            messages[1],
+            ReceivedMessage::Completed {
+                number_of_files: 1,
+                ..
+            }
+        ));
+
+        // without a stored archive, the dataset is skipped
+        let job = DatasetJob {
+            storage_file_path: temp_dir.path().join("storage.zip"),
+            ..job
+        };
+
+        let messages = process_in_worker(&job);
+        assert_eq!(messages.len(), 1);
+        assert!(matches!(messages[0], ReceivedMessage::DownloadFailed(_)));
+    }
+
+    #[test]
+    fn process_local_archive() {
+        let archive = test_utils::create_zip_file(&[("a.xml", &abcd_file("UNIT_1"))]);
+
+        let temp_dir = tempfile::tempdir().unwrap();
+        let job = DatasetJob {
+            archive: ArchiveLocation::Local(archive.to_path_buf()),
+            dataset_path: archive.to_string_lossy().into_owned(),
+            ..create_job("", temp_dir.path())
+        };
+
+        let messages = process_in_worker(&job);
+
+        assert_eq!(messages.len(), 3);
+        assert!(matches!(
+            messages[0],
+            ReceivedMessage::Retrieved(ArchiveSource::Local)
+        ));
+        match &messages[1] {
+            ReceivedMessage::File(dataset, units) => {
+                assert_eq!(dataset.dataset_path, archive.to_string_lossy());
+                assert_eq!(units, &["UNIT_1".to_string()]);
+            }
+            _ => panic!("Expected a file"),
+        }
+        match &messages[2] {
+            ReceivedMessage::Completed { state, .. } => {
+                assert_eq!(state.etag, None);
+                assert_eq!(state.content_hash.len(), 40);
+            }
+            _ => panic!("Expected completion"),
+        }
+
+        // a missing archive fails like a download
+        let job = DatasetJob {
+            archive: ArchiveLocation::Local(temp_dir.path().join("missing.zip")),
+            ..job
+        };
+
+        let messages = process_in_worker(&job);
+        assert_eq!(messages.len(), 1);
+        assert!(matches!(messages[0], ReceivedMessage::DownloadFailed(_)));
+    }
+
+    #[test]
+    fn report_unreadable_archive() {
+        let webserver = test_utils::MockWebserver::from_bytes(
+            "/broken.zip",
+            "GET",
+            b"PK\x03\x04 truncated archive",
+        );
+
+        let temp_dir = tempfile::tempdir().unwrap();
+        let job = create_job(
+            &format!("{}/broken.zip", webserver.webserver_root_url()),
+            temp_dir.path(),
+        );
+
+        let messages = process_in_worker(&job);
+
+        assert_eq!(messages.len(), 2);
+        assert!(matches!(
+            messages[0],
+            ReceivedMessage::Retrieved(ArchiveSource::Downloaded)
+        ));
+        match &messages[1] {
+            ReceivedMessage::FailedArchive(error) => assert!(!error.to_string().is_empty()),
+            _ => panic!("Expected a failed archive"),
+        }
+    }
+
+    #[test]
+    fn unique_archive_file_names() {
+        assert_eq!(
+            DatasetJob::archive_file_name("ds1"),
+            DatasetJob::archive_file_name("ds1")
+        );
+        assert!(DatasetJob::archive_file_name("ds1").starts_with("ds1-"));
+        assert_ne!(
+            DatasetJob::archive_file_name("ds1"),
+            DatasetJob::archive_file_name("ds2")
+        );
+        assert_ne!(
+            DatasetJob::archive_file_name("a/b"),
+            DatasetJob::archive_file_name("a_b")
+        );
+        assert!(!DatasetJob::archive_file_name("../a.b").contains(['/', '.']));
+    }
+
+    enum ReceivedMessage {
+        Retrieved(ArchiveSource),
+        DownloadFailed(Error),
+        FailedArchive(Error),
+        File(AbcdResult, Vec<String>),
+        FailedFile(Error),
+        Unchanged {
+            state: DatasetState,
+        },
+        Completed {
+            number_of_files: usize,
+            state: DatasetState,
+        },
+    }
+
+    /// Create a job for the archive at `url` that keeps its files in `temp_dir`.
+    fn create_job(url: &str, temp_dir: &Path) -> DatasetJob {
+        DatasetJob {
+            id: "TEST_ID".into(),
+            publisher: "TEST_PUBLISHER".into(),
+            archive: ArchiveLocation::Remote(url.into()),
+            dataset_path: url.into(),
+            landing_page_url: "TEST_LANDING_PAGE".into(),
+            metadata: BTreeMap::new(),
+            temp_file_path: temp_dir.join("temp.zip"),
+            storage_file_path: temp_dir.join("storage.zip"),
+            previous_state: None,
+        }
+    }
+
+    /// Run a worker in its own thread and collect its messages with the unit ids.
+    fn process_in_worker(job: &DatasetJob) -> Vec<ReceivedMessage> {
+        let abcd_settings = AbcdSettings {
+            fields_file: "".into(),
+            extension_fields_files: vec![],
+            namespaces: HashMap::new(),
+            landing_page_field: "/DataSets/DataSet/Metadata/Description/Representation/URI".into(),
+            storage_dir: "".into(),
+            max_archive_megabytes: 1,
+        };
+        let abcd_fields = create_abcd_fields();
+        let http_client = test_utils::create_http_client();
+
+        let (job_sender, job_receiver) = mpsc::channel();
+        let jobs: JobQueue = Mutex::new(job_receiver);
+
+        let (message_sender, message_receiver) = mpsc::channel();
+        job_sender.send((job.clone(), message_sender)).unwrap();
+        drop(job_sender);
+
+        std::thread::scope(|scope| {
+            scope.spawn(|| {
+                DatasetWorker::new(&http_client, &abcd_settings, &abcd_fields, 1).run(&jobs)
+            });
+
+            message_receiver
+                .iter()
+                .map(|message| match message {
+                    ArchiveMessage::File { dataset, units } => {
+                        let unit_ids = units
+                            .iter()
+                            .map(|unit| {
+                                match unit.unwrap().get("/DataSets/DataSet/Units/Unit/UnitID") {
+                                    Some(VatType::Textual(id)) => id.clone(),
+                                    _ => panic!("Missing unit id"),
+                                }
+                            })
+                            .collect();
+                        ReceivedMessage::File(dataset, unit_ids)
+                    }
+                    ArchiveMessage::Retrieved { source, .. } => ReceivedMessage::Retrieved(source),
+                    ArchiveMessage::DownloadFailed { error } => {
+                        ReceivedMessage::DownloadFailed(error)
+                    }
+                    ArchiveMessage::FailedFile { error } => ReceivedMessage::FailedFile(error),
+                    ArchiveMessage::FailedArchive { error } => {
+                        ReceivedMessage::FailedArchive(error)
+                    }
+                    ArchiveMessage::Unchanged { state } => ReceivedMessage::Unchanged { state },
+                    ArchiveMessage::Completed {
+                        number_of_files,
+                        state,
+                    } => ReceivedMessage::Completed {
+                        number_of_files,
+                        state,
+                    },
+                })
+                .collect()
+        })
+    }
+
+    fn abcd_file(unit_id: &str) -> String {
+        format!(
+            r#"<?xml version="1.0" encoding="UTF-8"?>
+            <abcd:DataSets xmlns:abcd="http://www.tdwg.org/schemas/abcd/2.06">
+            <abcd:DataSet>
+                <abcd:Metadata><abcd:Description><abcd:Representation>
+                    <abcd:Title>Title</abcd:Title>
+                </abcd:Representation></abcd:Description>
+                <abcd:RevisionData><abcd:DateModified>2020-01-01</abcd:DateModified></abcd:RevisionData>
+                </abcd:Metadata>
+                <abcd:Units>
+                    <abcd:Unit><abcd:UnitID>{}</abcd:UnitID></abcd:Unit>
+                </abcd:Units>
+            </abcd:DataSet>
+            </abcd:DataSets>"#,
+            unit_id
+        )
+    }
+
+    fn create_abcd_fields() -> AbcdFields {
+        let fields_file = test_utils::create_temp_file(
+            r#"[
+                {
+                    "name": "/DataSets/DataSet/Metadata/Description/Representation/Title",
+                    "numeric": false,
+                    "vatMandatory": false,
+                    "gfbioMandatory": true,
+                    "globalField": true,
+                    "unit": ""
+                },
+                {
+                    "name": "/DataSets/DataSet/Metadata/RevisionData/DateModified",
+                    "numeric": false,
+                    "vatMandatory": false,
+                    "gfbioMandatory": false,
+                    "globalField": true,
+                    "unit": ""
+                },
+                {
+                    "name": "/DataSets/DataSet/Units/Unit/UnitID",
+                    "numeric": false,
+                    "vatMandatory": false,
+                    "gfbioMandatory": true,
+                    "globalField": false,
+                    "unit": ""
+                }
+            ]"#,
+        );
+
+        AbcdFields::from_path(&fields_file).expect("Unable to create ABCD Fields Spec")
+    }
+}
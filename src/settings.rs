@@ -1,3 +1,4 @@
+use std::collections::{BTreeMap, HashMap};
 use std::path::Path;
 
 use config::builder::DefaultState;
@@ -11,19 +12,72 @@ use serde::Deserialize;
 pub struct GeneralSettings {
     pub log_file: String,
     pub debug: bool,
+    pub workers: usize,
 }
 
 #[derive(Debug, Deserialize)]
 pub struct AbcdSettings {
     pub fields_file: String,
+    pub extension_fields_files: Vec<String>,
+    pub namespaces: HashMap<String, String>,
     pub landing_page_field: String,
     pub storage_dir: String,
+    pub max_archive_megabytes: u64,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct HttpSettings {
+    pub connect_timeout_seconds: u64,
+    /// The maximum time to wait for a response or the next part of its body, which detects stalled transfers.
+    pub read_timeout_seconds: u64,
+    /// The maximum time of a request including its body, unlimited if omitted.
+    pub timeout_seconds: Option<u64>,
+    pub max_retries: u32,
+    pub initial_backoff_milliseconds: u64,
+    pub max_backoff_milliseconds: u64,
+    pub max_connections_per_host: usize,
 }
 
 #[derive(Debug, Deserialize)]
 pub struct PangaeaSettings {
     pub search_url: String,
     pub scroll_url: String,
+    /// How the search results are paged.
+    pub pagination: PangaeaPagination,
+    /// The URL that opens a point in time of the dataset index.
+    pub point_in_time_url: String,
+    /// The URL that searches a point in time, which must not contain an index.
+    pub point_in_time_search_url: String,
+    /// The URL that closes a point in time.
+    pub close_point_in_time_url: String,
+    /// How long Elasticsearch keeps the scroll or point-in-time context between two requests, e.g., `1m`.
+    pub scroll_timeout: String,
+    /// Sort clauses as JSON for `search_after` pagination if the scroll context expired.
+    /// They must order the datasets uniquely by keyword fields, since sorting by `_id` is disabled in current versions.
+    /// Points in time are always sorted by `_shard_doc`.
+    pub sort: String,
+    /// The number of datasets per request.
+    pub page_size: usize,
+    /// Elasticsearch filter clauses as JSON, which all datasets must match.
+    pub filters: Vec<String>,
+    /// A file with an Elasticsearch query as JSON that replaces the `filters`, if not empty.
+    pub query_file: String,
+    /// Only crawl datasets of these publishers, or all if empty.
+    pub publishers: Vec<String>,
+    /// Never crawl datasets of these publishers.
+    pub excluded_publishers: Vec<String>,
+    /// Fields of the search documents, e.g., `citation_title`, that fill the dataset metadata columns.
+    /// Nested fields are separated by dots.
+    pub metadata_fields: BTreeMap<String, String>,
+}
+
+#[derive(Clone, Copy, Debug, Deserialize, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum PangaeaPagination {
+    /// The scroll API, which is deprecated for deep pagination.
+    Scroll,
+    /// `search_after` pagination within a point in time.
+    PointInTime,
 }
 
 #[derive(Debug, Deserialize)]
@@ -48,9 +102,66 @@ pub struct DatabaseSettings {
     pub dataset_path_column: String,
     pub dataset_landing_page_column: String,
     pub dataset_provider_column: String,
+    /// Additional text columns for the dataset metadata of the source, e.g., a title or license.
+    /// They are the keys of the Pangaea `metadata_fields`, so every mapped field has a column.
+    #[serde(skip)]
+    pub dataset_metadata_columns: Vec<String>,
+    pub dataset_abcd_version_column: String,
     pub unit_table: String,
     pub temp_unit_table: String,
     pub unit_indexed_columns: Vec<String>,
+    pub unit_batch_size: usize,
+    pub schema_generations: usize,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct MigrationSettings {
+    pub max_dataset_decrease: f64,
+    pub max_unit_decrease: f64,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct ReportSettings {
+    pub file: String,
+    pub max_failure_rate: f64,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct NotificationSettings {
+    pub webhook_url: String,
+    pub channel: String,
+    pub username: String,
+    pub icon_emoji: String,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct ExportSettings {
+    /// The unit fields that form the point geometries of geographic formats.
+    pub longitude_field: String,
+    pub latitude_field: String,
+    pub geopackage: GeoPackageSettings,
+    pub parquet: ParquetSettings,
+    pub dwca: DwcaSettings,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct GeoPackageSettings {
+    pub dataset_table: String,
+    pub unit_table: String,
+    /// Indexes of the unit table, each a list of unit fields.
+    pub indexes: Vec<Vec<String>>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct ParquetSettings {
+    /// The number of units per row group.
+    pub row_group_size: usize,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct DwcaSettings {
+    /// A JSON file that maps ABCD fields to Darwin Core terms.
+    pub mapping_file: String,
 }
 
 #[derive(Debug, Deserialize)]
@@ -63,9 +174,14 @@ pub struct DebugSettings {
 #[derive(Debug, Deserialize)]
 pub struct Settings {
     pub abcd: AbcdSettings,
+    pub http: HttpSettings,
     pub pangaea: PangaeaSettings,
     pub terminology_service: TerminologyServiceSettings,
     pub database: DatabaseSettings,
+    pub migration: MigrationSettings,
+    pub report: ReportSettings,
+    pub notifications: NotificationSettings,
+    pub export: ExportSettings,
     pub debug: DebugSettings,
     pub general: GeneralSettings,
 }
@@ -81,7 +197,11 @@ impl Settings {
 
         let config = s.build()?;
 
-        config.try_deserialize()
+        let mut settings: Self = config.try_deserialize()?;
+        settings.database.dataset_metadata_columns =
+            settings.pangaea.metadata_fields.keys().cloned().collect();
+
+        Ok(settings)
     }
 }
 
@@ -104,5 +224,26 @@ mod test {
         let settings = Settings::new(Some(&path)).expect("Unable to load settings.");
 
         assert!(settings.general.debug);
+        assert_eq!(settings.http.max_retries, 5);
+        assert_eq!(settings.pangaea.filters.len(), 3);
+        assert_eq!(settings.pangaea.pagination, PangaeaPagination::Scroll);
+        assert_eq!(settings.export.geopackage.indexes[0].len(), 2);
+        assert_eq!(
+            settings.database.dataset_metadata_columns,
+            settings
+                .pangaea
+                .metadata_fields
+                .keys()
+                .cloned()
+                .collect::<Vec<_>>()
+        );
+        assert!(settings
+            .database
+            .dataset_metadata_columns
+            .contains(&"dataset_title".to_string()));
+        assert_eq!(
+            settings.abcd.namespaces.get("efg").map(String::as_str),
+            Some("http://www.synthesys.info/ABCDEFG/1.0")
+        );
     }
 }
@@ -0,0 +1,61 @@
+use std::collections::{BTreeMap, HashMap};
+
+use failure::Error;
+
+use crate::abcd::{AbcdResult, ValueMap};
+use crate::settings::MigrationSettings;
+use crate::storage::DatasetState;
+
+/// A destination for the datasets of a crawl or conversion, e.g., the database or an export file.
+/// A run begins, inserts datasets and then either finishes or aborts.
+pub trait Sink {
+    /// Prepare a run, e.g., by creating temporary tables.
+    fn begin(&mut self) -> Result<(), Error> {
+        Ok(())
+    }
+
+    /// Insert a dataset and its units.
+    /// Returns the number of inserted units.
+    fn insert_dataset(
+        &mut self,
+        dataset: &AbcdResult,
+        units: &mut dyn Iterator<Item = Result<ValueMap, Error>>,
+    ) -> Result<usize, Error>;
+
+    /// Retrieve the archive states of the datasets of the previous run that can be carried over.
+    fn previous_dataset_states(&mut self) -> Result<HashMap<String, DatasetState>, Error> {
+        Ok(HashMap::new())
+    }
+
+    /// Carry over a dataset of the previous run, whose archive did not change.
+    /// Returns the number of carried over units.
+    fn carry_over_dataset(
+        &mut self,
+        dataset_id: &str,
+        _source_metadata: &BTreeMap<String, String>,
+    ) -> Result<usize, Error> {
+        Err(failure::err_msg(format!(
+            "Unable to carry over dataset `{}` without a previous run",
+            dataset_id
+        )))
+    }
+
+    /// Record the archive state of a completely inserted dataset.
+    fn insert_dataset_state(&mut self, _state: &DatasetState) -> Result<(), Error> {
+        Ok(())
+    }
+
+    /// Check that the run is a plausible replacement for the previous run before finishing it.
+    fn check_migration(&mut self, _migration_settings: &MigrationSettings) -> Result<(), Error> {
+        Ok(())
+    }
+
+    /// Commit the run, e.g., by migrating the temporary tables or completing the file.
+    fn finish(&mut self) -> Result<(), Error>;
+
+    /// Give up the run without committing it.
+    /// Sinks that cannot take back streamed data complete their output instead, so it stays readable.
+    fn abort(&mut self) -> Result<(), Error> {
+        Ok(())
+    }
+}
@@ -1,16 +1,24 @@
+use std::collections::{BTreeMap, HashMap};
 use std::io::Write;
 
 use csv::WriterBuilder;
 use failure::{Error, Fail};
 use log::debug;
 use openssl::ssl::{SslConnector, SslMethod};
-use postgres::{config::SslMode, Client, Config, IsolationLevel, NoTls, Transaction};
+use postgres::{
+    config::SslMode, types::ToSql, Client, Config, GenericClient, IsolationLevel, NoTls,
+    Transaction,
+};
 use postgres_openssl::MakeTlsConnector;
 
-use crate::abcd::{AbcdFields, AbcdResult};
+use crate::abcd::{AbcdField, AbcdFields, AbcdResult, FieldMultiplicity, ValueMap};
 use crate::settings;
-use crate::settings::DatabaseSettings;
-use crate::storage::{Field, SurrogateKey, SurrogateKeyType};
+use crate::settings::{DatabaseSettings, MigrationSettings};
+use crate::sink::Sink;
+use crate::storage::{
+    DatasetState, Field, SurrogateKey, SurrogateKeyType, TableCounts, TableNames,
+};
+use crate::vat_type::VatType;
 
 const POSTGRES_CSV_CONFIGURATION: &str =
     "DELIMITER '\t', NULL '', QUOTE '\"', ESCAPE '\"', FORMAT CSV";
@@ -19,33 +27,45 @@ const POSTGRES_CSV_CONFIGURATION: &str =
 pub struct DatabaseSink<'s> {
     connection: Client,
     database_settings: &'s settings::DatabaseSettings,
+    abcd_fields: &'s AbcdFields,
     dataset_fields: Vec<Field>,
     surrogate_key: SurrogateKey,
     unit_fields: Vec<Field>,
 }
 
 impl<'s> DatabaseSink<'s> {
-    /// Create a new PostgreSQL storage sink (DAO).
+    /// Create a new PostgreSQL storage sink (DAO) and begin a crawl.
+    #[cfg(test)]
     pub fn new(
         database_settings: &'s settings::DatabaseSettings,
-        abcd_fields: &AbcdFields,
+        abcd_fields: &'s AbcdFields,
+    ) -> Result<Self, Error> {
+        let mut sink = Self::open(database_settings, abcd_fields)?;
+
+        sink.begin()?;
+
+        Ok(sink)
+    }
+
+    /// Connect to the database without resetting the temporary tables of a previous crawl.
+    /// A new crawl starts with `begin`.
+    pub fn open(
+        database_settings: &'s settings::DatabaseSettings,
+        abcd_fields: &'s AbcdFields,
     ) -> Result<Self, Error> {
         let connection = <DatabaseSink<'s>>::create_database_connection(database_settings)?;
 
         let (dataset_fields, unit_fields) =
             <DatabaseSink<'s>>::create_lists_of_dataset_and_unit_fields(abcd_fields);
 
-        let mut sink = Self {
+        Ok(Self {
             connection,
             database_settings,
+            abcd_fields,
             dataset_fields,
             surrogate_key: Default::default(),
             unit_fields,
-        };
-
-        sink.initialize_temporary_schema(abcd_fields)?;
-
-        Ok(sink)
+        })
     }
 
     fn create_database_connection(database_settings: &DatabaseSettings) -> Result<Client, Error> {
@@ -97,9 +117,113 @@ impl<'s> DatabaseSink<'s> {
 
         self.create_and_fill_temporary_mapping_table()?;
 
+        self.create_temporary_state_table()?;
+
+        Ok(())
+    }
+
+    /// Create a temporary table for the states of the inserted dataset archives.
+    fn create_temporary_state_table(&mut self) -> Result<(), Error> {
+        let statement = self.connection.prepare(&format!(
+            "CREATE TABLE {schema}.{table}_state ( \
+                dataset_id text primary key, \
+                etag text, \
+                last_modified text, \
+                content_hash text not null, \
+                date_modified text \
+             );",
+            schema = self.database_settings.schema,
+            table = self.database_settings.temp_dataset_table
+        ))?;
+        self.connection.execute(&statement, &[])?;
+
         Ok(())
     }
 
+    /// Retrieve the archive states of the datasets in the persistent tables.
+    /// Returns no states if the persistent tables do not match the temporary tables,
+    /// since their datasets cannot be carried over then.
+    pub fn previous_dataset_states(&mut self) -> Result<HashMap<String, DatasetState>, Error> {
+        let state_table = format!(
+            "{schema}.{table}_state",
+            schema = self.database_settings.schema,
+            table = self.database_settings.dataset_table
+        );
+
+        if !self.previous_tables_are_compatible()?
+            || Self::table_columns(&mut self.connection, &state_table)?.is_empty()
+        {
+            return Ok(HashMap::new());
+        }
+
+        let statement = self.connection.prepare(&format!(
+            "SELECT dataset_id, etag, last_modified, content_hash, date_modified FROM {};",
+            state_table
+        ))?;
+
+        Ok(self
+            .connection
+            .query(&statement, &[])?
+            .iter()
+            .map(|row| {
+                let state = DatasetState {
+                    dataset_id: row.get("dataset_id"),
+                    etag: row.get("etag"),
+                    last_modified: row.get("last_modified"),
+                    content_hash: row.get("content_hash"),
+                    date_modified: row.get("date_modified"),
+                };
+                (state.dataset_id.clone(), state)
+            })
+            .collect())
+    }
+
+    /// Check whether the persistent tables have the same columns as the temporary tables.
+    fn previous_tables_are_compatible(&mut self) -> Result<bool, Error> {
+        for (table, temp_table) in &[
+            (
+                &self.database_settings.dataset_table,
+                &self.database_settings.temp_dataset_table,
+            ),
+            (
+                &self.database_settings.unit_table,
+                &self.database_settings.temp_unit_table,
+            ),
+        ] {
+            let schema = &self.database_settings.schema;
+            let columns =
+                Self::table_columns(&mut self.connection, &format!("{}.{}", schema, table))?;
+            let temp_columns =
+                Self::table_columns(&mut self.connection, &format!("{}.{}", schema, temp_table))?;
+
+            if columns.is_empty() || columns != temp_columns {
+                return Ok(false);
+            }
+        }
+
+        Ok(true)
+    }
+
+    /// Retrieve the ordered column names and types of a table.
+    /// Returns no columns if the table does not exist.
+    fn table_columns(
+        client: &mut impl GenericClient,
+        qualified_table: &str,
+    ) -> Result<Vec<(String, String)>, Error> {
+        let statement = client.prepare(
+            "SELECT attname::text AS name, format_type(atttypid, atttypmod) AS type \
+             FROM pg_attribute \
+             WHERE attrelid = to_regclass($1) AND attnum > 0 AND NOT attisdropped \
+             ORDER BY attname;",
+        )?;
+
+        Ok(client
+            .query(&statement, &[&qualified_table])?
+            .iter()
+            .map(|row| (row.get("name"), row.get("type")))
+            .collect())
+    }
+
     /// Create and fill a temporary mapping table from hashes to field names.
     fn create_and_fill_temporary_mapping_table(&mut self) -> Result<(), Error> {
         // create table
@@ -124,6 +248,39 @@ impl<'s> DatabaseSink<'s> {
         Ok(())
     }
 
+    /// Determine the PostgreSQL data type of a field's column.
+    /// Fields that keep all occurrences are stored as arrays.
+    fn postgres_data_type(abcd_field: &AbcdField) -> &'static str {
+        match (abcd_field.multiplicity, abcd_field.numeric) {
+            (FieldMultiplicity::Join, _) => "text",
+            (FieldMultiplicity::All, true) => "double precision[]",
+            (FieldMultiplicity::All, false) => "text[]",
+            (_, true) => "double precision",
+            (_, false) => "text",
+        }
+    }
+
+    /// Format a value for the PostgreSQL `COPY` statement.
+    /// Lists are written as array literals.
+    fn postgres_value(value: &VatType) -> String {
+        fn array_literal<T: ToString>(values: &[T]) -> String {
+            let elements: Vec<String> = values
+                .iter()
+                .map(|value| {
+                    let escaped = value.to_string().replace('\\', "\\\\").replace('"', "\\\"");
+                    format!("\"{}\"", escaped)
+                })
+                .collect();
+            format!("{{{}}}", elements.join(","))
+        }
+
+        match value {
+            VatType::TextualList(values) => array_literal(values),
+            VatType::NumericList(values) => array_literal(values),
+            value => value.to_string(),
+        }
+    }
+
     /// Create the temporary unit table
     fn create_temporary_unit_table(&mut self, abcd_fields: &AbcdFields) -> Result<(), Error> {
         let mut fields = vec![
@@ -139,11 +296,7 @@ impl<'s> DatabaseSink<'s> {
                 .value_of(field.name.as_bytes())
                 .ok_or_else(|| DatabaseSinkError::InconsistentUnitColumns(field.name.clone()))?;
 
-            let data_type_string = if abcd_field.numeric {
-                "double precision"
-            } else {
-                "text"
-            };
+            let data_type_string = Self::postgres_data_type(abcd_field);
 
             // TODO: enforce/filter not null
             // let null_string = if abcd_field.vat_mandatory { "NOT NULL" } else { "" }
@@ -188,18 +341,22 @@ impl<'s> DatabaseSink<'s> {
                 "{} text not null",
                 self.database_settings.dataset_provider_column
             ), // provider name
+            format!(
+                "{} text not null",
+                self.database_settings.dataset_abcd_version_column
+            ), // abcd version
         ];
 
+        for column in &self.database_settings.dataset_metadata_columns {
+            fields.push(format!("{} text", column));
+        }
+
         for field in &self.dataset_fields {
             let abcd_field = abcd_fields
                 .value_of(field.name.as_bytes())
                 .ok_or_else(|| DatabaseSinkError::InconsistentDatasetColumns(field.name.clone()))?;
 
-            let data_type_string = if abcd_field.numeric {
-                "double precision"
-            } else {
-                "text"
-            };
+            let data_type_string = Self::postgres_data_type(abcd_field);
 
             // TODO: enforce/filter not null
             // let null_string = if abcd_field.vat_mandatory { "NOT NULL" } else { "" }
@@ -245,6 +402,12 @@ impl<'s> DatabaseSink<'s> {
                 schema = &self.database_settings.schema,
                 table = &self.database_settings.temp_dataset_table
             ),
+            // state temp table
+            format!(
+                "DROP TABLE IF EXISTS {schema}.{table}_state;",
+                schema = &self.database_settings.schema,
+                table = &self.database_settings.temp_dataset_table
+            ),
         ] {
             let statement = self.connection.prepare(statement)?;
             self.connection.execute(&statement, &[])?;
@@ -253,8 +416,66 @@ impl<'s> DatabaseSink<'s> {
         Ok(())
     }
 
+    /// Check that the temporary tables are a plausible replacement for the persistent tables,
+    /// i.e., the number of datasets and units did not drop by more than the configured fractions.
+    pub fn check_migration(&mut self, migration_settings: &MigrationSettings) -> Result<(), Error> {
+        let schema = &self.database_settings.schema;
+        let dataset_table = format!("{}.{}", schema, self.database_settings.dataset_table);
+        let unit_table = format!("{}.{}", schema, self.database_settings.unit_table);
+        let temp_dataset_table =
+            format!("{}.{}", schema, self.database_settings.temp_dataset_table);
+        let temp_unit_table = format!("{}.{}", schema, self.database_settings.temp_unit_table);
+
+        let previous_counts = self.table_counts(&dataset_table, &unit_table)?;
+        let counts = self
+            .table_counts(&temp_dataset_table, &temp_unit_table)?
+            .ok_or_else(|| failure::err_msg("Temporary tables are missing"))?;
+
+        debug!(
+            "Dataset and unit counts: {:?} (previous: {:?})",
+            counts, previous_counts
+        );
+
+        Ok(counts.check_against(previous_counts.as_ref(), migration_settings)?)
+    }
+
+    /// Count the rows of a dataset and a unit table.
+    /// Returns `None` if the tables do not exist.
+    fn table_counts(
+        &mut self,
+        qualified_dataset_table: &str,
+        qualified_unit_table: &str,
+    ) -> Result<Option<TableCounts>, Error> {
+        let statement = self
+            .connection
+            .prepare("SELECT to_regclass($1) IS NOT NULL AND to_regclass($2) IS NOT NULL;")?;
+        let tables_exist: bool = self
+            .connection
+            .query_one(
+                &statement,
+                &[&qualified_dataset_table, &qualified_unit_table],
+            )?
+            .get(0);
+        if !tables_exist {
+            return Ok(None);
+        }
+
+        let statement = self.connection.prepare(&format!(
+            "SELECT (SELECT count(*) FROM {}) AS datasets, (SELECT count(*) FROM {}) AS units;",
+            qualified_dataset_table, qualified_unit_table
+        ))?;
+        let row = self.connection.query_one(&statement, &[])?;
+
+        Ok(Some(TableCounts {
+            datasets: row.get::<_, i64>("datasets") as u64,
+            units: row.get::<_, i64>("units") as u64,
+        }))
+    }
+
     /// Migrate the temporary tables to the persistent tables.
-    /// Drops the old tables.
+    /// The old tables are kept as a generation, of which the newest `schema_generations` are retained.
+    /// The indexes are created before the transaction, since `VACUUM` cannot run inside of one,
+    /// but their creation is idempotent, so a failed migration can be repeated.
     pub fn migrate_schema(&mut self) -> Result<(), Error> {
         self.create_indexes_and_statistics()?;
 
@@ -265,11 +486,16 @@ impl<'s> DatabaseSink<'s> {
             .read_only(false)
             .start()?;
 
-        Self::drop_old_tables(self.database_settings, &mut transaction)?;
+        Self::drop_listing_view(self.database_settings, &mut transaction)?;
 
-        Self::rename_temporary_tables(self.database_settings, &mut transaction)?;
+        Self::retire_persistent_tables(self.database_settings, &mut transaction)?;
 
-        Self::rename_constraints_and_indexes(self.database_settings, &mut transaction)?;
+        Self::rename_tables(
+            self.database_settings,
+            &mut transaction,
+            &TableNames::temporary(self.database_settings),
+            &TableNames::persistent(self.database_settings),
+        )?;
 
         Self::create_listing_view(
             self.database_settings,
@@ -278,123 +504,291 @@ impl<'s> DatabaseSink<'s> {
             &mut transaction,
         )?;
 
+        Self::drop_old_generations(self.database_settings, &mut transaction)?;
+
+        transaction.commit()?;
+
+        Ok(())
+    }
+
+    /// Replace the persistent tables with a previous generation, or the newest one if none is specified.
+    /// The replaced tables are kept as a new generation, of which the newest `schema_generations` are retained.
+    /// Returns the restored generation.
+    pub fn rollback(&mut self, generation: Option<&str>) -> Result<String, Error> {
+        let mut transaction = self
+            .connection
+            .build_transaction()
+            .isolation_level(IsolationLevel::Serializable)
+            .read_only(false)
+            .start()?;
+
+        let generations = Self::list_generations(self.database_settings, &mut transaction)?;
+        let generation = match generation {
+            Some(generation) => generations
+                .into_iter()
+                .find(|existing| existing == generation)
+                .ok_or_else(|| DatabaseSinkError::UnknownGeneration(generation.into()))?,
+            None => generations
+                .into_iter()
+                .next()
+                .ok_or_else(|| DatabaseSinkError::UnknownGeneration("(newest)".into()))?,
+        };
+
+        Self::drop_listing_view(self.database_settings, &mut transaction)?;
+
+        Self::retire_persistent_tables(self.database_settings, &mut transaction)?;
+
+        Self::rename_tables(
+            self.database_settings,
+            &mut transaction,
+            &TableNames::generation(self.database_settings, &generation),
+            &TableNames::persistent(self.database_settings),
+        )?;
+
+        // the restored tables may stem from a different field specification
+        let (dataset_fields, unit_fields) =
+            Self::persistent_fields(self.database_settings, &mut transaction)?;
+
+        Self::create_listing_view(
+            self.database_settings,
+            &dataset_fields,
+            &unit_fields,
+            &mut transaction,
+        )?;
+
+        Self::drop_old_generations(self.database_settings, &mut transaction)?;
+
         transaction.commit()?;
 
+        Ok(generation)
+    }
+
+    /// Retrieve the dataset and unit fields of the persistent tables from their translation table.
+    fn persistent_fields(
+        database_settings: &settings::DatabaseSettings,
+        transaction: &mut Transaction,
+    ) -> Result<(Vec<Field>, Vec<Field>), Error> {
+        let schema = &database_settings.schema;
+
+        let column_names = |transaction: &mut Transaction, table: &str| {
+            Self::table_columns(transaction, &format!("{}.{}", schema, table)).map(|columns| {
+                columns
+                    .into_iter()
+                    .map(|(name, _)| name)
+                    .collect::<Vec<String>>()
+            })
+        };
+        let dataset_columns = column_names(transaction, &database_settings.dataset_table)?;
+        let unit_columns = column_names(transaction, &database_settings.unit_table)?;
+
+        let statement = transaction.prepare(&format!(
+            "SELECT name, hash FROM {schema}.{table}_translation;",
+            schema = schema,
+            table = database_settings.dataset_table
+        ))?;
+
+        let mut dataset_fields = Vec::new();
+        let mut unit_fields = Vec::new();
+        for row in transaction.query(&statement, &[])? {
+            let field = Field {
+                name: row.get("name"),
+                hash: row.get("hash"),
+            };
+
+            if dataset_columns.contains(&field.hash) {
+                dataset_fields.push(field);
+            } else if unit_columns.contains(&field.hash) {
+                unit_fields.push(field);
+            }
+        }
+
+        Ok((dataset_fields, unit_fields))
+    }
+
+    /// Retrieve the generations of previous tables, newest first.
+    pub fn schema_generations(&mut self) -> Result<Vec<String>, Error> {
+        Self::list_generations(self.database_settings, &mut self.connection)
+    }
+
+    fn list_generations(
+        database_settings: &settings::DatabaseSettings,
+        client: &mut impl GenericClient,
+    ) -> Result<Vec<String>, Error> {
+        let statement = client.prepare(
+            "SELECT generation FROM ( \
+                SELECT oid, relname, substring(relname FROM length($2) + 1) AS generation \
+                FROM pg_class \
+                WHERE relkind = 'r' AND left(relname, length($2)) = $2 \
+             ) AS tables \
+             WHERE generation ~ '^[0-9]+$' \
+               AND to_regclass(quote_ident($1) || '.' || quote_ident(relname)) = oid \
+             ORDER BY generation DESC;",
+        )?;
+
+        Ok(client
+            .query(
+                &statement,
+                &[
+                    &database_settings.schema,
+                    &TableNames::generation_prefix(database_settings),
+                ],
+            )?
+            .iter()
+            .map(|row| row.get("generation"))
+            .collect())
+    }
+
+    /// Drop the listing view, since it is bound to the tables it was created for.
+    fn drop_listing_view(
+        database_settings: &settings::DatabaseSettings,
+        transaction: &mut Transaction,
+    ) -> Result<(), Error> {
+        let statement = transaction.prepare(&format!(
+            "DROP VIEW IF EXISTS {schema}.{view_name};",
+            schema = database_settings.schema,
+            view_name = database_settings.listing_view
+        ))?;
+        transaction.execute(&statement, &[])?;
+
         Ok(())
     }
 
-    /// Drop old persistent tables.
-    fn drop_old_tables(
+    /// Rename the persistent tables to a new generation if they exist.
+    fn retire_persistent_tables(
         database_settings: &settings::DatabaseSettings,
         transaction: &mut Transaction,
     ) -> Result<(), Error> {
-        for statement in &[
-            // listing view
-            format!(
-                "DROP VIEW IF EXISTS {schema}.{view_name};",
-                schema = database_settings.schema,
-                view_name = database_settings.listing_view
-            ),
-            // unit table
-            format!(
-                "DROP TABLE IF EXISTS {schema}.{table};",
-                schema = database_settings.schema,
-                table = database_settings.unit_table
-            ),
-            // dataset table
-            format!(
-                "DROP TABLE IF EXISTS {schema}.{table};",
-                schema = database_settings.schema,
-                table = database_settings.dataset_table
-            ),
-            // translation table
-            format!(
-                "DROP TABLE IF EXISTS {schema}.{table}_translation;",
+        let persistent = TableNames::persistent(database_settings);
+
+        let statement = transaction.prepare(
+            "SELECT to_regclass($1) IS NOT NULL AND to_regclass($2) IS NOT NULL AS exist, \
+                    to_char(clock_timestamp() AT TIME ZONE 'UTC', 'YYYYMMDDHH24MISSUS') AS generation;",
+        )?;
+        let row = transaction.query_one(
+            &statement,
+            &[
+                &format!("{}.{}", database_settings.schema, persistent.dataset_table),
+                &format!("{}.{}", database_settings.schema, persistent.unit_table),
+            ],
+        )?;
+
+        if !row.get::<_, bool>("exist") {
+            return Ok(());
+        }
+
+        let generation: String = row.get("generation");
+        debug!("Keeping previous tables as generation {}", generation);
+
+        Self::rename_tables(
+            database_settings,
+            transaction,
+            &persistent,
+            &TableNames::generation(database_settings, &generation),
+        )
+    }
+
+    /// Drop all but the newest `schema_generations` generations.
+    fn drop_old_generations(
+        database_settings: &settings::DatabaseSettings,
+        transaction: &mut Transaction,
+    ) -> Result<(), Error> {
+        for generation in Self::list_generations(database_settings, transaction)?
+            .into_iter()
+            .skip(database_settings.schema_generations)
+        {
+            let tables = TableNames::generation(database_settings, &generation);
+
+            debug!("Dropping tables of generation {}", generation);
+
+            let statement = transaction.prepare(&format!(
+                "DROP TABLE IF EXISTS {schema}.{unit_table}, {schema}.{dataset_table}, \
+                 {schema}.{dataset_table}_translation, {schema}.{dataset_table}_state;",
                 schema = database_settings.schema,
-                table = database_settings.dataset_table
-            ),
-        ] {
-            let statement = transaction.prepare(statement)?;
+                unit_table = tables.unit_table,
+                dataset_table = tables.dataset_table,
+            ))?;
             transaction.execute(&statement, &[])?;
         }
 
         Ok(())
     }
 
-    /// Rename temporary tables to persistent tables.
-    fn rename_temporary_tables(
+    /// Rename a set of tables with their constraints and indexes.
+    /// Tables, constraints and indexes that are missing in older tables are skipped.
+    fn rename_tables(
         database_settings: &settings::DatabaseSettings,
         transaction: &mut Transaction,
+        from: &TableNames,
+        to: &TableNames,
     ) -> Result<(), Error> {
+        let schema = &database_settings.schema;
+
         for statement in &[
             // unit table
             format!(
-                "ALTER TABLE {schema}.{temp_table} RENAME TO {table};",
-                schema = database_settings.schema,
-                temp_table = database_settings.temp_unit_table,
-                table = database_settings.unit_table
+                "ALTER TABLE {schema}.{from} RENAME TO {to};",
+                schema = schema,
+                from = from.unit_table,
+                to = to.unit_table
             ),
             // dataset table
             format!(
-                "ALTER TABLE {schema}.{temp_table} RENAME TO {table};",
-                schema = database_settings.schema,
-                temp_table = database_settings.temp_dataset_table,
-                table = database_settings.dataset_table
+                "ALTER TABLE {schema}.{from} RENAME TO {to};",
+                schema = schema,
+                from = from.dataset_table,
+                to = to.dataset_table
             ),
             // translation table
             format!(
-                "ALTER TABLE {schema}.{temp_table}_translation RENAME TO {table}_translation;",
-                schema = database_settings.schema,
-                temp_table = database_settings.temp_dataset_table,
-                table = database_settings.dataset_table
+                "ALTER TABLE IF EXISTS {schema}.{from}_translation RENAME TO {to}_translation;",
+                schema = schema,
+                from = from.dataset_table,
+                to = to.dataset_table
+            ),
+            // state table
+            format!(
+                "ALTER TABLE IF EXISTS {schema}.{from}_state RENAME TO {to}_state;",
+                schema = schema,
+                from = from.dataset_table,
+                to = to.dataset_table
             ),
-        ] {
-            let statement = transaction.prepare(statement)?;
-            transaction.execute(&statement, &[])?;
-        }
-
-        Ok(())
-    }
-
-    /// Rename constraints and indexes from temporary to persistent.
-    fn rename_constraints_and_indexes(
-        database_settings: &settings::DatabaseSettings,
-        transaction: &mut Transaction,
-    ) -> Result<(), Error> {
-        for statement in &[
             // primary key
-            // foreign key
             format!(
-                "ALTER TABLE {schema}.{table} \
-                 RENAME CONSTRAINT {temp_table}_pkey TO {table}_pkey;",
-                schema = &database_settings.schema,
-                table = &database_settings.dataset_table,
-                temp_table = &database_settings.temp_dataset_table,
+                "ALTER TABLE {schema}.{to} RENAME CONSTRAINT {from}_pkey TO {to}_pkey;",
+                schema = schema,
+                from = from.dataset_table,
+                to = to.dataset_table
+            ),
+            // state primary key
+            format!(
+                "ALTER TABLE IF EXISTS {schema}.{to}_state \
+                 RENAME CONSTRAINT {from}_state_pkey TO {to}_state_pkey;",
+                schema = schema,
+                from = from.dataset_table,
+                to = to.dataset_table
             ),
             // foreign key
             format!(
-                "ALTER TABLE {schema}.{table} \
-                 RENAME CONSTRAINT {temp_prefix}_{temp_suffix}_fk TO {prefix}_{suffix}_fk;",
-                schema = &database_settings.schema,
-                table = &database_settings.unit_table,
-                temp_prefix = &database_settings.temp_unit_table,
-                temp_suffix = &database_settings.surrogate_key_column,
-                prefix = &database_settings.unit_table,
-                suffix = &database_settings.surrogate_key_column
+                "ALTER TABLE {schema}.{to} \
+                 RENAME CONSTRAINT {from}_{surrogate_key}_fk TO {to}_{surrogate_key}_fk;",
+                schema = schema,
+                from = from.unit_table,
+                to = to.unit_table,
+                surrogate_key = database_settings.surrogate_key_column
             ),
             // index
             format!(
-                "ALTER INDEX {schema}.{temp_index}_idx RENAME TO {index}_idx;",
-                schema = &database_settings.schema,
-                temp_index = &database_settings.temp_unit_table,
-                index = &database_settings.unit_table
+                "ALTER INDEX IF EXISTS {schema}.{from}_idx RENAME TO {to}_idx;",
+                schema = schema,
+                from = from.unit_table,
+                to = to.unit_table
             ),
             // geom index
             format!(
-                "ALTER INDEX {schema}.{temp_index}_geom_idx RENAME TO {index}_geom_idx;",
-                schema = &database_settings.schema,
-                temp_index = &database_settings.temp_unit_table,
-                index = &database_settings.unit_table
+                "ALTER INDEX IF EXISTS {schema}.{from}_geom_idx RENAME TO {to}_geom_idx;",
+                schema = schema,
+                from = from.unit_table,
+                to = to.unit_table
             ),
         ] {
             let statement = transaction.prepare(statement)?;
@@ -405,9 +799,11 @@ impl<'s> DatabaseSink<'s> {
     }
 
     /// Create foreign key relationships, indexes, clustering and statistics on the temporary tables.
+    /// Existing constraints and indexes of a previous attempt are kept or replaced.
     fn create_indexes_and_statistics(&mut self) -> Result<(), Error> {
         let foreign_key_statement = format!(
             "ALTER TABLE {schema}.{unit_table} \
+             DROP CONSTRAINT IF EXISTS {unit_table}_{dataset_id}_fk, \
              ADD CONSTRAINT {unit_table}_{dataset_id}_fk \
              FOREIGN KEY ({dataset_id}) REFERENCES {schema}.{dataset_table}({dataset_id});",
             schema = &self.database_settings.schema,
@@ -427,7 +823,7 @@ impl<'s> DatabaseSink<'s> {
             .map(|field| field.hash)
             .collect::<Vec<String>>();
         let unit_index_statement = format!(
-            "CREATE INDEX {unit_table}_idx ON {schema}.{unit_table} \
+            "CREATE INDEX IF NOT EXISTS {unit_table}_idx ON {schema}.{unit_table} \
              USING btree ({surrogate_key_column} {other_begin}{other}{other_end});",
             schema = &self.database_settings.schema,
             unit_table = &self.database_settings.temp_unit_table,
@@ -449,7 +845,7 @@ impl<'s> DatabaseSink<'s> {
         self.connection.execute(&unit_index_statement, &[])?;
 
         let geom_index_statement = format!(
-            "CREATE INDEX {unit_table}_geom_idx ON {schema}.{unit_table} \
+            "CREATE INDEX IF NOT EXISTS {unit_table}_geom_idx ON {schema}.{unit_table} \
              USING SPGIST (geom);",
             schema = &self.database_settings.schema,
             unit_table = &self.database_settings.temp_unit_table,
@@ -558,57 +954,287 @@ impl<'s> DatabaseSink<'s> {
     }
 
     /// Insert a dataset and its units into the temporary tables.
-    pub fn insert_dataset(&mut self, abcd_data: &AbcdResult) -> Result<(), Error> {
-        match self.surrogate_key.for_id(&abcd_data.dataset_id) {
+    /// The units are copied in batches of `unit_batch_size`, so they are never kept in memory at once.
+    /// All inserts of a dataset run in one transaction that is rolled back if any unit fails,
+    /// so a partially parsed file leaves no rows behind.
+    /// Returns the number of inserted units.
+    pub fn insert_dataset<I, E>(&mut self, dataset: &AbcdResult, units: I) -> Result<usize, Error>
+    where
+        I: IntoIterator<Item = Result<ValueMap, E>>,
+        E: Into<Error>,
+    {
+        let mut transaction = self.connection.transaction()?;
+        let surrogate_key_type = self.surrogate_key.for_id(&dataset.dataset_id);
+
+        let result = Self::insert_dataset_in_transaction(
+            self.database_settings,
+            &self.dataset_fields,
+            &self.unit_fields,
+            &mut transaction,
+            dataset,
+            &surrogate_key_type,
+            units,
+        )
+        .and_then(|number_of_units| {
+            transaction.commit()?;
+            Ok(number_of_units)
+        });
+
+        if result.is_err() {
+            if let SurrogateKeyType::New(_) = surrogate_key_type {
+                // the dataset row was rolled back, so a later file has to insert it again
+                self.surrogate_key.release(&dataset.dataset_id);
+            }
+        }
+
+        result
+    }
+
+    /// Insert the dataset metadata if the dataset is new and copy its units in batches.
+    fn insert_dataset_in_transaction<I, E>(
+        database_settings: &settings::DatabaseSettings,
+        dataset_fields: &[Field],
+        unit_fields: &[Field],
+        transaction: &mut Transaction,
+        dataset: &AbcdResult,
+        surrogate_key_type: &SurrogateKeyType,
+        units: I,
+    ) -> Result<usize, Error>
+    where
+        I: IntoIterator<Item = Result<ValueMap, E>>,
+        E: Into<Error>,
+    {
+        let surrogate_key = match *surrogate_key_type {
             SurrogateKeyType::New(surrogate_key) => {
                 Self::insert_dataset_metadata(
-                    self.database_settings,
-                    &mut self.connection,
-                    self.dataset_fields.as_slice(),
-                    abcd_data,
+                    database_settings,
+                    transaction,
+                    dataset_fields,
+                    dataset,
                     surrogate_key,
                 )?;
-                self.insert_units(abcd_data, surrogate_key)?;
+                surrogate_key
             }
-            SurrogateKeyType::Existing(surrogate_key) => {
-                self.insert_units(abcd_data, surrogate_key)?;
+            SurrogateKeyType::Existing(surrogate_key) => surrogate_key,
+        };
+
+        let batch_size = database_settings.unit_batch_size.max(1);
+        let mut batch = Vec::with_capacity(batch_size);
+        let mut number_of_units = 0;
+
+        for unit in units {
+            batch.push(unit.map_err(Into::into)?);
+
+            if batch.len() >= batch_size {
+                Self::insert_units(
+                    database_settings,
+                    unit_fields,
+                    transaction,
+                    &batch,
+                    surrogate_key,
+                )?;
+                number_of_units += batch.len();
+                batch.clear();
             }
         }
 
-        Ok(())
+        if !batch.is_empty() {
+            Self::insert_units(
+                database_settings,
+                unit_fields,
+                transaction,
+                &batch,
+                surrogate_key,
+            )?;
+            number_of_units += batch.len();
+        }
+
+        Ok(number_of_units)
     }
 
-    /// Insert the dataset metadata into the temporary schema
-    fn insert_dataset_metadata(
-        database_settings: &settings::DatabaseSettings,
-        connection: &mut Client,
-        dataset_fields: &[Field],
-        abcd_data: &AbcdResult,
-        id: u32,
-    ) -> Result<(), Error> {
-        let mut values = WriterBuilder::new()
-            .terminator(csv::Terminator::Any(b'\n'))
-            .delimiter(b'\t')
-            .quote(b'"')
-            .escape(b'"')
-            .has_headers(false)
-            .from_writer(vec![]);
-        let mut columns: Vec<&str> = vec![
-            database_settings.surrogate_key_column.as_ref(),
-            database_settings.dataset_id_column.as_ref(),
-            database_settings.dataset_path_column.as_ref(),
-            database_settings.dataset_landing_page_column.as_ref(),
-            database_settings.dataset_provider_column.as_ref(),
-        ];
-        values.write_field(id.to_string())?;
-        values.write_field(abcd_data.dataset_id.clone())?;
+    /// Copy a dataset and its units from the persistent tables into the temporary tables.
+    /// This carries over datasets whose archives did not change since the previous crawl.
+    /// The dataset metadata columns are filled with the current `source_metadata`.
+    /// Returns the number of copied units.
+    pub fn carry_over_dataset(
+        &mut self,
+        dataset_id: &str,
+        source_metadata: &BTreeMap<String, String>,
+    ) -> Result<usize, Error> {
+        let surrogate_key = match self.surrogate_key.for_id(dataset_id) {
+            SurrogateKeyType::New(surrogate_key) => surrogate_key,
+            SurrogateKeyType::Existing(_) => return Ok(0), // dataset is already present
+        };
+
+        let result = self.copy_previous_dataset(dataset_id, source_metadata, surrogate_key);
+
+        if result.is_err() {
+            // the copy was rolled back, so inserting the parsed archive has to insert the dataset again
+            self.surrogate_key.release(dataset_id);
+        }
+
+        result
+    }
+
+    /// Copy a dataset and its units within a transaction, using the `surrogate_key` for the new rows.
+    fn copy_previous_dataset(
+        &mut self,
+        dataset_id: &str,
+        source_metadata: &BTreeMap<String, String>,
+        surrogate_key: u32,
+    ) -> Result<usize, Error> {
+        let settings = self.database_settings;
+
+        let dataset_columns = [
+            settings.dataset_id_column.as_str(),
+            settings.dataset_path_column.as_str(),
+            settings.dataset_landing_page_column.as_str(),
+            settings.dataset_provider_column.as_str(),
+            settings.dataset_abcd_version_column.as_str(),
+        ]
+        .iter()
+        .map(|column| column.to_string())
+        .chain(self.dataset_fields.iter().map(|field| field.hash.clone()))
+        .collect::<Vec<String>>()
+        .join("\",\"");
+
+        let metadata_columns = settings
+            .dataset_metadata_columns
+            .iter()
+            .map(|column| format!(", {}", column))
+            .collect::<String>();
+        let metadata_parameters = (0..settings.dataset_metadata_columns.len())
+            .map(|index| format!(", ${}", index + 3))
+            .collect::<String>();
+        let metadata_values: Vec<Option<&str>> = settings
+            .dataset_metadata_columns
+            .iter()
+            .map(|column| source_metadata.get(column).map(String::as_str))
+            .collect();
+
+        let surrogate_key_parameter = surrogate_key as i32;
+        let mut parameters: Vec<&(dyn ToSql + Sync)> = vec![&surrogate_key_parameter, &dataset_id];
+        parameters.extend(
+            metadata_values
+                .iter()
+                .map(|value| value as &(dyn ToSql + Sync)),
+        );
+
+        let mut transaction = self.connection.transaction()?;
+
+        let statement = transaction.prepare(&format!(
+            "INSERT INTO {schema}.{temp_table}({surrogate_key_column}, \"{columns}\"{metadata_columns}) \
+             SELECT $1, \"{columns}\"{metadata_parameters} FROM {schema}.{table} \
+             WHERE {dataset_id_column} = $2 ORDER BY {surrogate_key_column} LIMIT 1;",
+            schema = settings.schema,
+            temp_table = settings.temp_dataset_table,
+            table = settings.dataset_table,
+            surrogate_key_column = settings.surrogate_key_column,
+            dataset_id_column = settings.dataset_id_column,
+            columns = dataset_columns,
+            metadata_columns = metadata_columns,
+            metadata_parameters = metadata_parameters,
+        ))?;
+        if transaction.execute(&statement, &parameters)? == 0 {
+            return Err(DatabaseSinkError::MissingPreviousDataset(dataset_id.into()).into());
+        }
+
+        let unit_columns = self
+            .unit_fields
+            .iter()
+            .map(|field| format!(", \"{}\"", field.hash))
+            .collect::<String>();
+
+        let statement = transaction.prepare(&format!(
+            "INSERT INTO {schema}.{temp_table}({surrogate_key_column}, geom{columns}) \
+             SELECT $1, geom{columns} FROM {schema}.{table} \
+             WHERE {surrogate_key_column} = ( \
+                SELECT {surrogate_key_column} FROM {schema}.{dataset_table} \
+                WHERE {dataset_id_column} = $2 \
+                ORDER BY {surrogate_key_column} LIMIT 1 \
+             );",
+            schema = settings.schema,
+            temp_table = settings.temp_unit_table,
+            table = settings.unit_table,
+            dataset_table = settings.dataset_table,
+            surrogate_key_column = settings.surrogate_key_column,
+            dataset_id_column = settings.dataset_id_column,
+            columns = unit_columns,
+        ))?;
+        let number_of_units =
+            transaction.execute(&statement, &[&(surrogate_key as i32), &dataset_id])?;
+
+        transaction.commit()?;
+
+        Ok(number_of_units as usize)
+    }
+
+    /// Record the archive state of a completely inserted dataset.
+    pub fn insert_dataset_state(&mut self, state: &DatasetState) -> Result<(), Error> {
+        let statement = self.connection.prepare(&format!(
+            "INSERT INTO {schema}.{table}_state \
+             (dataset_id, etag, last_modified, content_hash, date_modified) \
+             VALUES ($1, $2, $3, $4, $5) ON CONFLICT DO NOTHING;",
+            schema = self.database_settings.schema,
+            table = self.database_settings.temp_dataset_table
+        ))?;
+        self.connection.execute(
+            &statement,
+            &[
+                &state.dataset_id,
+                &state.etag,
+                &state.last_modified,
+                &state.content_hash,
+                &state.date_modified,
+            ],
+        )?;
+
+        Ok(())
+    }
+
+    /// Insert the dataset metadata into the temporary schema
+    fn insert_dataset_metadata(
+        database_settings: &settings::DatabaseSettings,
+        connection: &mut impl GenericClient,
+        dataset_fields: &[Field],
+        abcd_data: &AbcdResult,
+        id: u32,
+    ) -> Result<(), Error> {
+        let mut values = WriterBuilder::new()
+            .terminator(csv::Terminator::Any(b'\n'))
+            .delimiter(b'\t')
+            .quote(b'"')
+            .escape(b'"')
+            .has_headers(false)
+            .from_writer(vec![]);
+        let mut columns: Vec<&str> = vec![
+            database_settings.surrogate_key_column.as_ref(),
+            database_settings.dataset_id_column.as_ref(),
+            database_settings.dataset_path_column.as_ref(),
+            database_settings.dataset_landing_page_column.as_ref(),
+            database_settings.dataset_provider_column.as_ref(),
+            database_settings.dataset_abcd_version_column.as_ref(),
+        ];
+        values.write_field(id.to_string())?;
+        values.write_field(abcd_data.dataset_id.clone())?;
         values.write_field(abcd_data.dataset_path.clone())?;
         values.write_field(abcd_data.landing_page.clone())?;
         values.write_field(abcd_data.provider_name.clone())?;
+        values.write_field(abcd_data.abcd_version.to_string())?;
+        for column in &database_settings.dataset_metadata_columns {
+            columns.push(column);
+            values.write_field(
+                abcd_data
+                    .source_metadata
+                    .get(column)
+                    .map(String::as_str)
+                    .unwrap_or_default(),
+            )?;
+        }
         for field in dataset_fields {
             columns.push(&field.hash);
             if let Some(value) = abcd_data.dataset.get(&field.name) {
-                values.write_field(value.to_string())?;
+                values.write_field(Self::postgres_value(value))?;
             } else {
                 values.write_field("")?;
             }
@@ -639,9 +1265,15 @@ impl<'s> DatabaseSink<'s> {
     }
 
     /// Insert the dataset units into the temporary schema
-    fn insert_units(&mut self, abcd_data: &AbcdResult, id: u32) -> Result<(), Error> {
-        let mut columns: Vec<String> = vec![self.database_settings.surrogate_key_column.clone()];
-        columns.extend(self.unit_fields.iter().map(|field| field.hash.clone()));
+    fn insert_units(
+        database_settings: &settings::DatabaseSettings,
+        unit_fields: &[Field],
+        connection: &mut impl GenericClient,
+        units: &[ValueMap],
+        id: u32,
+    ) -> Result<(), Error> {
+        let mut columns: Vec<String> = vec![database_settings.surrogate_key_column.clone()];
+        columns.extend(unit_fields.iter().map(|field| field.hash.clone()));
         columns.push("geom".to_owned());
 
         let mut values = WriterBuilder::new()
@@ -653,16 +1285,18 @@ impl<'s> DatabaseSink<'s> {
             .from_writer(vec![]);
 
         // append units one by one to tsv
-        for unit_data in &abcd_data.units {
-            values.write_field(&id.to_string())?; // put id first
+        for unit_data in units {
+            values.write_field(id.to_string())?; // put id first
 
             let mut lon = None;
             let mut lat = None;
-            for field in &self.unit_fields {
+            for field in unit_fields {
                 if let Some(value) = unit_data.get(&field.name) {
-                    values.write_field(value.to_string())?;
+                    values.write_field(Self::postgres_value(value))?;
 
-                    if field.name == "/DataSets/DataSet/Units/Unit/Gathering/SiteCoordinateSets/SiteCoordinates/CoordinatesLatLong/LongitudeDecimal" {
+                    if value.is_list() {
+                        // only single coordinates form a point
+                    } else if field.name == "/DataSets/DataSet/Units/Unit/Gathering/SiteCoordinateSets/SiteCoordinates/CoordinatesLatLong/LongitudeDecimal" {
                         lon = Some(value);
                     } else if field.name == "/DataSets/DataSet/Units/Unit/Gathering/SiteCoordinateSets/SiteCoordinates/CoordinatesLatLong/LatitudeDecimal" {
                         lat = Some(value);
@@ -683,16 +1317,16 @@ impl<'s> DatabaseSink<'s> {
 
         let copy_statement = format!(
             "COPY {schema}.{table}(\"{columns}\") FROM STDIN WITH ({options})",
-            schema = self.database_settings.schema,
-            table = self.database_settings.temp_unit_table,
+            schema = database_settings.schema,
+            table = database_settings.temp_unit_table,
             columns = columns.join("\",\""),
             options = POSTGRES_CSV_CONFIGURATION
         );
 
-        let statement = self.connection.prepare(&copy_statement)?;
+        let statement = connection.prepare(&copy_statement)?;
         //            dbg!(&value_string);
 
-        let mut writer = self.connection.copy_in(&statement)?;
+        let mut writer = connection.copy_in(&statement)?;
         writer.write_all(values.into_inner()?.as_slice())?;
         writer.finish()?;
 
@@ -700,6 +1334,52 @@ impl<'s> DatabaseSink<'s> {
     }
 }
 
+impl<'s> Sink for DatabaseSink<'s> {
+    /// Reset the temporary tables for a new crawl.
+    fn begin(&mut self) -> Result<(), Error> {
+        self.surrogate_key = Default::default();
+
+        self.initialize_temporary_schema(self.abcd_fields)
+    }
+
+    fn insert_dataset(
+        &mut self,
+        dataset: &AbcdResult,
+        units: &mut dyn Iterator<Item = Result<ValueMap, Error>>,
+    ) -> Result<usize, Error> {
+        DatabaseSink::insert_dataset(self, dataset, units)
+    }
+
+    fn previous_dataset_states(&mut self) -> Result<HashMap<String, DatasetState>, Error> {
+        DatabaseSink::previous_dataset_states(self)
+    }
+
+    fn carry_over_dataset(
+        &mut self,
+        dataset_id: &str,
+        source_metadata: &BTreeMap<String, String>,
+    ) -> Result<usize, Error> {
+        DatabaseSink::carry_over_dataset(self, dataset_id, source_metadata)
+    }
+
+    fn insert_dataset_state(&mut self, state: &DatasetState) -> Result<(), Error> {
+        DatabaseSink::insert_dataset_state(self, state)
+    }
+
+    fn check_migration(&mut self, migration_settings: &MigrationSettings) -> Result<(), Error> {
+        DatabaseSink::check_migration(self, migration_settings)
+    }
+
+    fn finish(&mut self) -> Result<(), Error> {
+        self.migrate_schema()
+    }
+
+    /// Keep the temporary tables, so they can still be migrated with `migrate --force`.
+    fn abort(&mut self) -> Result<(), Error> {
+        Ok(())
+    }
+}
+
 /// An error enum for different storage sink errors.
 #[derive(Debug, Fail)]
 pub enum DatabaseSinkError {
@@ -709,13 +1389,21 @@ pub enum DatabaseSinkError {
     /// This error occurs when there is an inconsistency between the ABCD unit data and the sink's columns.
     #[fail(display = "Inconsistent unit columns: {}", 0)]
     InconsistentUnitColumns(String),
+    /// This error occurs when a dataset that should be carried over is missing in the persistent tables.
+    #[fail(display = "Missing previous dataset: {}", _0)]
+    MissingPreviousDataset(String),
+    /// This error occurs when a generation of previous tables does not exist.
+    #[fail(display = "Unknown schema generation: {}", _0)]
+    UnknownGeneration(String),
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
 
+    use crate::abcd::AbcdVersion;
     use crate::settings::{DatabaseSettings, Settings};
+    use crate::storage::MigrationCheckError;
     use crate::test_utils;
     use postgres::Row;
     use serde_json::json;
@@ -735,7 +1423,8 @@ mod tests {
             sorted_vec(vec![
                 database_settings.temp_dataset_table.clone(),
                 database_settings.temp_unit_table.clone(),
-                format!("{}_translation", database_settings.temp_dataset_table)
+                format!("{}_translation", database_settings.temp_dataset_table),
+                format!("{}_state", database_settings.temp_dataset_table),
             ])
         );
     }
@@ -786,7 +1475,9 @@ mod tests {
                 "dataset_landing_page".to_string(),
                 "dataset_path".to_string(),
                 "dataset_provider".to_string(),
+                "dataset_abcd_version".to_string(),
             ])
+            .chain(database_settings.dataset_metadata_columns.iter().cloned())
             .collect::<Vec<_>>();
 
         assert!(!dataset_columns.is_empty());
@@ -990,18 +1681,23 @@ mod tests {
         let mut database_sink = DatabaseSink::new(&database_settings, &abcd_fields).unwrap();
 
         database_sink
-            .insert_dataset(&AbcdResult {
-                dataset_id: "TEST_ID".to_string(),
-                dataset_path: "TEST_PATH".to_string(),
-                landing_page: "TEST_LANDING_PAGE".to_string(),
-                provider_name: "TEST_PROVIDER".to_string(),
-                dataset: {
-                    let mut values = HashMap::new();
-                    values.insert("DS_TEXT".into(), "FOOBAR".into());
-                    values.insert("DS_NUM".into(), 42.0.into());
-                    values
+            .insert_dataset(
+                &AbcdResult {
+                    dataset_id: "TEST_ID".to_string(),
+                    dataset_path: "TEST_PATH".to_string(),
+                    landing_page: "TEST_LANDING_PAGE".to_string(),
+                    provider_name: "TEST_PROVIDER".to_string(),
+                    abcd_version: AbcdVersion::Version206,
+                    source_metadata: [("dataset_title".to_string(), "TEST_TITLE".to_string())]
+                        .into(),
+                    dataset: {
+                        let mut values = HashMap::new();
+                        values.insert("DS_TEXT".into(), "FOOBAR".into());
+                        values.insert("DS_NUM".into(), 42.0.into());
+                        values
+                    },
                 },
-                units: vec![
+                ok_units(vec![
                     {
                         let mut values = HashMap::new();
                         values.insert("UNIT_TEXT".into(), "FOO".into());
@@ -1014,8 +1710,8 @@ mod tests {
                         values.insert("UNIT_NUM".into(), 37.0.into());
                         values
                     },
-                ],
-            })
+                ]),
+            )
             .unwrap();
 
         assert_eq!(
@@ -1047,6 +1743,15 @@ mod tests {
             "TEST_PROVIDER",
             dataset.get::<_, &str>(database_settings.dataset_provider_column.as_str())
         );
+        assert_eq!(
+            "2.06",
+            dataset.get::<_, &str>(database_settings.dataset_abcd_version_column.as_str())
+        );
+        assert_eq!(
+            Some("TEST_TITLE"),
+            dataset.get::<_, Option<&str>>("dataset_title")
+        );
+        assert_eq!(None, dataset.get::<_, Option<&str>>("dataset_doi"));
         assert_eq!(
             "FOOBAR",
             dataset.get::<_, &str>(Field::new("DS_TEXT").hash.as_str())
@@ -1058,7 +1763,7 @@ mod tests {
 
         let unit_result = retrieve_rows(&mut database_sink, &database_settings.temp_unit_table);
 
-        let unit1 = unit_result.get(0).unwrap();
+        let unit1 = unit_result.first().unwrap();
         assert_eq!(
             "FOO",
             unit1.get::<_, &str>(Field::new("UNIT_TEXT").hash.as_str())
@@ -1079,6 +1784,171 @@ mod tests {
         );
     }
 
+    #[test]
+    fn units_are_inserted_in_batches() {
+        let mut database_settings = retrieve_settings_from_file_and_override_schema();
+        database_settings.unit_batch_size = 2;
+        let abcd_fields = create_abcd_fields_from_json(&json!([
+            {
+                "name": "UNIT_NUM",
+                "numeric": true,
+                "vatMandatory": false,
+                "gfbioMandatory": true,
+                "globalField": false,
+                "unit": ""
+            },
+        ]));
+
+        let mut database_sink = DatabaseSink::new(&database_settings, &abcd_fields).unwrap();
+
+        let units = (0..5).map(|i| {
+            let mut values = HashMap::new();
+            values.insert("UNIT_NUM".to_string(), VatType::from(f64::from(i)));
+            Ok::<_, Error>(values)
+        });
+
+        let number_of_units = database_sink
+            .insert_dataset(
+                &AbcdResult {
+                    dataset_id: "TEST_ID".to_string(),
+                    dataset_path: "TEST_PATH".to_string(),
+                    landing_page: "TEST_LANDING_PAGE".to_string(),
+                    provider_name: "TEST_PROVIDER".to_string(),
+                    abcd_version: AbcdVersion::Version206,
+                    source_metadata: Default::default(),
+                    dataset: Default::default(),
+                },
+                units,
+            )
+            .unwrap();
+
+        assert_eq!(5, number_of_units);
+        assert_eq!(
+            1,
+            number_of_entries(&mut database_sink, &database_settings.temp_dataset_table)
+        );
+        assert_eq!(
+            5,
+            number_of_entries(&mut database_sink, &database_settings.temp_unit_table)
+        );
+    }
+
+    #[test]
+    fn failing_unit_stream_leads_to_error() {
+        let mut database_settings = retrieve_settings_from_file_and_override_schema();
+        database_settings.unit_batch_size = 1;
+        let abcd_fields = create_abcd_fields_from_json(&json!([]));
+
+        let mut database_sink = DatabaseSink::new(&database_settings, &abcd_fields).unwrap();
+
+        let units = vec![
+            Ok(ValueMap::new()),
+            Err(failure::err_msg("broken unit")),
+            Ok(ValueMap::new()),
+        ];
+
+        assert!(database_sink
+            .insert_dataset(
+                &AbcdResult {
+                    dataset_id: "TEST_ID".to_string(),
+                    dataset_path: "TEST_PATH".to_string(),
+                    landing_page: "TEST_LANDING_PAGE".to_string(),
+                    provider_name: "TEST_PROVIDER".to_string(),
+                    abcd_version: AbcdVersion::Version206,
+                    source_metadata: Default::default(),
+                    dataset: Default::default(),
+                },
+                units,
+            )
+            .is_err());
+
+        assert_eq!(
+            0,
+            number_of_entries(&mut database_sink, &database_settings.temp_dataset_table)
+        );
+        assert_eq!(
+            0,
+            number_of_entries(&mut database_sink, &database_settings.temp_unit_table)
+        );
+    }
+
+    #[test]
+    #[allow(clippy::float_cmp)]
+    fn unit_table_contains_arrays_for_multi_valued_fields() {
+        let database_settings = retrieve_settings_from_file_and_override_schema();
+        let abcd_fields = create_abcd_fields_from_json(&json!([
+            {
+                "name": "UNIT_TEXT_LIST",
+                "numeric": false,
+                "vatMandatory": false,
+                "gfbioMandatory": true,
+                "globalField": false,
+                "unit": "",
+                "multiplicity": "all"
+            },
+            {
+                "name": "UNIT_NUM_LIST",
+                "numeric": true,
+                "vatMandatory": false,
+                "gfbioMandatory": true,
+                "globalField": false,
+                "unit": "",
+                "multiplicity": "all"
+            },
+            {
+                "name": "UNIT_JOINED",
+                "numeric": true,
+                "vatMandatory": false,
+                "gfbioMandatory": true,
+                "globalField": false,
+                "unit": "",
+                "multiplicity": "join"
+            },
+        ]));
+
+        let mut database_sink = DatabaseSink::new(&database_settings, &abcd_fields).unwrap();
+
+        database_sink
+            .insert_dataset(
+                &AbcdResult {
+                    dataset_id: "TEST_ID".to_string(),
+                    dataset_path: "TEST_PATH".to_string(),
+                    landing_page: "TEST_LANDING_PAGE".to_string(),
+                    provider_name: "TEST_PROVIDER".to_string(),
+                    abcd_version: AbcdVersion::Version206,
+                    source_metadata: Default::default(),
+                    dataset: Default::default(),
+                },
+                ok_units(vec![{
+                    let mut values = HashMap::new();
+                    values.insert(
+                        "UNIT_TEXT_LIST".into(),
+                        VatType::TextualList(vec!["FOO".into(), "B\"A,R".into()]),
+                    );
+                    values.insert("UNIT_NUM_LIST".into(), VatType::NumericList(vec![1.0, 2.5]));
+                    values.insert("UNIT_JOINED".into(), "1; 2".into());
+                    values
+                }]),
+            )
+            .unwrap();
+
+        let unit_result = retrieve_rows(&mut database_sink, &database_settings.temp_unit_table);
+        let unit = unit_result.first().unwrap();
+
+        assert_eq!(
+            vec!["FOO".to_string(), "B\"A,R".to_string()],
+            unit.get::<_, Vec<String>>(Field::new("UNIT_TEXT_LIST").hash.as_str())
+        );
+        assert_eq!(
+            vec![1.0, 2.5],
+            unit.get::<_, Vec<f64>>(Field::new("UNIT_NUM_LIST").hash.as_str())
+        );
+        assert_eq!(
+            "1; 2",
+            unit.get::<_, &str>(Field::new("UNIT_JOINED").hash.as_str())
+        );
+    }
+
     #[test]
     fn second_insert_of_same_dataset_does_not_lead_to_second_entry_in_dataset_table() {
         let database_settings = retrieve_settings_from_file_and_override_schema();
@@ -1120,47 +1990,152 @@ mod tests {
         let mut database_sink = DatabaseSink::new(&database_settings, &abcd_fields).unwrap();
 
         database_sink
-            .insert_dataset(&AbcdResult {
-                dataset_id: "TEST_ID".to_string(),
-                dataset_path: "TEST_PATH".to_string(),
-                landing_page: "TEST_LANDING_PAGE".to_string(),
-                provider_name: "TEST_PROVIDER".to_string(),
-                dataset: {
-                    let mut values = HashMap::new();
-                    values.insert("DS_TEXT".into(), "FOOBAR".into());
-                    values.insert("DS_NUM".into(), 42.0.into());
-                    values
+            .insert_dataset(
+                &AbcdResult {
+                    dataset_id: "TEST_ID".to_string(),
+                    dataset_path: "TEST_PATH".to_string(),
+                    landing_page: "TEST_LANDING_PAGE".to_string(),
+                    provider_name: "TEST_PROVIDER".to_string(),
+                    abcd_version: AbcdVersion::Version206,
+                    source_metadata: Default::default(),
+                    dataset: {
+                        let mut values = HashMap::new();
+                        values.insert("DS_TEXT".into(), "FOOBAR".into());
+                        values.insert("DS_NUM".into(), 42.0.into());
+                        values
+                    },
                 },
-                units: vec![{
+                ok_units(vec![{
                     let mut values = HashMap::new();
                     values.insert("UNIT_TEXT".into(), "FOO".into());
                     values.insert("UNIT_NUM".into(), 13.0.into());
                     values
-                }],
-            })
+                }]),
+            )
             .unwrap();
 
         database_sink
-            .insert_dataset(&AbcdResult {
-                dataset_id: "TEST_ID".to_string(),
-                dataset_path: "TEST_PATH".to_string(),
-                landing_page: "TEST_LANDING_PAGE".to_string(),
-                provider_name: "TEST_PROVIDER".to_string(),
-                dataset: {
-                    let mut values = HashMap::new();
-                    values.insert("DS_TEXT".into(), "FOOBAR".into());
-                    values.insert("DS_NUM".into(), 42.0.into());
-                    values
+            .insert_dataset(
+                &AbcdResult {
+                    dataset_id: "TEST_ID".to_string(),
+                    dataset_path: "TEST_PATH".to_string(),
+                    landing_page: "TEST_LANDING_PAGE".to_string(),
+                    provider_name: "TEST_PROVIDER".to_string(),
+                    abcd_version: AbcdVersion::Version206,
+                    source_metadata: Default::default(),
+                    dataset: {
+                        let mut values = HashMap::new();
+                        values.insert("DS_TEXT".into(), "FOOBAR".into());
+                        values.insert("DS_NUM".into(), 42.0.into());
+                        values
+                    },
                 },
-                units: vec![{
+                ok_units(vec![{
                     let mut values = HashMap::new();
                     values.insert("UNIT_TEXT".into(), "BAR".into());
                     values.insert("UNIT_NUM".into(), 37.0.into());
                     values
-                }],
+                }]),
+            )
+            .unwrap();
+
+        assert_eq!(
+            1,
+            number_of_entries(&mut database_sink, &database_settings.temp_dataset_table)
+        );
+        assert_eq!(
+            2,
+            number_of_entries(&mut database_sink, &database_settings.temp_unit_table)
+        );
+    }
+
+    #[test]
+    fn unchanged_dataset_is_carried_over_after_migration() {
+        let mut database_settings = retrieve_settings_from_file_and_override_schema();
+        database_settings.unit_indexed_columns = vec![];
+
+        let abcd_fields = create_abcd_fields_from_json(&json!([
+            {
+                "name": "DS_TEXT",
+                "numeric": false,
+                "vatMandatory": false,
+                "gfbioMandatory": true,
+                "globalField": true,
+                "unit": ""
+            },
+            {
+                "name": "UNIT_NUM",
+                "numeric": true,
+                "vatMandatory": false,
+                "gfbioMandatory": true,
+                "globalField": false,
+                "unit": ""
+            },
+        ]));
+
+        let mut database_sink = DatabaseSink::new(&database_settings, &abcd_fields).unwrap();
+
+        // no previous crawl
+        assert!(database_sink.previous_dataset_states().unwrap().is_empty());
+
+        let mut dataset_data = ValueMap::new();
+        dataset_data.insert("DS_TEXT".into(), "FOOBAR".into());
+        let units = vec![1.0, 2.0]
+            .into_iter()
+            .map(|value| {
+                let mut unit = ValueMap::new();
+                unit.insert("UNIT_NUM".into(), VatType::Numeric(value));
+                unit
             })
+            .collect();
+
+        database_sink
+            .insert_dataset(
+                &AbcdResult {
+                    dataset_id: "TEST_ID".to_string(),
+                    dataset_path: "TEST_PATH".to_string(),
+                    landing_page: "TEST_LANDING_PAGE".to_string(),
+                    provider_name: "TEST_PROVIDER".to_string(),
+                    abcd_version: AbcdVersion::Version206,
+                    source_metadata: Default::default(),
+                    dataset: dataset_data,
+                },
+                ok_units(units),
+            )
             .unwrap();
 
+        let state = DatasetState {
+            dataset_id: "TEST_ID".into(),
+            etag: Some("\"ETAG\"".into()),
+            last_modified: None,
+            content_hash: "HASH".into(),
+            date_modified: Some("2020-01-01".into()),
+        };
+        database_sink.insert_dataset_state(&state).unwrap();
+
+        database_sink.migrate_schema().unwrap();
+
+        // start the next crawl on the same connection
+        database_sink.surrogate_key = Default::default();
+        database_sink
+            .initialize_temporary_schema(&abcd_fields)
+            .unwrap();
+
+        let previous_states = database_sink.previous_dataset_states().unwrap();
+        assert_eq!(previous_states.len(), 1);
+        assert_eq!(previous_states.get("TEST_ID"), Some(&state));
+
+        let source_metadata = [("dataset_title".to_string(), "NEW_TITLE".to_string())].into();
+        assert_eq!(
+            database_sink
+                .carry_over_dataset("TEST_ID", &source_metadata)
+                .unwrap(),
+            2
+        );
+        assert!(database_sink
+            .carry_over_dataset("OTHER_ID", &source_metadata)
+            .is_err());
+
         assert_eq!(
             1,
             number_of_entries(&mut database_sink, &database_settings.temp_dataset_table)
@@ -1169,27 +2144,379 @@ mod tests {
             2,
             number_of_entries(&mut database_sink, &database_settings.temp_unit_table)
         );
+
+        let datasets = retrieve_rows(&mut database_sink, &database_settings.temp_dataset_table);
+        assert_eq!(
+            "FOOBAR",
+            datasets[0].get::<_, &str>(Field::new("DS_TEXT").hash.as_str())
+        );
+        assert_eq!(
+            "TEST_PROVIDER",
+            datasets[0].get::<_, &str>(database_settings.dataset_provider_column.as_str())
+        );
+        assert_eq!(
+            Some("NEW_TITLE"),
+            datasets[0].get::<_, Option<&str>>("dataset_title")
+        );
     }
 
     #[test]
-    fn correct_tables_after_schema_migration() {
+    fn dataset_missing_in_previous_tables_can_be_inserted() {
+        let database_settings = retrieve_settings_from_file_and_override_schema();
+        let abcd_fields = create_abcd_fields_from_json(&json!([
+            {
+                "name": "UNIT_NUM",
+                "numeric": true,
+                "vatMandatory": false,
+                "gfbioMandatory": true,
+                "globalField": false,
+                "unit": ""
+            },
+        ]));
+
+        let mut database_sink = DatabaseSink::new(&database_settings, &abcd_fields).unwrap();
+
+        let dataset = |dataset_id: &str| AbcdResult {
+            dataset_id: dataset_id.to_string(),
+            dataset_path: "TEST_PATH".to_string(),
+            landing_page: "TEST_LANDING_PAGE".to_string(),
+            provider_name: "TEST_PROVIDER".to_string(),
+            abcd_version: AbcdVersion::Version206,
+            source_metadata: Default::default(),
+            dataset: Default::default(),
+        };
+        let unit = || {
+            let mut unit = ValueMap::new();
+            unit.insert("UNIT_NUM".into(), VatType::Numeric(1.0));
+            unit
+        };
+
+        database_sink
+            .insert_dataset(&dataset("TEST_ID"), ok_units(vec![unit()]))
+            .unwrap();
+        database_sink.migrate_schema().unwrap();
+
+        // the next crawl finds a state, but no dataset in the previous tables
+        database_sink.surrogate_key = Default::default();
+        database_sink
+            .initialize_temporary_schema(&abcd_fields)
+            .unwrap();
+
+        assert!(database_sink
+            .carry_over_dataset("OTHER_ID", &Default::default())
+            .is_err());
+
+        // the released surrogate key lets the parsed archive insert the dataset again
+        assert_eq!(
+            database_sink
+                .insert_dataset(&dataset("OTHER_ID"), ok_units(vec![unit()]))
+                .unwrap(),
+            1
+        );
+
+        let datasets = retrieve_rows(&mut database_sink, &database_settings.temp_dataset_table);
+        assert_eq!(datasets.len(), 1);
+        assert_eq!(
+            "OTHER_ID",
+            datasets[0].get::<_, &str>(database_settings.dataset_id_column.as_str())
+        );
+        assert_eq!(
+            1,
+            number_of_entries(&mut database_sink, &database_settings.temp_unit_table)
+        );
+    }
+
+    #[test]
+    fn migration_check_compares_against_previous_tables() {
         let mut database_settings = retrieve_settings_from_file_and_override_schema();
         database_settings.unit_indexed_columns = vec![];
 
         let abcd_fields = create_abcd_fields_from_json(&json!([]));
+        let migration_settings = MigrationSettings {
+            max_dataset_decrease: 0.2,
+            max_unit_decrease: 0.2,
+        };
 
         let mut database_sink = DatabaseSink::new(&database_settings, &abcd_fields).unwrap();
 
+        // an empty crawl is never migrated
+        assert!(database_sink.check_migration(&migration_settings).is_err());
+
+        for dataset_id in ["TEST_ID_1", "TEST_ID_2"] {
+            database_sink
+                .insert_dataset(
+                    &AbcdResult {
+                        dataset_id: dataset_id.to_string(),
+                        dataset_path: "TEST_PATH".to_string(),
+                        landing_page: "TEST_LANDING_PAGE".to_string(),
+                        provider_name: "TEST_PROVIDER".to_string(),
+                        abcd_version: AbcdVersion::Version206,
+                        source_metadata: Default::default(),
+                        dataset: Default::default(),
+                    },
+                    ok_units(vec![]),
+                )
+                .unwrap();
+        }
+
+        // first crawl
+        database_sink.check_migration(&migration_settings).unwrap();
+        database_sink.migrate_schema().unwrap();
+
+        // the next crawl lost one of two datasets
+        database_sink.surrogate_key = Default::default();
         database_sink
-            .insert_dataset(&AbcdResult {
-                dataset_id: "TEST_ID".to_string(),
-                dataset_path: "TEST_PATH".to_string(),
-                landing_page: "TEST_LANDING_PAGE".to_string(),
-                provider_name: "TEST_PROVIDER".to_string(),
-                dataset: Default::default(),
-                units: vec![],
+            .initialize_temporary_schema(&abcd_fields)
+            .unwrap();
+        database_sink
+            .insert_dataset(
+                &AbcdResult {
+                    dataset_id: "TEST_ID_1".to_string(),
+                    dataset_path: "TEST_PATH".to_string(),
+                    landing_page: "TEST_LANDING_PAGE".to_string(),
+                    provider_name: "TEST_PROVIDER".to_string(),
+                    abcd_version: AbcdVersion::Version206,
+                    source_metadata: Default::default(),
+                    dataset: Default::default(),
+                },
+                ok_units(vec![]),
+            )
+            .unwrap();
+
+        let error = database_sink
+            .check_migration(&migration_settings)
+            .unwrap_err();
+        assert!(matches!(
+            error.downcast_ref::<MigrationCheckError>(),
+            Some(MigrationCheckError::DatasetDecrease {
+                previous: 2,
+                current: 1
+            })
+        ));
+
+        database_sink
+            .check_migration(&MigrationSettings {
+                max_dataset_decrease: 0.5,
+                max_unit_decrease: 0.2,
             })
             .unwrap();
+    }
+
+    #[test]
+    fn previous_tables_are_kept_as_generations_and_can_be_restored() {
+        let mut database_settings = retrieve_settings_from_file_and_override_schema();
+        database_settings.unit_indexed_columns = vec![];
+        database_settings.schema_generations = 2;
+
+        let mut fewer_generations_settings = retrieve_settings_from_file_and_override_schema();
+        fewer_generations_settings.unit_indexed_columns = vec![];
+        fewer_generations_settings.schema_generations = 1;
+
+        let abcd_fields = create_abcd_fields_from_json(&json!([]));
+
+        let mut database_sink = DatabaseSink::new(&database_settings, &abcd_fields).unwrap();
+
+        // no generations before the first migration
+        assert!(database_sink.rollback(None).is_err());
+
+        for crawl in 1..=4 {
+            if crawl > 1 {
+                database_sink.surrogate_key = Default::default();
+                database_sink
+                    .initialize_temporary_schema(&abcd_fields)
+                    .unwrap();
+            }
+
+            database_sink
+                .insert_dataset(
+                    &AbcdResult {
+                        dataset_id: format!("CRAWL_{}", crawl),
+                        dataset_path: "TEST_PATH".to_string(),
+                        landing_page: "TEST_LANDING_PAGE".to_string(),
+                        provider_name: "TEST_PROVIDER".to_string(),
+                        abcd_version: AbcdVersion::Version206,
+                        source_metadata: Default::default(),
+                        dataset: Default::default(),
+                    },
+                    ok_units(vec![]),
+                )
+                .unwrap();
+
+            database_sink.migrate_schema().unwrap();
+        }
+
+        let generations = database_sink.schema_generations().unwrap();
+        assert_eq!(generations.len(), 2);
+        assert!(generations[0] > generations[1]);
+
+        let dataset_ids = |database_sink: &mut DatabaseSink| -> Vec<String> {
+            retrieve_rows(database_sink, &database_settings.dataset_table)
+                .iter()
+                .map(|row| row.get(database_settings.dataset_id_column.as_str()))
+                .collect()
+        };
+        assert_eq!(dataset_ids(&mut database_sink), vec!["CRAWL_4".to_string()]);
+
+        assert!(database_sink.rollback(Some("12345")).is_err());
+
+        // restore the second crawl
+        assert_eq!(
+            database_sink.rollback(Some(&generations[1])).unwrap(),
+            generations[1]
+        );
+        assert_eq!(dataset_ids(&mut database_sink), vec!["CRAWL_2".to_string()]);
+        assert_eq!(
+            1,
+            number_of_entries(&mut database_sink, &database_settings.listing_view)
+        );
+
+        // the replaced tables became the newest generation
+        let generations_after_rollback = database_sink.schema_generations().unwrap();
+        assert_eq!(generations_after_rollback.len(), 2);
+        assert_eq!(generations_after_rollback[1], generations[0]);
+
+        assert_eq!(
+            database_sink.rollback(None).unwrap(),
+            generations_after_rollback[0]
+        );
+        assert_eq!(dataset_ids(&mut database_sink), vec!["CRAWL_4".to_string()]);
+
+        // a rollback retains fewer generations if `schema_generations` was lowered
+        database_sink.database_settings = &fewer_generations_settings;
+
+        let generations_before_rollback = database_sink.schema_generations().unwrap();
+        assert_eq!(generations_before_rollback.len(), 2);
+
+        database_sink.rollback(None).unwrap();
+        assert_eq!(dataset_ids(&mut database_sink), vec!["CRAWL_2".to_string()]);
+
+        let generations_after_pruning = database_sink.schema_generations().unwrap();
+        assert_eq!(generations_after_pruning.len(), 1);
+        assert!(generations_after_pruning[0] > generations_before_rollback[0]);
+    }
+
+    #[test]
+    fn rollback_creates_listing_view_for_restored_columns() {
+        let mut database_settings = retrieve_settings_from_file_and_override_schema();
+        database_settings.unit_indexed_columns = vec![];
+
+        let abcd_fields_without_title = create_abcd_fields_from_json(&json!([]));
+        let abcd_fields_with_title = create_abcd_fields_from_json(&json!([
+            {
+                "name": "/DataSets/DataSet/Metadata/Description/Representation/Title",
+                "numeric": false,
+                "vatMandatory": false,
+                "gfbioMandatory": true,
+                "globalField": true,
+                "unit": ""
+            },
+        ]));
+
+        let mut database_sink =
+            DatabaseSink::new(&database_settings, &abcd_fields_without_title).unwrap();
+
+        for (crawl, abcd_fields) in [&abcd_fields_without_title, &abcd_fields_with_title]
+            .into_iter()
+            .enumerate()
+        {
+            if crawl > 0 {
+                // the field specification changed since the previous crawl
+                let (dataset_fields, unit_fields) =
+                    DatabaseSink::create_lists_of_dataset_and_unit_fields(abcd_fields);
+                database_sink.abcd_fields = abcd_fields;
+                database_sink.dataset_fields = dataset_fields;
+                database_sink.unit_fields = unit_fields;
+                database_sink.surrogate_key = Default::default();
+                database_sink
+                    .initialize_temporary_schema(abcd_fields)
+                    .unwrap();
+            }
+
+            database_sink
+                .insert_dataset(
+                    &AbcdResult {
+                        dataset_id: format!("CRAWL_{}", crawl),
+                        dataset_path: "TEST_PATH".to_string(),
+                        landing_page: "TEST_LANDING_PAGE".to_string(),
+                        provider_name: "TEST_PROVIDER".to_string(),
+                        abcd_version: AbcdVersion::Version206,
+                        source_metadata: Default::default(),
+                        dataset: Default::default(),
+                    },
+                    ok_units(vec![]),
+                )
+                .unwrap();
+
+            database_sink.migrate_schema().unwrap();
+        }
+
+        // the restored tables have no title column
+        database_sink.rollback(None).unwrap();
+
+        assert_eq!(
+            1,
+            number_of_entries(&mut database_sink, &database_settings.listing_view)
+        );
+    }
+
+    #[test]
+    fn migration_can_be_repeated_after_index_creation() {
+        let mut database_settings = retrieve_settings_from_file_and_override_schema();
+        database_settings.unit_indexed_columns = vec![];
+
+        let abcd_fields = create_abcd_fields_from_json(&json!([]));
+
+        let mut database_sink = DatabaseSink::new(&database_settings, &abcd_fields).unwrap();
+
+        database_sink
+            .insert_dataset(
+                &AbcdResult {
+                    dataset_id: "TEST_ID".to_string(),
+                    dataset_path: "TEST_PATH".to_string(),
+                    landing_page: "TEST_LANDING_PAGE".to_string(),
+                    provider_name: "TEST_PROVIDER".to_string(),
+                    abcd_version: AbcdVersion::Version206,
+                    source_metadata: Default::default(),
+                    dataset: Default::default(),
+                },
+                ok_units(vec![]),
+            )
+            .unwrap();
+
+        // a previous migration failed after creating the indexes
+        database_sink.create_indexes_and_statistics().unwrap();
+
+        database_sink.migrate_schema().unwrap();
+
+        assert_eq!(
+            1,
+            number_of_entries(&mut database_sink, &database_settings.dataset_table)
+        );
+    }
+
+    #[test]
+    fn correct_tables_after_schema_migration() {
+        let mut database_settings = retrieve_settings_from_file_and_override_schema();
+        database_settings.unit_indexed_columns = vec![];
+
+        let abcd_fields = create_abcd_fields_from_json(&json!([]));
+
+        let mut database_sink = DatabaseSink::new(&database_settings, &abcd_fields).unwrap();
+
+        database_sink
+            .insert_dataset(
+                &AbcdResult {
+                    dataset_id: "TEST_ID".to_string(),
+                    dataset_path: "TEST_PATH".to_string(),
+                    landing_page: "TEST_LANDING_PAGE".to_string(),
+                    provider_name: "TEST_PROVIDER".to_string(),
+                    abcd_version: AbcdVersion::Version206,
+                    source_metadata: Default::default(),
+                    dataset: Default::default(),
+                },
+                ok_units(vec![]),
+            )
+            .unwrap();
 
         database_sink.migrate_schema().unwrap();
 
@@ -1201,6 +2528,7 @@ mod tests {
                 database_settings.dataset_table.clone(),
                 database_settings.unit_table.clone(),
                 format!("{}_translation", database_settings.dataset_table),
+                format!("{}_state", database_settings.dataset_table),
                 database_settings.listing_view.clone(),
             ])
         );
@@ -1241,25 +2569,29 @@ mod tests {
         let mut database_sink = DatabaseSink::new(&database_settings, &abcd_fields).unwrap();
 
         database_sink
-            .insert_dataset(&AbcdResult {
-                dataset_id: "TEST_ID".to_string(),
-                dataset_path: "TEST_PATH".to_string(),
-                landing_page: "TEST_LANDING_PAGE".to_string(),
-                provider_name: "TEST_PROVIDER".to_string(),
-                dataset: {
+            .insert_dataset(
+                &AbcdResult {
+                    dataset_id: "TEST_ID".to_string(),
+                    dataset_path: "TEST_PATH".to_string(),
+                    landing_page: "TEST_LANDING_PAGE".to_string(),
+                    provider_name: "TEST_PROVIDER".to_string(),
+                    abcd_version: AbcdVersion::Version206,
+                    source_metadata: Default::default(),
+                    dataset: {
                         let mut values = HashMap::new();
                         values.insert("/DataSets/DataSet/Metadata/Description/Representation/Title".into(), "FOOBAR".into());
                         values
+                    },
                 },
-                units: vec![
+                ok_units(vec![
                     {
                         let mut values = HashMap::new();
                         values.insert("/DataSets/DataSet/Units/Unit/Gathering/SiteCoordinateSets/SiteCoordinates/CoordinatesLatLong/LatitudeDecimal".into(), 10.0.into());
                         values.insert("/DataSets/DataSet/Units/Unit/Gathering/SiteCoordinateSets/SiteCoordinates/CoordinatesLatLong/LongitudeDecimal".into(), 20.0.into());
                         values
                     },
-                ],
-            })
+                ]),
+            )
             .unwrap();
 
         database_sink.migrate_schema().unwrap();
@@ -1286,6 +2618,10 @@ mod tests {
         assert!(row.get::<_, bool>("isGeoReferenced"));
     }
 
+    fn ok_units(units: Vec<ValueMap>) -> impl Iterator<Item = Result<ValueMap, Error>> {
+        units.into_iter().map(Ok)
+    }
+
     fn retrieve_rows(database_sink: &mut DatabaseSink, table_name: &str) -> Vec<Row> {
         let statement = database_sink
             .connection
@@ -0,0 +1,44 @@
+/// This struct reflects the state of a dataset archive at the time of its last ingestion.
+/// It allows skipping archives that did not change since the previous crawl.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct DatasetState {
+    pub dataset_id: String,
+    pub etag: Option<String>,
+    pub last_modified: Option<String>,
+    pub content_hash: String,
+    pub date_modified: Option<String>,
+}
+
+impl DatasetState {
+    /// Check whether the archive's content equals the content of a previous state.
+    pub fn is_unchanged_since(&self, previous: &DatasetState) -> bool {
+        self.dataset_id == previous.dataset_id && self.content_hash == previous.content_hash
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn unchanged_content() {
+        let previous = DatasetState {
+            dataset_id: "ID".into(),
+            etag: Some("\"1\"".into()),
+            content_hash: "HASH".into(),
+            ..Default::default()
+        };
+
+        let current = DatasetState {
+            etag: Some("\"2\"".into()),
+            ..previous.clone()
+        };
+        assert!(current.is_unchanged_since(&previous));
+
+        let current = DatasetState {
+            content_hash: "OTHER_HASH".into(),
+            ..previous.clone()
+        };
+        assert!(!current.is_unchanged_since(&previous));
+    }
+}
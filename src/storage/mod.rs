@@ -1,7 +1,14 @@
 mod database_sink;
+mod dataset_state;
 mod field;
 mod surrogate_key;
+mod table_counts;
+mod table_names;
 
 pub use self::database_sink::DatabaseSink;
-pub(self) use self::field::Field;
-pub(self) use self::surrogate_key::{SurrogateKey, SurrogateKeyType};
+pub use self::dataset_state::DatasetState;
+use self::field::Field;
+use self::surrogate_key::{SurrogateKey, SurrogateKeyType};
+pub use self::table_counts::MigrationCheckError;
+use self::table_counts::TableCounts;
+use self::table_names::TableNames;
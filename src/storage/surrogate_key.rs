@@ -32,6 +32,11 @@ impl SurrogateKey {
             }
         }
     }
+
+    /// Forget the key of an id, e.g., if its dataset could not be inserted.
+    pub fn release(&mut self, id: &str) {
+        self.id_to_key.remove(id);
+    }
 }
 
 impl Default for SurrogateKey {
@@ -64,4 +69,13 @@ mod tests {
         assert_eq!(SurrogateKeyType::Existing(1), surrogate_key.for_id("foo"));
         assert_eq!(SurrogateKeyType::New(2), surrogate_key.for_id("bar"));
     }
+
+    #[test]
+    fn released_key() {
+        let mut surrogate_key = SurrogateKey::new();
+
+        assert_eq!(SurrogateKeyType::New(1), surrogate_key.for_id("foo"));
+        surrogate_key.release("foo");
+        assert_eq!(SurrogateKeyType::New(2), surrogate_key.for_id("foo"));
+    }
 }
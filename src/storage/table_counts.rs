@@ -0,0 +1,145 @@
+use failure::Fail;
+
+use crate::settings::MigrationSettings;
+
+/// This struct contains the number of datasets and units of a crawl.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct TableCounts {
+    pub datasets: u64,
+    pub units: u64,
+}
+
+impl TableCounts {
+    /// Check that the counts did not drop by more than the configured fractions of the `previous` counts.
+    pub fn check_against(
+        &self,
+        previous: Option<&TableCounts>,
+        migration_settings: &MigrationSettings,
+    ) -> Result<(), MigrationCheckError> {
+        if self.datasets == 0 {
+            return Err(MigrationCheckError::NoDatasets);
+        }
+
+        let previous = match previous {
+            Some(previous) => previous,
+            None => return Ok(()), // first crawl
+        };
+
+        if Self::decrease(previous.datasets, self.datasets)
+            > migration_settings.max_dataset_decrease
+        {
+            return Err(MigrationCheckError::DatasetDecrease {
+                previous: previous.datasets,
+                current: self.datasets,
+            });
+        }
+
+        if Self::decrease(previous.units, self.units) > migration_settings.max_unit_decrease {
+            return Err(MigrationCheckError::UnitDecrease {
+                previous: previous.units,
+                current: self.units,
+            });
+        }
+
+        Ok(())
+    }
+
+    /// The decrease as a fraction of the previous value.
+    fn decrease(previous: u64, current: u64) -> f64 {
+        if previous == 0 || current >= previous {
+            return 0.;
+        }
+
+        (previous - current) as f64 / previous as f64
+    }
+}
+
+#[derive(Debug, Fail)]
+pub enum MigrationCheckError {
+    /// This error occurs when the crawl did not store any datasets.
+    #[fail(display = "The crawl contains no datasets")]
+    NoDatasets,
+    /// This error occurs when the crawl contains too few datasets compared to the persistent tables.
+    #[fail(
+        display = "The number of datasets dropped from {} to {}",
+        previous, current
+    )]
+    DatasetDecrease { previous: u64, current: u64 },
+    /// This error occurs when the crawl contains too few units compared to the persistent tables.
+    #[fail(
+        display = "The number of units dropped from {} to {}",
+        previous, current
+    )]
+    UnitDecrease { previous: u64, current: u64 },
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const MIGRATION_SETTINGS: MigrationSettings = MigrationSettings {
+        max_dataset_decrease: 0.2,
+        max_unit_decrease: 0.5,
+    };
+
+    #[test]
+    fn accept_first_crawl() {
+        let counts = TableCounts {
+            datasets: 1,
+            units: 0,
+        };
+
+        assert!(counts.check_against(None, &MIGRATION_SETTINGS).is_ok());
+    }
+
+    #[test]
+    fn reject_empty_crawl() {
+        let counts = TableCounts {
+            datasets: 0,
+            units: 0,
+        };
+
+        assert!(matches!(
+            counts.check_against(None, &MIGRATION_SETTINGS),
+            Err(MigrationCheckError::NoDatasets)
+        ));
+    }
+
+    #[test]
+    fn check_decrease() {
+        let previous = TableCounts {
+            datasets: 100,
+            units: 1000,
+        };
+
+        for (datasets, units, ok) in [
+            (100, 1000, true),
+            (120, 2000, true),
+            (80, 500, true),
+            (79, 1000, false),
+            (100, 499, false),
+        ] {
+            let counts = TableCounts { datasets, units };
+            assert_eq!(
+                counts
+                    .check_against(Some(&previous), &MIGRATION_SETTINGS)
+                    .is_ok(),
+                ok,
+                "{:?}",
+                counts
+            );
+        }
+
+        assert!(matches!(
+            TableCounts {
+                datasets: 3,
+                units: 1000
+            }
+            .check_against(Some(&previous), &MIGRATION_SETTINGS),
+            Err(MigrationCheckError::DatasetDecrease {
+                previous: 100,
+                current: 3
+            })
+        ));
+    }
+}
@@ -0,0 +1,55 @@
+use crate::settings::DatabaseSettings;
+
+/// This struct contains the names of a set of crawl tables.
+/// The translation and state tables, constraints and indexes are named after the dataset and unit tables.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct TableNames {
+    pub dataset_table: String,
+    pub unit_table: String,
+}
+
+impl TableNames {
+    const GENERATION_INFIX: &'static str = "_gen";
+
+    /// The tables that are queried by the VAT system.
+    pub fn persistent(database_settings: &DatabaseSettings) -> Self {
+        Self {
+            dataset_table: database_settings.dataset_table.clone(),
+            unit_table: database_settings.unit_table.clone(),
+        }
+    }
+
+    /// The tables that are filled during a crawl.
+    pub fn temporary(database_settings: &DatabaseSettings) -> Self {
+        Self {
+            dataset_table: database_settings.temp_dataset_table.clone(),
+            unit_table: database_settings.temp_unit_table.clone(),
+        }
+    }
+
+    /// The tables of a previous crawl that were replaced by a migration.
+    pub fn generation(database_settings: &DatabaseSettings, generation: &str) -> Self {
+        Self {
+            dataset_table: format!(
+                "{}{}",
+                Self::generation_prefix(database_settings),
+                generation
+            ),
+            unit_table: format!(
+                "{}{}{}",
+                database_settings.unit_table,
+                Self::GENERATION_INFIX,
+                generation
+            ),
+        }
+    }
+
+    /// The dataset tables of generations start with this prefix, followed by the generation.
+    pub fn generation_prefix(database_settings: &DatabaseSettings) -> String {
+        format!(
+            "{}{}",
+            database_settings.dataset_table,
+            Self::GENERATION_INFIX
+        )
+    }
+}
@@ -0,0 +1,79 @@
+use std::collections::{BTreeMap, HashMap};
+
+use failure::Error;
+
+use crate::abcd::{AbcdResult, ValueMap};
+use crate::sink::Sink;
+use crate::storage::DatasetState;
+
+/// A sink that keeps the datasets of a run in memory.
+/// Datasets of the previous run are carried over by their `previous_states` and `previous_units`.
+#[derive(Debug, Default)]
+pub struct MemorySink {
+    pub previous_states: HashMap<String, DatasetState>,
+    pub previous_units: HashMap<String, usize>,
+    pub datasets: Vec<(AbcdResult, Vec<ValueMap>)>,
+    pub carried_over: Vec<String>,
+    pub states: Vec<DatasetState>,
+    pub begun: bool,
+    pub finished: bool,
+    pub aborted: bool,
+}
+
+impl Sink for MemorySink {
+    fn begin(&mut self) -> Result<(), Error> {
+        self.begun = true;
+
+        Ok(())
+    }
+
+    fn insert_dataset(
+        &mut self,
+        dataset: &AbcdResult,
+        units: &mut dyn Iterator<Item = Result<ValueMap, Error>>,
+    ) -> Result<usize, Error> {
+        let units = units.collect::<Result<Vec<ValueMap>, Error>>()?;
+        let number_of_units = units.len();
+
+        self.datasets.push((dataset.clone(), units));
+
+        Ok(number_of_units)
+    }
+
+    fn previous_dataset_states(&mut self) -> Result<HashMap<String, DatasetState>, Error> {
+        Ok(self.previous_states.clone())
+    }
+
+    fn carry_over_dataset(
+        &mut self,
+        dataset_id: &str,
+        _source_metadata: &BTreeMap<String, String>,
+    ) -> Result<usize, Error> {
+        let number_of_units = *self
+            .previous_units
+            .get(dataset_id)
+            .ok_or_else(|| failure::err_msg(format!("Unknown dataset `{}`", dataset_id)))?;
+
+        self.carried_over.push(dataset_id.to_string());
+
+        Ok(number_of_units)
+    }
+
+    fn insert_dataset_state(&mut self, state: &DatasetState) -> Result<(), Error> {
+        self.states.push(state.clone());
+
+        Ok(())
+    }
+
+    fn finish(&mut self) -> Result<(), Error> {
+        self.finished = true;
+
+        Ok(())
+    }
+
+    fn abort(&mut self) -> Result<(), Error> {
+        self.aborted = true;
+
+        Ok(())
+    }
+}
@@ -1,9 +1,19 @@
+mod memory_sink;
 mod webserver;
 
 use std::io::Write;
 
+use serde_json::{json, Value};
 use tempfile::TempPath;
 
+use crate::abcd::{AbcdFields, AbcdResult, AbcdVersion};
+use crate::http_client::HttpClient;
+use crate::settings::{
+    DwcaSettings, ExportSettings, GeoPackageSettings, HttpSettings, ParquetSettings,
+};
+use crate::vat_type::VatType;
+
+pub use self::memory_sink::MemorySink;
 pub use self::webserver::MockWebserver;
 
 pub fn create_temp_file(content: &str) -> TempPath {
@@ -27,3 +37,98 @@ pub fn create_empty_temp_file() -> TempPath {
         .expect("Unable to create test file.")
         .into_temp_path()
 }
+
+/// Create a ZIP archive from pairs of file names and contents.
+pub fn create_zip_file(files: &[(&str, &str)]) -> TempPath {
+    let mut file = tempfile::NamedTempFile::new().expect("Unable to create file to test.");
+
+    {
+        let mut zip_writer = zip::ZipWriter::new(&mut file);
+
+        let options =
+            zip::write::FileOptions::default().compression_method(zip::CompressionMethod::Stored);
+        for (name, content) in files {
+            zip_writer
+                .start_file(*name, options)
+                .expect("Unable to start file in zip archive.");
+            zip_writer
+                .write_all(content.as_bytes())
+                .expect("Unable to write file in zip archive.");
+        }
+
+        zip_writer.finish().expect("Unable to finish zip archive.");
+    }
+
+    file.into_temp_path()
+}
+
+/// Settings for an HTTP client that retries quickly.
+pub fn create_http_settings() -> HttpSettings {
+    HttpSettings {
+        connect_timeout_seconds: 5,
+        read_timeout_seconds: 10,
+        timeout_seconds: None,
+        max_retries: 2,
+        initial_backoff_milliseconds: 1,
+        max_backoff_milliseconds: 10,
+        max_connections_per_host: 4,
+    }
+}
+
+pub fn create_http_client() -> HttpClient {
+    HttpClient::new(&create_http_settings()).expect("Unable to create HTTP client.")
+}
+
+pub const LONGITUDE: &str = "/DataSets/DataSet/Units/Unit/LongitudeDecimal";
+pub const LATITUDE: &str = "/DataSets/DataSet/Units/Unit/LatitudeDecimal";
+pub const UNIT_ID: &str = "/DataSets/DataSet/Units/Unit/UnitID";
+pub const TITLE: &str = "/DataSets/DataSet/Metadata/Description/Representation/Title";
+
+/// A dataset with a title.
+pub fn create_dataset() -> AbcdResult {
+    AbcdResult::new(
+        "DATASET_ID".into(),
+        "DATASET_PATH".into(),
+        "LANDING_PAGE".into(),
+        "PROVIDER".into(),
+        AbcdVersion::Version206,
+        [(TITLE.to_string(), VatType::from("TITLE"))]
+            .into_iter()
+            .collect(),
+    )
+}
+
+/// The specification of a single ABCD field.
+pub fn abcd_field(name: &str, numeric: bool, global_field: bool) -> Value {
+    json!({
+        "name": name,
+        "numeric": numeric,
+        "vatMandatory": false,
+        "gfbioMandatory": false,
+        "globalField": global_field,
+        "unit": "",
+    })
+}
+
+pub fn create_abcd_fields(fields: &[Value]) -> AbcdFields {
+    let fields_file = create_temp_file(&Value::from(fields).to_string());
+
+    AbcdFields::from_path(&fields_file).expect("Unable to create ABCD Fields Spec")
+}
+
+/// Export settings with unit coordinates, no indexes and a row group per unit.
+pub fn create_export_settings() -> ExportSettings {
+    ExportSettings {
+        longitude_field: LONGITUDE.into(),
+        latitude_field: LATITUDE.into(),
+        geopackage: GeoPackageSettings {
+            dataset_table: "datasets".into(),
+            unit_table: "units".into(),
+            indexes: vec![],
+        },
+        parquet: ParquetSettings { row_group_size: 1 },
+        dwca: DwcaSettings {
+            mapping_file: "dwc-mapping.json".into(),
+        },
+    }
+}
@@ -11,6 +11,12 @@ impl MockWebserver {
         }
     }
 
+    pub fn from_bytes(path: &str, method: &str, bytes: &[u8]) -> Self {
+        Self {
+            _mock: mock(method, path).with_body(bytes).create(),
+        }
+    }
+
     pub fn from_json(path: &str, method: &str, json_string: &str) -> Self {
         Self {
             _mock: mock(method, path)
@@ -35,6 +41,20 @@ impl MockWebserver {
         }
     }
 
+    pub fn from_text_with_partial_json_condition(
+        path: &str,
+        method: &str,
+        json_condition: &str,
+        text_result: &str,
+    ) -> Self {
+        Self {
+            _mock: mock(method, path)
+                .match_body(Matcher::PartialJsonString(json_condition.to_string()))
+                .with_body(text_result)
+                .create(),
+        }
+    }
+
     pub fn webserver_root_url(&self) -> String {
         mockito::server_url()
     }
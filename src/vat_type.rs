@@ -6,6 +6,36 @@ use std::fmt;
 pub enum VatType {
     Textual(String),
     Numeric(f64),
+    TextualList(Vec<String>),
+    NumericList(Vec<f64>),
+}
+
+impl VatType {
+    /// Append a value to a list of the same type.
+    /// All other combinations are joined to a textual value using `separator`.
+    pub fn append(&mut self, value: VatType, separator: &str) {
+        match (self, value) {
+            (VatType::TextualList(values), VatType::Textual(value)) => values.push(value),
+            (VatType::NumericList(values), VatType::Numeric(value)) => values.push(value),
+            (this, value) => {
+                *this = VatType::Textual(format!("{}{}{}", this, separator, value));
+            }
+        }
+    }
+
+    /// Check whether the value is a list type.
+    pub fn is_list(&self) -> bool {
+        matches!(self, VatType::TextualList(_) | VatType::NumericList(_))
+    }
+
+    /// Wrap a single value into a list type.
+    pub fn into_list(self) -> Self {
+        match self {
+            VatType::Textual(value) => VatType::TextualList(vec![value]),
+            VatType::Numeric(value) => VatType::NumericList(vec![value]),
+            list => list,
+        }
+    }
 }
 
 impl From<String> for VatType {
@@ -37,6 +67,11 @@ impl fmt::Display for VatType {
         match self {
             VatType::Textual(value) => write!(f, "{}", value),
             VatType::Numeric(value) => write!(f, "{}", value),
+            VatType::TextualList(values) => write!(f, "{}", values.join("; ")),
+            VatType::NumericList(values) => {
+                let values: Vec<String> = values.iter().map(f64::to_string).collect();
+                write!(f, "{}", values.join("; "))
+            }
         }
     }
 }
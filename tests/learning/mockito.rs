@@ -11,7 +11,7 @@ fn mockito_expect_body() {
 
     let client = Client::new();
     let response = client
-        .post(&mockito::server_url())
+        .post(mockito::server_url())
         .body("FOOBAR")
         .send()
         .unwrap();
@@ -31,7 +31,7 @@ fn mockito_expect_json() {
 
     let client = Client::new();
     let response = client
-        .post(&mockito::server_url())
+        .post(mockito::server_url())
         .body(JSON_STRING)
         .send()
         .unwrap();
@@ -52,7 +52,7 @@ fn mockito_expect_json_from_map() {
 
     let client = Client::new();
     let response = client
-        .post(&mockito::server_url())
+        .post(mockito::server_url())
         .json(&map)
         .send()
         .unwrap();